@@ -9,10 +9,13 @@ extern crate env_logger;
 #[macro_use]
 extern crate libremarkable;
 use libremarkable::framebuffer::common::*;
+use libremarkable::framebuffer::dither::Dither;
+use libremarkable::framebuffer::fonts::FontHandle;
+use libremarkable::framebuffer::imaging::{ImageFit, ScaleFilter};
+use libremarkable::framebuffer::ink::StrokePoint;
 use libremarkable::framebuffer::refresh::PartialRefreshMode;
 use libremarkable::framebuffer::storage;
 use libremarkable::framebuffer::{FramebufferDraw, FramebufferIO, FramebufferRefresh};
-use libremarkable::image::GenericImage;
 use libremarkable::input::{gpio, multitouch, wacom, InputDevice};
 use libremarkable::ui_extensions::element::{
     UIConstraintRefresh, UIElement, UIElementHandle, UIElementWrapper,
@@ -98,7 +101,7 @@ lazy_static! {
     static ref G_DRAW_MODE: Atomic<DrawMode> = Atomic::new(DrawMode::Draw(2));
     static ref UNPRESS_OBSERVED: AtomicBool = AtomicBool::new(false);
     static ref WACOM_IN_RANGE: AtomicBool = AtomicBool::new(false);
-    static ref WACOM_HISTORY: Mutex<Vec<(i32, i32)>> = Mutex::new(Vec::new());
+    static ref WACOM_HISTORY: Mutex<Vec<StrokePoint>> = Mutex::new(Vec::new());
     static ref G_COUNTER: Mutex<u32> = Mutex::new(0);
     static ref LAST_REFRESHED_CANVAS_RECT: Atomic<mxcfb_rect> = Atomic::new(mxcfb_rect::invalid());
     static ref SAVED_CANVAS: Mutex<Option<storage::CompressedCanvasState>> = Mutex::new(None);
@@ -131,32 +134,36 @@ fn on_zoom_out(app: &mut appctx::ApplicationContext, _element: UIElementHandle)
     match framebuffer.dump_region(CANVAS_REGION) {
         Err(err) => println!("Failed to dump buffer: {0}", err),
         Ok(buff) => {
-            let resized = image::DynamicImage::ImageRgb8(
+            let canvas = image::DynamicImage::ImageRgb8(
                 storage::rgbimage_from_u8_slice(
                     CANVAS_REGION.width,
                     CANVAS_REGION.height,
                     buff.as_slice(),
+                    framebuffer.pixel_format(),
                 ).unwrap(),
-            ).resize(
-                (CANVAS_REGION.width as f32 / 1.25f32) as u32,
-                (CANVAS_REGION.height as f32 / 1.25f32) as u32,
-                image::imageops::Nearest,
             );
 
-            // Get a clean image the size of the canvas
-            let mut new_image =
-                image::DynamicImage::new_rgb8(CANVAS_REGION.width, CANVAS_REGION.height);
-            new_image.invert();
-
-            // Copy the resized image into the subimage
-            new_image.copy_from(&resized, CANVAS_REGION.width / 8, CANVAS_REGION.height / 8);
-
-            framebuffer.draw_image(
-                &new_image.as_rgb8().unwrap(),
+            // Clear the canvas and draw its previous contents back scaled down
+            framebuffer.fill_rect(
                 CANVAS_REGION.top as usize,
                 CANVAS_REGION.left as usize,
+                CANVAS_REGION.height as usize,
+                CANVAS_REGION.width as usize,
+                color::WHITE,
             );
-            framebuffer.partial_refresh(
+            framebuffer.draw_image_scaled(
+                &canvas,
+                mxcfb_rect {
+                    top: CANVAS_REGION.top + CANVAS_REGION.height / 8,
+                    left: CANVAS_REGION.left + CANVAS_REGION.width / 8,
+                    height: (CANVAS_REGION.height as f32 / 1.25f32) as u32,
+                    width: (CANVAS_REGION.width as f32 / 1.25f32) as u32,
+                },
+                ScaleFilter::Nearest,
+                ImageFit::Fit,
+                Dither::None,
+            );
+            if let Err(e) = framebuffer.partial_refresh(
                 &CANVAS_REGION,
                 PartialRefreshMode::Async,
                 waveform_mode::WAVEFORM_MODE_GC16_FAST,
@@ -164,7 +171,9 @@ fn on_zoom_out(app: &mut appctx::ApplicationContext, _element: UIElementHandle)
                 dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                 0,
                 false,
-            );
+            ) {
+                println!("Failed to refresh: {0}", e);
+            }
         }
     };
     end_bench!(zoom_out);
@@ -181,6 +190,7 @@ fn on_blur_canvas(app: &mut appctx::ApplicationContext, _element: UIElementHandl
                     CANVAS_REGION.width,
                     CANVAS_REGION.height,
                     buff.as_slice(),
+                    framebuffer.pixel_format(),
                 ).unwrap(),
             ).blur(0.6f32);
 
@@ -188,8 +198,9 @@ fn on_blur_canvas(app: &mut appctx::ApplicationContext, _element: UIElementHandl
                 &dynamic.as_rgb8().unwrap(),
                 CANVAS_REGION.top as usize,
                 CANVAS_REGION.left as usize,
+                Dither::None,
             );
-            framebuffer.partial_refresh(
+            if let Err(e) = framebuffer.partial_refresh(
                 &CANVAS_REGION,
                 PartialRefreshMode::Async,
                 waveform_mode::WAVEFORM_MODE_GC16_FAST,
@@ -197,7 +208,9 @@ fn on_blur_canvas(app: &mut appctx::ApplicationContext, _element: UIElementHandl
                 dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                 0,
                 false,
-            );
+            ) {
+                println!("Failed to refresh: {0}", e);
+            }
         }
     };
     end_bench!(blur_canvas);
@@ -206,28 +219,26 @@ fn on_blur_canvas(app: &mut appctx::ApplicationContext, _element: UIElementHandl
 fn on_invert_canvas(app: &mut appctx::ApplicationContext, element: UIElementHandle) {
     start_bench!(stopwatch, invert);
     let framebuffer = app.get_framebuffer_ref();
-    match framebuffer.dump_region(CANVAS_REGION) {
-        Err(err) => println!("Failed to dump buffer: {0}", err),
-        Ok(mut buff) => {
-            buff.iter_mut().for_each(|p| {
-                *p = !(*p);
-            });
-            match framebuffer.restore_region(CANVAS_REGION, &buff) {
-                Err(e) => println!("Error while restoring region: {0}", e),
-                Ok(_) => {
-                    framebuffer.partial_refresh(
-                        &CANVAS_REGION,
-                        PartialRefreshMode::Async,
-                        waveform_mode::WAVEFORM_MODE_GC16_FAST,
-                        display_temp::TEMP_USE_REMARKABLE_DRAW,
-                        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
-                        0,
-                        false,
-                    );
-                }
-            };
-        }
-    };
+    framebuffer.set_raster_op(RasterOp::Invert);
+    framebuffer.fill_rect(
+        CANVAS_REGION.top as usize,
+        CANVAS_REGION.left as usize,
+        CANVAS_REGION.height as usize,
+        CANVAS_REGION.width as usize,
+        color::BLACK,
+    );
+    framebuffer.set_raster_op(RasterOp::Copy);
+    if let Err(e) = framebuffer.partial_refresh(
+        &CANVAS_REGION,
+        PartialRefreshMode::Async,
+        waveform_mode::WAVEFORM_MODE_GC16_FAST,
+        display_temp::TEMP_USE_REMARKABLE_DRAW,
+        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        false,
+    ) {
+        println!("Failed to refresh: {0}", e);
+    }
     end_bench!(invert);
 
     // Invert the draw color as well for more natural UX
@@ -245,7 +256,7 @@ fn on_load_canvas(app: &mut appctx::ApplicationContext, _element: UIElementHandl
             match framebuffer.restore_region(CANVAS_REGION, &decompressed) {
                 Err(e) => println!("Error while restoring region: {0}", e),
                 Ok(_) => {
-                    framebuffer.partial_refresh(
+                    if let Err(e) = framebuffer.partial_refresh(
                         &CANVAS_REGION,
                         PartialRefreshMode::Async,
                         waveform_mode::WAVEFORM_MODE_GC16_FAST,
@@ -253,7 +264,9 @@ fn on_load_canvas(app: &mut appctx::ApplicationContext, _element: UIElementHandl
                         dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                         0,
                         false,
-                    );
+                    ) {
+                        println!("Failed to refresh: {0}", e);
+                    }
                 }
             };
         }
@@ -281,11 +294,12 @@ fn on_touch_rustlogo(app: &mut appctx::ApplicationContext, _element: UIElementHa
         240,
         1140,
         format!("{0}", new_press_count),
+        FontHandle::default(),
         65,
         color::BLACK,
         false,
     );
-    framebuffer.partial_refresh(
+    if let Err(e) = framebuffer.partial_refresh(
         &rect,
         PartialRefreshMode::Wait,
         waveform,
@@ -293,7 +307,9 @@ fn on_touch_rustlogo(app: &mut appctx::ApplicationContext, _element: UIElementHa
         dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
         0,
         false,
-    );
+    ) {
+        println!("Failed to refresh: {0}", e);
+    }
 }
 
 fn on_toggle_eraser(app: &mut appctx::ApplicationContext, _: UIElementHandle) {
@@ -335,8 +351,9 @@ fn draw_color_test_rgb(app: &mut appctx::ApplicationContext, _element: UIElement
         &img_rgb565.as_rgb8().unwrap(),
         CANVAS_REGION.top as usize,
         CANVAS_REGION.left as usize,
+        Dither::None,
     );
-    fb.partial_refresh(
+    if let Err(e) = fb.partial_refresh(
         &CANVAS_REGION,
         PartialRefreshMode::Wait,
         waveform_mode::WAVEFORM_MODE_GC16,
@@ -344,7 +361,9 @@ fn draw_color_test_rgb(app: &mut appctx::ApplicationContext, _element: UIElement
         dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
         0,
         false,
-    );
+    ) {
+        println!("Failed to refresh: {0}", e);
+    }
 }
 
 fn change_brush_width(app: &mut appctx::ApplicationContext, delta: isize) {
@@ -423,24 +442,18 @@ fn on_wacom_input(app: &mut appctx::ApplicationContext, input: wacom::WacomEvent
                 DrawMode::Erase(s) => (color::WHITE, s * 3),
             };
 
-            let rad = mult as f32 * (pressure as f32) / 2048.;
+            let point = StrokePoint::from_pressure(y, x, pressure, 1.0, 2.0 * mult as f32);
             if wacom_stack.len() >= 2 {
                 let framebuffer = app.get_framebuffer_ref();
                 let controlpt = wacom_stack.pop().unwrap();
                 let beginpt = wacom_stack.pop().unwrap();
-                let rect = framebuffer.draw_bezier(
-                    (beginpt.1 as f32, beginpt.0 as f32),
-                    (controlpt.1 as f32, controlpt.0 as f32),
-                    (x as f32, y as f32),
-                    rad as usize,
-                    col,
-                );
+                let rect = framebuffer.draw_ink_stroke(&[beginpt, controlpt, point], col);
 
                 if !LAST_REFRESHED_CANVAS_RECT
                     .load(Ordering::Relaxed)
                     .contains_rect(&rect)
                 {
-                    framebuffer.partial_refresh(
+                    if let Err(e) = framebuffer.partial_refresh(
                         &rect,
                         PartialRefreshMode::Async,
                         waveform_mode::WAVEFORM_MODE_DU,
@@ -448,11 +461,13 @@ fn on_wacom_input(app: &mut appctx::ApplicationContext, input: wacom::WacomEvent
                         dither_mode::EPDC_FLAG_EXP1,
                         DRAWING_QUANT_BIT,
                         false,
-                    );
+                    ) {
+                        println!("Failed to refresh: {0}", e);
+                    }
                     LAST_REFRESHED_CANVAS_RECT.store(rect, Ordering::Relaxed);
                 }
             }
-            wacom_stack.push((y as i32, x as i32));
+            wacom_stack.push(point);
         }
         wacom::WacomEvent::InstrumentChange { pen, state } => {
             match pen {
@@ -514,7 +529,7 @@ fn on_touch_handler(app: &mut appctx::ApplicationContext, input: multitouch::Mul
                 }
                 _ => return,
             };
-            framebuffer.partial_refresh(
+            if let Err(e) = framebuffer.partial_refresh(
                 &rect,
                 PartialRefreshMode::Async,
                 waveform_mode::WAVEFORM_MODE_DU,
@@ -522,7 +537,9 @@ fn on_touch_handler(app: &mut appctx::ApplicationContext, input: multitouch::Mul
                 dither_mode::EPDC_FLAG_USE_DITHERING_ALPHA,
                 DRAWING_QUANT_BIT,
                 false,
-            );
+            ) {
+                println!("Failed to refresh: {0}", e);
+            }
         }
         _ => {}
     }
@@ -562,6 +579,7 @@ fn on_button_press(app: &mut appctx::ApplicationContext, input: gpio::GPIOEvent)
                 Some(ref elem) => {
                     if let UIElement::Text {
                         ref mut text,
+                        font: _,
                         scale: _,
                         foreground: _,
                         border_px: _,
@@ -576,7 +594,9 @@ fn on_button_press(app: &mut appctx::ApplicationContext, input: gpio::GPIOEvent)
             return;
         }
         gpio::PhysicalButton::MIDDLE | gpio::PhysicalButton::LEFT => {
-            app.clear(btn == gpio::PhysicalButton::MIDDLE);
+            if let Err(e) = app.clear(btn == gpio::PhysicalButton::MIDDLE) {
+                println!("Failed to clear the screen: {0}", e);
+            }
             app.draw_elements();
         }
         gpio::PhysicalButton::POWER => {
@@ -599,10 +619,18 @@ fn main() {
     // Takes callback functions as arguments
     // They are called with the event and the &mut framebuffer
     let mut app: appctx::ApplicationContext =
-        appctx::ApplicationContext::new(on_button_press, on_wacom_input, on_touch_handler);
+        match appctx::ApplicationContext::new(on_button_press, on_wacom_input, on_touch_handler) {
+            Ok(app) => app,
+            Err(e) => {
+                println!("Failed to open the framebuffer: {0}", e);
+                std::process::exit(1);
+            }
+        };
 
     // Alternatively we could have called `app.execute_lua("fb.clear()")`
-    app.clear(true);
+    if let Err(e) = app.clear(true) {
+        println!("Failed to clear the screen: {0}", e);
+    }
 
     // A rudimentary way to declare a scene and layout
     app.add_element(
@@ -620,6 +648,7 @@ fn main() {
             onclick: Some(on_touch_rustlogo),
             inner: UIElement::Image {
                 img: image::load_from_memory(include_bytes!("../assets/rustlang.png")).unwrap(),
+                dither: Dither::None,
             },
             ..Default::default()
         },
@@ -638,6 +667,7 @@ fn main() {
                 width: (CANVAS_REGION.width + 1) as usize,
                 border_px: 2,
                 border_color: color::BLACK,
+                background: None,
             },
             ..Default::default()
         },
@@ -652,6 +682,7 @@ fn main() {
 
             onclick: Some(draw_color_test_rgb),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Show RGB Test Image".to_owned(),
                 scale: 35,
@@ -671,6 +702,7 @@ fn main() {
 
             onclick: Some(on_zoom_out),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Zoom Out".to_owned(),
                 scale: 45,
@@ -689,6 +721,7 @@ fn main() {
 
             onclick: Some(on_blur_canvas),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Blur".to_owned(),
                 scale: 45,
@@ -707,6 +740,7 @@ fn main() {
 
             onclick: Some(on_invert_canvas),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Invert".to_owned(),
                 scale: 45,
@@ -726,6 +760,7 @@ fn main() {
 
             onclick: Some(on_save_canvas),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Save".to_owned(),
                 scale: 45,
@@ -744,6 +779,7 @@ fn main() {
 
             onclick: Some(on_load_canvas),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Load".to_owned(),
                 scale: 45,
@@ -763,6 +799,7 @@ fn main() {
 
             onclick: Some(on_change_touchdraw_mode),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Touch Mode".to_owned(),
                 scale: 45,
@@ -780,6 +817,7 @@ fn main() {
 
             onclick: None,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "None".to_owned(),
                 scale: 40,
@@ -799,6 +837,7 @@ fn main() {
 
             onclick: Some(on_toggle_eraser),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Draw Color".to_owned(),
                 scale: 45,
@@ -816,6 +855,7 @@ fn main() {
 
             onclick: None,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: G_DRAW_MODE.load(Ordering::Relaxed).color_as_string(),
                 scale: 40,
@@ -836,6 +876,7 @@ fn main() {
                 change_brush_width(appctx, -1);
             }),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "-".to_owned(),
                 scale: 90,
@@ -851,6 +892,7 @@ fn main() {
             x: 1030,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: format!("size: {0}", G_DRAW_MODE.load(Ordering::Relaxed).get_size()),
                 scale: 45,
@@ -869,6 +911,7 @@ fn main() {
                 change_brush_width(appctx, 1);
             }),
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "+".to_owned(),
                 scale: 90,
@@ -887,6 +930,7 @@ fn main() {
 
             onclick: None,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Press POWER to return to reMarkable".to_owned(),
                 scale: 35,
@@ -902,6 +946,7 @@ fn main() {
             x: 30,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Available at:".to_owned(),
                 scale: 70,
@@ -917,6 +962,7 @@ fn main() {
             x: 30,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "github.com/canselcik/libremarkable".to_owned(),
                 scale: 55,
@@ -932,6 +978,7 @@ fn main() {
             x: 30,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Low Latency eInk Display Partial Refresh API".to_owned(),
                 scale: 45,
@@ -947,6 +994,7 @@ fn main() {
             x: 30,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Capacitive Multitouch Input Support".to_owned(),
                 scale: 45,
@@ -962,6 +1010,7 @@ fn main() {
             x: 30,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Physical Button Support".to_owned(),
                 scale: 45,
@@ -977,6 +1026,7 @@ fn main() {
             x: 30,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Wacom Digitizer Support".to_owned(),
                 scale: 45,
@@ -994,6 +1044,7 @@ fn main() {
             refresh: UIConstraintRefresh::Refresh,
             onclick: None,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Quick Redraw".to_owned(), // maybe quick redraw for the demo or waveform change?
                 scale: 50,
@@ -1009,6 +1060,7 @@ fn main() {
             x: 565,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Full Redraw".to_owned(),
                 scale: 50,
@@ -1024,6 +1076,7 @@ fn main() {
             x: 1112,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: "Disable Touch".to_owned(),
                 scale: 50,
@@ -1042,6 +1095,7 @@ fn main() {
             x: 30,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: format!(
                     "{0:<128}",
@@ -1064,6 +1118,7 @@ fn main() {
             x: 30,
             refresh: UIConstraintRefresh::Refresh,
             inner: UIElement::Text {
+                font: FontHandle::default(),
                 foreground: color::BLACK,
                 text: format!("{}", dt.format("%F %r")),
                 scale: 75,
@@ -1102,10 +1157,10 @@ fn main() {
       width = 320;
       height = 90;
       borderpx = 3;
-      draw_box(top, left, height, width, borderpx, 255);
+      draw_box(top, left, height, width, borderpx, 0);
 
       -- Draw black text inside the box. Notice the text is bottom aligned.
-      fb.draw_text(top+55, left+22, '...also supports Lua', 30, 255);
+      fb.draw_text(top+55, left+22, '...also supports Lua', 30, 0);
 
       -- Update the drawn rect w/ `deep_plot=false` and `wait_for_update_complete=true`
       fb.refresh(top, left, height, width, false, true);
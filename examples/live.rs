@@ -13,7 +13,7 @@ use libremarkable::framebuffer::common::{DISPLAYHEIGHT, DISPLAYWIDTH};
 /// An HTTP server that listens on :8000 and responds to all incoming requests
 /// with the full contents of the framebuffer properly exported as a JPEG.
 fn main() {
-    let fb = Framebuffer::new("/dev/fb0");
+    let fb = Framebuffer::new("/dev/fb0").unwrap();
     println!("libremarkable Framebuffer device initialized");
 
     let server = Server::http("0.0.0.0:8000").unwrap();
@@ -37,6 +37,7 @@ fn main() {
             DISPLAYWIDTH.into(),
             DISPLAYHEIGHT.into(),
             &rgb565,
+            fb.pixel_format(),
         ).unwrap();
         let mut writer = BufWriter::new(Vec::new());
         image::jpeg::JPEGEncoder::new(&mut writer)
@@ -9,6 +9,8 @@ use std::collections::HashMap;
 use image;
 use input::ev;
 
+use device::DeviceProfile;
+use error::Result;
 use framebuffer::common::*;
 
 use hlua;
@@ -22,8 +24,11 @@ use ui_extensions::luaext;
 use aabb_quadtree::{geom, ItemId, QuadTree};
 
 use framebuffer::core;
+use framebuffer::dither::Dither;
+use framebuffer::fill::FillStyle;
+use framebuffer::fonts::FontHandle;
 use framebuffer::refresh::PartialRefreshMode;
-use framebuffer::FramebufferBase;
+use framebuffer::svg::SvgImage;
 use framebuffer::FramebufferDraw;
 use framebuffer::FramebufferRefresh;
 
@@ -40,6 +45,7 @@ unsafe impl<'a> Sync for ApplicationContext<'a> {}
 
 pub struct ApplicationContext<'a> {
     framebuffer: Box<core::Framebuffer<'a>>,
+    profile: DeviceProfile,
     yres: u32,
     xres: u32,
 
@@ -87,14 +93,56 @@ impl<'a> ApplicationContext<'a> {
         (self.yres, self.xres)
     }
 
+    /// Changes the orientation the framebuffer is drawn in and input events are reported
+    /// in. UI elements and active regions keep their coordinates and aren't redrawn.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.framebuffer.set_orientation(orientation);
+        let (yres, xres) = self.framebuffer.dimensions();
+        self.yres = yres;
+        self.xres = xres;
+        for ctx in &[&self.wacom_ctx, &self.touch_ctx, &self.button_ctx] {
+            if let Some(ref device) = *ctx.read().unwrap() {
+                device.state.set_orientation(orientation);
+            }
+        }
+    }
+
+    /// Returns the profile of the device the application is running on
+    pub fn get_profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
+
+    /// Opens the framebuffer of the detected device. Fails if it can't be opened or
+    /// configured.
     pub fn new(
         on_button: fn(&mut ApplicationContext, GPIOEvent),
         on_wacom: fn(&mut ApplicationContext, WacomEvent),
         on_touch: fn(&mut ApplicationContext, MultitouchEvent),
+    ) -> Result<ApplicationContext<'static>> {
+        let profile = DeviceProfile::detect();
+        let framebuffer = core::Framebuffer::from_profile(&profile)?;
+        Ok(ApplicationContext::with_framebuffer(
+            box framebuffer,
+            profile,
+            on_button,
+            on_wacom,
+            on_touch,
+        ))
+    }
+
+    /// Same as `ApplicationContext::new(..)` but draws into the provided `framebuffer`,
+    /// which may be backed by something other than the EPDC (see `framebuffer::backend`).
+    /// Input devices are opened and decoded according to `profile`.
+    pub fn with_framebuffer(
+        framebuffer: Box<core::Framebuffer<'static>>,
+        profile: DeviceProfile,
+        on_button: fn(&mut ApplicationContext, GPIOEvent),
+        on_wacom: fn(&mut ApplicationContext, WacomEvent),
+        on_touch: fn(&mut ApplicationContext, MultitouchEvent),
     ) -> ApplicationContext<'static> {
-        let framebuffer = box core::Framebuffer::new("/dev/fb0");
-        let yres = framebuffer.var_screen_info.yres;
-        let xres = framebuffer.var_screen_info.xres;
+        let (yres, xres) = framebuffer.dimensions();
+        // Large enough to hold the active regions in any orientation
+        let extent = std::cmp::max(yres, xres) as f32;
 
         let (input_tx, input_rx) = std::sync::mpsc::channel();
         let mut res = ApplicationContext {
@@ -102,6 +150,7 @@ impl<'a> ApplicationContext<'a> {
             button_ctx: RwLock::new(None),
             touch_ctx: RwLock::new(None),
             framebuffer,
+            profile,
             xres,
             yres,
             running: AtomicBool::new(false),
@@ -115,8 +164,8 @@ impl<'a> ApplicationContext<'a> {
             active_regions: QuadTree::default(geom::Rect::from_points(
                 &geom::Point { x: 0.0, y: 0.0 },
                 &geom::Point {
-                    x: xres as f32,
-                    y: yres as f32,
+                    x: extent,
+                    y: extent,
                 },
             )),
         };
@@ -158,6 +207,7 @@ impl<'a> ApplicationContext<'a> {
         y: usize,
         x: usize,
         c: color,
+        font: FontHandle,
         scale: usize,
         border_px: usize,
         border_padding: usize,
@@ -165,10 +215,11 @@ impl<'a> ApplicationContext<'a> {
         refresh: UIConstraintRefresh,
     ) -> mxcfb_rect {
         let framebuffer = self.get_framebuffer_ref();
-        let mut draw_area: mxcfb_rect = framebuffer.draw_text(y, x, text, scale, c, false);
+        let mut draw_area: mxcfb_rect = framebuffer.draw_text(y, x, text, font, scale, c, false);
 
         // Draw the border if border_px is set to a non-default value
         if border_px > 0 {
+            let c = framebuffer.tone_curve().map(c);
             draw_area.top -= border_padding as u32;
             draw_area.left -= border_padding as u32;
             draw_area.height += 2 * border_padding as u32;
@@ -183,26 +234,12 @@ impl<'a> ApplicationContext<'a> {
             );
         }
 
-        let marker = match refresh {
-            UIConstraintRefresh::Refresh | UIConstraintRefresh::RefreshAndWait => framebuffer
-                .partial_refresh(
-                    &draw_area,
-                    PartialRefreshMode::Async,
-                    waveform_mode::WAVEFORM_MODE_GC16_FAST,
-                    display_temp::TEMP_USE_REMARKABLE_DRAW,
-                    dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
-                    0,
-                    false,
-                ),
-            _ => return draw_area,
-        };
-
-        if let UIConstraintRefresh::RefreshAndWait = refresh {
-            framebuffer.wait_refresh_complete(marker);
-        }
+        self.refresh_drawn_area(&draw_area, refresh);
         draw_area
     }
 
+    /// Draws the border of a region of `height` and `width` at `(y, x)`, after painting its
+    /// inside with `background` if it is set
     pub fn display_rect(
         &mut self,
         y: usize,
@@ -211,10 +248,15 @@ impl<'a> ApplicationContext<'a> {
         width: usize,
         border_px: usize,
         border_color: color,
+        background: Option<&FillStyle>,
         refresh: UIConstraintRefresh,
     ) -> mxcfb_rect {
         let framebuffer = self.get_framebuffer_ref();
 
+        if let Some(style) = background {
+            framebuffer.fill_rect_styled(y, x, height, width, style);
+        }
+        let border_color = framebuffer.tone_curve().map(border_color);
         framebuffer.draw_rect(y, x, height, width, border_px, border_color);
         let draw_area = mxcfb_rect {
             top: y as u32,
@@ -222,23 +264,7 @@ impl<'a> ApplicationContext<'a> {
             height: height as u32,
             width: width as u32,
         };
-        let marker = match refresh {
-            UIConstraintRefresh::Refresh | UIConstraintRefresh::RefreshAndWait => framebuffer
-                .partial_refresh(
-                    &draw_area,
-                    PartialRefreshMode::Async,
-                    waveform_mode::WAVEFORM_MODE_GC16_FAST,
-                    display_temp::TEMP_USE_REMARKABLE_DRAW,
-                    dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
-                    0,
-                    false,
-                ),
-            _ => return draw_area,
-        };
-
-        if let UIConstraintRefresh::RefreshAndWait = refresh {
-            framebuffer.wait_refresh_complete(marker);
-        }
+        self.refresh_drawn_area(&draw_area, refresh);
         draw_area
     }
 
@@ -247,17 +273,48 @@ impl<'a> ApplicationContext<'a> {
         img: &image::DynamicImage,
         y: usize,
         x: usize,
+        dither: Dither,
         refresh: UIConstraintRefresh,
     ) -> mxcfb_rect {
         let framebuffer = self.get_framebuffer_ref();
         let draw_area = match img {
-            image::DynamicImage::ImageRgb8(ref rgb) => framebuffer.draw_image(rgb, y, x),
-            other => framebuffer.draw_image(&other.to_rgb(), y, x),
+            image::DynamicImage::ImageRgb8(ref rgb) => framebuffer.draw_image(rgb, y, x, dither),
+            other => framebuffer.draw_image(&other.to_rgb(), y, x, dither),
         };
+        self.refresh_drawn_area(&draw_area, refresh);
+        draw_area
+    }
+
+    /// Draws `svg` scaled to fit in the `height` by `width` region at `(y, x)`. The whole
+    /// region is returned so that redrawing a smaller image clears what it no longer covers.
+    pub fn display_svg(
+        &mut self,
+        svg: &SvgImage,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        refresh: UIConstraintRefresh,
+    ) -> mxcfb_rect {
+        let dest = mxcfb_rect {
+            top: y as u32,
+            left: x as u32,
+            height: height as u32,
+            width: width as u32,
+        };
+        self.get_framebuffer_ref().draw_svg(svg, dest);
+        self.refresh_drawn_area(&dest, refresh);
+        dest
+    }
+
+    /// Refreshes `draw_area` as requested by `refresh`. Failures are logged since the
+    /// content has already been drawn and will be shown by the next successful refresh.
+    fn refresh_drawn_area(&mut self, draw_area: &mxcfb_rect, refresh: UIConstraintRefresh) {
+        let framebuffer = self.get_framebuffer_ref();
         let marker = match refresh {
             UIConstraintRefresh::Refresh | UIConstraintRefresh::RefreshAndWait => framebuffer
                 .partial_refresh(
-                    &draw_area,
+                    draw_area,
                     PartialRefreshMode::Async,
                     waveform_mode::WAVEFORM_MODE_GC16_FAST,
                     display_temp::TEMP_USE_REMARKABLE_DRAW,
@@ -265,13 +322,18 @@ impl<'a> ApplicationContext<'a> {
                     0,
                     false,
                 ),
-            _ => return draw_area,
+            _ => return,
         };
 
-        if let UIConstraintRefresh::RefreshAndWait = refresh {
-            framebuffer.wait_refresh_complete(marker);
+        let result = match (marker, refresh) {
+            (Ok(marker), UIConstraintRefresh::RefreshAndWait) => {
+                framebuffer.wait_refresh_complete(marker).map(|_| ())
+            }
+            (marker, _) => marker.map(|_| ()),
+        };
+        if let Err(e) = result {
+            warn!("Failed to refresh the drawn area: {0}", e);
         }
-        draw_area
     }
 
     pub fn add_element(
@@ -353,7 +415,7 @@ impl<'a> ApplicationContext<'a> {
                     rect.width as usize,
                     color::BLACK,
                 );
-                framebuffer.partial_refresh(
+                if let Err(e) = framebuffer.partial_refresh(
                     &rect,
                     PartialRefreshMode::Wait,
                     waveform_mode::WAVEFORM_MODE_DU,
@@ -361,7 +423,9 @@ impl<'a> ApplicationContext<'a> {
                     dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                     0,
                     false,
-                );
+                ) {
+                    warn!("Failed to refresh the flashed element: {0}", e);
+                }
 
                 // We can pass None as the `handler` here as we know this flashing is not
                 // changing the positioning of the `UIElementWrapper`.
@@ -370,12 +434,9 @@ impl<'a> ApplicationContext<'a> {
         }
     }
 
-    pub fn clear(&mut self, deep: bool) {
+    pub fn clear(&mut self, deep: bool) -> Result<()> {
         let framebuffer = self.get_framebuffer_ref();
-        let (yres, xres) = (
-            framebuffer.var_screen_info.yres,
-            framebuffer.var_screen_info.xres,
-        );
+        let (yres, xres) = framebuffer.dimensions();
         framebuffer.clear();
 
         if deep {
@@ -385,7 +446,7 @@ impl<'a> ApplicationContext<'a> {
                 dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                 0,
                 true,
-            );
+            )?;
         } else {
             framebuffer.partial_refresh(
                 &mxcfb_rect {
@@ -400,8 +461,24 @@ impl<'a> ApplicationContext<'a> {
                 dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                 0,
                 false,
-            );
+            )?;
         }
+        Ok(())
+    }
+
+    /// Switches the whole display to white on black, or back, through the EPDC and refreshes
+    /// it so that the change shows immediately. The framebuffer contents are left as they are.
+    pub fn set_night_mode(&mut self, enabled: bool) -> Result<()> {
+        let framebuffer = self.get_framebuffer_ref();
+        framebuffer.set_night_mode(enabled);
+        framebuffer.full_refresh(
+            waveform_mode::WAVEFORM_MODE_GC16,
+            display_temp::TEMP_USE_AMBIENT,
+            dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+            0,
+            true,
+        )?;
+        Ok(())
     }
 
     /// Sets an atomic flag to disable event dispatch. Exiting event dispatch loop will cause
@@ -463,13 +540,17 @@ impl<'a> ApplicationContext<'a> {
             _ => return false,
         };
 
-        *dev = Some(ev::EvDevContext::new(t, self.input_tx.clone()));
-        match dev.as_mut() {
-            Some(ref mut device) => {
-                device.start();
+        let mut device = ev::EvDevContext::new(t, &self.profile, self.input_tx.clone());
+        device.state.set_orientation(self.framebuffer.orientation());
+        match device.start() {
+            Ok(()) => {
+                *dev = Some(device);
                 true
             }
-            None => false,
+            Err(e) => {
+                error!("Unable to activate the input device: {0}", e);
+                false
+            }
         }
     }
 
@@ -0,0 +1,180 @@
+use std::fs::{self, File};
+use std::io::Read;
+
+use framebuffer::common::{
+    DISPLAYHEIGHT, DISPLAYWIDTH, MTHEIGHT, MTWIDTH, WACOMHEIGHT, WACOMWIDTH,
+};
+
+/// Timings written into `VarScreeninfo` when the framebuffer is opened.
+/// All values are in pixclocks, except `pixclock` which is in picoseconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayTimings {
+    pub pixclock: u32,
+    pub left_margin: u32,
+    pub right_margin: u32,
+    pub upper_margin: u32,
+    pub lower_margin: u32,
+    pub hsync_len: u32,
+    pub vsync_len: u32,
+}
+
+/// Describes how the positions reported by a digitizer map onto the display
+#[derive(Clone, Debug, PartialEq)]
+pub struct DigitizerProfile {
+    /// Path to the evdev node of the digitizer
+    pub path: String,
+    /// Maximum value of the axis that runs along the width of the display (after `swap_axes`)
+    pub width: u16,
+    /// Maximum value of the axis that runs along the height of the display (after `swap_axes`)
+    pub height: u16,
+    /// The digitizer's X axis runs along the height of the display
+    pub swap_axes: bool,
+    /// The horizontal axis grows from right to left
+    pub invert_x: bool,
+    /// The vertical axis grows from bottom to top
+    pub invert_y: bool,
+}
+
+impl DigitizerProfile {
+    /// Maps the raw `(x, y)` position reported by the digitizer onto a display of
+    /// `height` by `width` pixels. Returns `(y, x)`, always within the display.
+    pub fn map_to_display(&self, raw_x: u16, raw_y: u16, height: u32, width: u32) -> (u16, u16) {
+        let (x, y) = if self.swap_axes {
+            (raw_y, raw_x)
+        } else {
+            (raw_x, raw_y)
+        };
+        let x = ::std::cmp::min(x, self.width);
+        let y = ::std::cmp::min(y, self.height);
+        let x = if self.invert_x { self.width - x } else { x };
+        let y = if self.invert_y { self.height - y } else { y };
+
+        let hscalar = width as f32 / f32::from(self.width);
+        let vscalar = height as f32 / f32::from(self.height);
+        // The far edge of the digitizer would land one pixel past the display
+        let y = ((f32::from(y) * vscalar) as u32).min(height.saturating_sub(1));
+        let x = ((f32::from(x) * hscalar) as u32).min(width.saturating_sub(1));
+        (y as u16, x as u16)
+    }
+}
+
+/// Geometry of the display and the digitizers of a tablet revision, along with the
+/// device nodes they are exposed through. Use `DeviceProfile::detect()` to pick the profile
+/// of the device at runtime, or build one yourself for hardware that isn't known yet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceProfile {
+    pub name: String,
+    /// Width of the display in pixels, as seen by the application
+    pub display_width: u32,
+    /// Height of the display in pixels, as seen by the application
+    pub display_height: u32,
+    /// Value written to `VarScreeninfo::rotate`. When odd, the panel is scanned out
+    /// with its width and height swapped.
+    pub rotate: u32,
+    pub timings: DisplayTimings,
+    /// Path to the framebuffer device
+    pub framebuffer_path: String,
+    pub wacom: DigitizerProfile,
+    pub multitouch: DigitizerProfile,
+    /// Path to the evdev node of the physical buttons
+    pub gpio_path: String,
+}
+
+/// Names the input devices of the reMarkable register with the kernel
+const WACOM_DEVICE_NAME: &str = "Wacom I2C Digitizer";
+const MULTITOUCH_DEVICE_NAME: &str = "cyttsp5_mt";
+const GPIO_DEVICE_NAME: &str = "gpio-keys";
+
+impl DeviceProfile {
+    /// The reMarkable paper tablet
+    pub fn remarkable() -> DeviceProfile {
+        DeviceProfile {
+            name: "reMarkable".to_owned(),
+            display_width: u32::from(DISPLAYWIDTH),
+            display_height: u32::from(DISPLAYHEIGHT),
+            rotate: 1,
+            timings: DisplayTimings {
+                pixclock: 160_000_000,
+                left_margin: 32,
+                right_margin: 326,
+                upper_margin: 4,
+                lower_margin: 12,
+                hsync_len: 44,
+                vsync_len: 1,
+            },
+            framebuffer_path: "/dev/fb0".to_owned(),
+            wacom: DigitizerProfile {
+                path: "/dev/input/event0".to_owned(),
+                width: WACOMWIDTH,
+                height: WACOMHEIGHT,
+                swap_axes: true,
+                invert_x: false,
+                invert_y: true,
+            },
+            multitouch: DigitizerProfile {
+                path: "/dev/input/event1".to_owned(),
+                width: MTWIDTH,
+                height: MTHEIGHT,
+                swap_axes: false,
+                invert_x: true,
+                invert_y: true,
+            },
+            gpio_path: "/dev/input/event2".to_owned(),
+        }
+    }
+
+    /// Picks the profile matching the machine reported by the kernel, falling back to
+    /// `DeviceProfile::remarkable()`, and resolves the input device nodes by name since
+    /// their numbering isn't guaranteed to be stable.
+    pub fn detect() -> DeviceProfile {
+        let machine = read_trimmed("/sys/devices/soc0/machine").unwrap_or_default();
+        if !machine.starts_with("reMarkable") {
+            warn!(
+                "Unknown machine '{0}', falling back to the reMarkable profile",
+                machine
+            );
+        }
+
+        let mut profile = DeviceProfile::remarkable();
+        if let Some(path) = find_input_device(WACOM_DEVICE_NAME) {
+            profile.wacom.path = path;
+        }
+        if let Some(path) = find_input_device(MULTITOUCH_DEVICE_NAME) {
+            profile.multitouch.path = path;
+        }
+        if let Some(path) = find_input_device(GPIO_DEVICE_NAME) {
+            profile.gpio_path = path;
+        }
+        profile
+    }
+}
+
+impl Default for DeviceProfile {
+    fn default() -> DeviceProfile {
+        DeviceProfile::remarkable()
+    }
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    let mut data = String::new();
+    File::open(path).ok()?.read_to_string(&mut data).ok()?;
+    Some(data.trim().to_owned())
+}
+
+/// Returns the `/dev/input/eventN` node of the input device registered as `name`
+fn find_input_device(name: &str) -> Option<String> {
+    for entry in fs::read_dir("/sys/class/input").ok()? {
+        let node = match entry {
+            Ok(entry) => entry.file_name().to_string_lossy().into_owned(),
+            Err(_) => continue,
+        };
+        if !node.starts_with("event") {
+            continue;
+        }
+        let device_name = read_trimmed(&format!("/sys/class/input/{0}/device/name", node));
+        if device_name.as_ref().map(String::as_str) == Some(name) {
+            return Some(format!("/dev/input/{0}", node));
+        }
+    }
+    None
+}
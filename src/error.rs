@@ -0,0 +1,72 @@
+use libc;
+use std;
+use std::fmt;
+use std::io;
+
+/// The error type returned by the framebuffer, refresh, input and battery APIs
+#[derive(Debug)]
+pub enum Error {
+    /// Opening, reading or mapping a device node or a sysfs attribute failed
+    Io(io::Error),
+    /// The driver rejected the ioctl named `request`
+    Ioctl {
+        request: &'static str,
+        source: io::Error,
+    },
+    /// A region didn't fit in the framebuffer or didn't match the size of its data
+    Bounds(&'static str),
+    /// The contents of `attribute` couldn't be parsed
+    Parse { attribute: String, value: String },
+    /// The device or its current configuration doesn't support the operation
+    Unsupported(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(ref e) => write!(f, "I/O error: {0}", e),
+            Error::Ioctl {
+                request,
+                ref source,
+            } => write!(f, "{0} failed: {1}", request, source),
+            Error::Bounds(reason) => write!(f, "Invalid region: {0}", reason),
+            Error::Parse {
+                ref attribute,
+                ref value,
+            } => write!(f, "Unable to parse the contents of '{0}': {1:?}", attribute, value),
+            Error::Unsupported(reason) => write!(f, "Unsupported: {0}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn description(&self) -> &str {
+        match self {
+            Error::Io(_) => "I/O error",
+            Error::Ioctl { .. } => "ioctl failed",
+            Error::Bounds(reason) => reason,
+            Error::Parse { .. } => "parse error",
+            Error::Unsupported(reason) => reason,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Turns the return value of `libc::ioctl` into a `Result`, picking up `errno` on failure
+pub(crate) fn check_ioctl(request: &'static str, result: libc::c_int) -> Result<()> {
+    if result < 0 {
+        Err(Error::Ioctl {
+            request,
+            source: io::Error::last_os_error(),
+        })
+    } else {
+        Ok(())
+    }
+}
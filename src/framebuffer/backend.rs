@@ -0,0 +1,375 @@
+#![allow(dead_code)]
+
+use libc;
+use mmap;
+use mmap::MemoryMap;
+
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+
+use device::DeviceProfile;
+use error::{check_ioctl, Error, Result};
+use framebuffer;
+use framebuffer::cmap::{Colormap, COLORMAP_LEN};
+use framebuffer::common::{
+    FBIOGETCMAP, FBIOPUTCMAP, MXCFB_DISABLE_EPDC_ACCESS, MXCFB_ENABLE_EPDC_ACCESS,
+    MXCFB_SEND_UPDATE, MXCFB_SET_AUTO_UPDATE_MODE, MXCFB_SET_UPDATE_SCHEME,
+    MXCFB_WAIT_FOR_UPDATE_COMPLETE,
+};
+use framebuffer::core;
+use framebuffer::mxcfb::{mxcfb_update_data, mxcfb_update_marker_data};
+use framebuffer::common::PixelFormat;
+use framebuffer::screeninfo::{FbCmap, FixScreeninfo, VarScreeninfo};
+use framebuffer::FramebufferBase;
+
+/// Backend talking to the EPDC through the framebuffer device node (e.g. `/dev/fb0`)
+/// with its memory mapped into the process.
+pub struct DeviceBackend {
+    pub device: File,
+    pub frame: MemoryMap,
+    var_screen_info: VarScreeninfo,
+    fix_screen_info: FixScreeninfo,
+}
+
+unsafe impl Send for DeviceBackend {}
+unsafe impl Sync for DeviceBackend {}
+
+impl DeviceBackend {
+    /// Opens the framebuffer device at `path_to_device`, configures the resolution and
+    /// display timings described by `profile` and maps its memory.
+    pub fn open(path_to_device: &str, profile: &DeviceProfile) -> Result<DeviceBackend> {
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path_to_device)?;
+
+        let fix_screen_info = core::Framebuffer::get_fix_screeninfo(&device)?;
+        let mut var_screen_info = core::Framebuffer::get_var_screeninfo(&device)?;
+        // The panel is scanned out in its native orientation, the driver swaps
+        // xres and yres back to what we see once it has applied the rotation.
+        if profile.rotate % 2 == 1 {
+            var_screen_info.xres = profile.display_height;
+            var_screen_info.yres = profile.display_width;
+        } else {
+            var_screen_info.xres = profile.display_width;
+            var_screen_info.yres = profile.display_height;
+        }
+        var_screen_info.rotate = profile.rotate;
+        var_screen_info.width = 0xffff_ffff;
+        var_screen_info.height = 0xffff_ffff;
+        var_screen_info.pixclock = profile.timings.pixclock;
+        var_screen_info.left_margin = profile.timings.left_margin;
+        var_screen_info.right_margin = profile.timings.right_margin;
+        var_screen_info.upper_margin = profile.timings.upper_margin;
+        var_screen_info.lower_margin = profile.timings.lower_margin;
+        var_screen_info.hsync_len = profile.timings.hsync_len;
+        var_screen_info.vsync_len = profile.timings.vsync_len;
+        var_screen_info.sync = 0;
+        var_screen_info.vmode = 0; // FB_VMODE_NONINTERLACED
+        var_screen_info.accel_flags = 0;
+
+        core::Framebuffer::put_var_screeninfo(&device, &mut var_screen_info)?;
+
+        // Map all of it, not just the visible frame, so that the rest can be used as a back buffer
+        let frame_length = fix_screen_info.smem_len as usize;
+        let mem_map = MemoryMap::new(
+            frame_length,
+            &[
+                mmap::MapOption::MapReadable,
+                mmap::MapOption::MapWritable,
+                mmap::MapOption::MapFd(device.as_raw_fd()),
+                mmap::MapOption::MapOffset(0),
+                mmap::MapOption::MapNonStandardFlags(libc::MAP_SHARED),
+            ],
+        ).map_err(|e| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Unable to map the framebuffer memory: {0:?}", e),
+            ))
+        })?;
+
+        Ok(DeviceBackend {
+            device,
+            frame: mem_map,
+            var_screen_info,
+            fix_screen_info,
+        })
+    }
+}
+
+impl framebuffer::FramebufferBackend for DeviceBackend {
+    fn frame_ptr(&self) -> *mut u8 {
+        self.frame.data()
+    }
+
+    fn frame_len(&self) -> usize {
+        self.frame.len()
+    }
+
+    fn fix_screeninfo(&self) -> FixScreeninfo {
+        self.fix_screen_info.clone()
+    }
+
+    fn var_screeninfo(&self) -> VarScreeninfo {
+        self.var_screen_info.clone()
+    }
+
+    fn set_epdc_access(&self, state: bool) -> Result<()> {
+        let (request, name) = if state {
+            (MXCFB_ENABLE_EPDC_ACCESS, "MXCFB_ENABLE_EPDC_ACCESS")
+        } else {
+            (MXCFB_DISABLE_EPDC_ACCESS, "MXCFB_DISABLE_EPDC_ACCESS")
+        };
+        let result = unsafe { libc::ioctl(self.device.as_raw_fd(), request) };
+        check_ioctl(name, result)
+    }
+
+    fn set_autoupdate_mode(&self, mode: u32) -> Result<()> {
+        let m = mode.to_owned();
+        let result = unsafe {
+            libc::ioctl(
+                self.device.as_raw_fd(),
+                MXCFB_SET_AUTO_UPDATE_MODE,
+                &m as *const u32,
+            )
+        };
+        check_ioctl("MXCFB_SET_AUTO_UPDATE_MODE", result)
+    }
+
+    fn set_update_scheme(&self, scheme: u32) -> Result<()> {
+        let s = scheme.to_owned();
+        let result = unsafe {
+            libc::ioctl(
+                self.device.as_raw_fd(),
+                MXCFB_SET_UPDATE_SCHEME,
+                &s as *const u32,
+            )
+        };
+        check_ioctl("MXCFB_SET_UPDATE_SCHEME", result)
+    }
+
+    fn send_update(&self, update: &mxcfb_update_data) -> Result<()> {
+        let pt: *const mxcfb_update_data = update;
+        let result = unsafe { libc::ioctl(self.device.as_raw_fd(), MXCFB_SEND_UPDATE, pt) };
+        check_ioctl("MXCFB_SEND_UPDATE", result)
+    }
+
+    fn wait_update_complete(&self, markerdata: &mut mxcfb_update_marker_data) -> Result<()> {
+        let result = unsafe {
+            libc::ioctl(
+                self.device.as_raw_fd(),
+                MXCFB_WAIT_FOR_UPDATE_COMPLETE,
+                markerdata as *mut mxcfb_update_marker_data,
+            )
+        };
+        check_ioctl("MXCFB_WAIT_FOR_UPDATE_COMPLETE", result)
+    }
+
+    fn put_var_screeninfo(&mut self, var_screen_info: &VarScreeninfo) -> Result<()> {
+        let mut info = var_screen_info.clone();
+        core::Framebuffer::put_var_screeninfo(&self.device, &mut info)?;
+        let fix_screen_info = core::Framebuffer::get_fix_screeninfo(&self.device)?;
+        if (fix_screen_info.line_length * info.yres) as usize > self.frame.len() {
+            return Err(Error::Unsupported(
+                "The new configuration doesn't fit in the mapped framebuffer memory",
+            ));
+        }
+        self.var_screen_info = info;
+        self.fix_screen_info = fix_screen_info;
+        Ok(())
+    }
+
+    fn colormap(&self, start: u32, len: u32) -> Result<Colormap> {
+        let mut colormap = Colormap {
+            start,
+            red: vec![0; len as usize],
+            green: vec![0; len as usize],
+            blue: vec![0; len as usize],
+        };
+        let mut raw = FbCmap {
+            start,
+            len,
+            red: colormap.red.as_mut_ptr(),
+            green: colormap.green.as_mut_ptr(),
+            blue: colormap.blue.as_mut_ptr(),
+            transp: ptr::null_mut(),
+        };
+        let result =
+            unsafe { libc::ioctl(self.device.as_raw_fd(), FBIOGETCMAP, &mut raw as *mut FbCmap) };
+        check_ioctl("FBIOGETCMAP", result)?;
+        Ok(colormap)
+    }
+
+    fn put_colormap(&self, colormap: &Colormap) -> Result<()> {
+        check_colormap(colormap)?;
+        // The driver only reads the entries
+        let raw = FbCmap {
+            start: colormap.start,
+            len: colormap.len() as u32,
+            red: colormap.red.as_ptr() as *mut u16,
+            green: colormap.green.as_ptr() as *mut u16,
+            blue: colormap.blue.as_ptr() as *mut u16,
+            transp: ptr::null_mut(),
+        };
+        let result =
+            unsafe { libc::ioctl(self.device.as_raw_fd(), FBIOPUTCMAP, &raw as *const FbCmap) };
+        check_ioctl("FBIOPUTCMAP", result)
+    }
+}
+
+/// Makes sure that all the components of `colormap` have the same number of entries and
+/// that they fit in the colormap of the framebuffer
+fn check_colormap(colormap: &Colormap) -> Result<()> {
+    let len = colormap.len();
+    if colormap.green.len() != len || colormap.blue.len() != len {
+        return Err(Error::Bounds("The components of the colormap have different lengths"));
+    }
+    if u64::from(colormap.start) + len as u64 > u64::from(COLORMAP_LEN) {
+        return Err(Error::Bounds("The colormap doesn't fit in the framebuffer's"));
+    }
+    Ok(())
+}
+
+/// Backend keeping the pixels in an RGB565 buffer in process memory. Nothing is ever
+/// shown; update requests are recorded instead so that they can be inspected.
+/// This allows the drawing code to run off-device, e.g. on a desktop or in `cargo test`.
+pub struct MemoryBackend {
+    frame: UnsafeCell<Box<[u8]>>,
+    refreshes: Arc<Mutex<Vec<mxcfb_update_data>>>,
+    colormap: Mutex<Colormap>,
+    var_screen_info: VarScreeninfo,
+    fix_screen_info: FixScreeninfo,
+}
+
+unsafe impl Send for MemoryBackend {}
+unsafe impl Sync for MemoryBackend {}
+
+impl MemoryBackend {
+    /// Creates a white RGB565 frame of `height` by `width` pixels, followed by room
+    /// for a back buffer of the same size
+    pub fn new(height: u32, width: u32) -> MemoryBackend {
+        let bytespp = 2;
+        let line_length = width * bytespp;
+        let yres_virtual = height * 2;
+        let frame_length = (line_length * yres_virtual) as usize;
+
+        let mut var_screen_info: VarScreeninfo = Default::default();
+        var_screen_info.xres = width;
+        var_screen_info.yres = height;
+        var_screen_info.xres_virtual = width;
+        var_screen_info.yres_virtual = yres_virtual;
+        PixelFormat::Rgb565.apply(&mut var_screen_info);
+        var_screen_info.width = 0xffff_ffff;
+        var_screen_info.height = 0xffff_ffff;
+
+        let mut fix_screen_info: FixScreeninfo = Default::default();
+        fix_screen_info.smem_len = frame_length as u32;
+        fix_screen_info.line_length = line_length;
+
+        MemoryBackend {
+            frame: UnsafeCell::new(vec![0xFF; frame_length].into_boxed_slice()),
+            refreshes: Arc::new(Mutex::new(Vec::new())),
+            colormap: Mutex::new(Colormap::identity()),
+            var_screen_info,
+            fix_screen_info,
+        }
+    }
+
+    /// Creates a white RGB565 frame matching the display of `profile`
+    pub fn from_profile(profile: &DeviceProfile) -> MemoryBackend {
+        MemoryBackend::new(profile.display_height, profile.display_width)
+    }
+
+    /// Returns a handle to the list of update requests that have been sent to this backend
+    /// so far. It stays valid after the backend has been moved into a `Framebuffer`.
+    pub fn refresh_log(&self) -> Arc<Mutex<Vec<mxcfb_update_data>>> {
+        Arc::clone(&self.refreshes)
+    }
+}
+
+impl ::std::default::Default for MemoryBackend {
+    fn default() -> Self {
+        MemoryBackend::from_profile(&DeviceProfile::default())
+    }
+}
+
+impl framebuffer::FramebufferBackend for MemoryBackend {
+    fn frame_ptr(&self) -> *mut u8 {
+        unsafe { (&mut *self.frame.get()).as_mut_ptr() }
+    }
+
+    fn frame_len(&self) -> usize {
+        unsafe { (&*self.frame.get()).len() }
+    }
+
+    fn fix_screeninfo(&self) -> FixScreeninfo {
+        self.fix_screen_info.clone()
+    }
+
+    fn var_screeninfo(&self) -> VarScreeninfo {
+        self.var_screen_info.clone()
+    }
+
+    fn set_epdc_access(&self, _state: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_autoupdate_mode(&self, _mode: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_update_scheme(&self, _scheme: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn send_update(&self, update: &mxcfb_update_data) -> Result<()> {
+        self.refreshes.lock().unwrap().push(update.clone());
+        Ok(())
+    }
+
+    fn wait_update_complete(&self, markerdata: &mut mxcfb_update_marker_data) -> Result<()> {
+        markerdata.collision_test = 0;
+        Ok(())
+    }
+
+    fn put_var_screeninfo(&mut self, var_screen_info: &VarScreeninfo) -> Result<()> {
+        let format = PixelFormat::from_screeninfo(var_screen_info);
+        if var_screen_info.bits_per_pixel != format.bytes_per_pixel() as u32 * 8 {
+            return Err(Error::Unsupported("Only 8 and 16 bits per pixel are supported"));
+        }
+
+        // Only the pixel format can change, the frame keeps its size
+        format.apply(&mut self.var_screen_info);
+        self.fix_screen_info.line_length =
+            self.var_screen_info.xres_virtual * format.bytes_per_pixel() as u32;
+        Ok(())
+    }
+
+    fn colormap(&self, start: u32, len: u32) -> Result<Colormap> {
+        if u64::from(start) + u64::from(len) > u64::from(COLORMAP_LEN) {
+            return Err(Error::Bounds("The colormap doesn't fit in the framebuffer's"));
+        }
+        let range = start as usize..(start + len) as usize;
+        let stored = self.colormap.lock().unwrap();
+        Ok(Colormap {
+            start,
+            red: stored.red[range.clone()].to_vec(),
+            green: stored.green[range.clone()].to_vec(),
+            blue: stored.blue[range].to_vec(),
+        })
+    }
+
+    fn put_colormap(&self, colormap: &Colormap) -> Result<()> {
+        check_colormap(colormap)?;
+        let range = colormap.start as usize..colormap.start as usize + colormap.len();
+        let mut stored = self.colormap.lock().unwrap();
+        stored.red[range.clone()].copy_from_slice(&colormap.red);
+        stored.green[range.clone()].copy_from_slice(&colormap.green);
+        stored.blue[range].copy_from_slice(&colormap.blue);
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::atomic::Ordering;
+
+use error::{Error, Result};
+use framebuffer;
+use framebuffer::common;
+use framebuffer::core;
+use framebuffer::mxcfb::*;
+use framebuffer::refresh::PxpFlags;
+
+impl<'a> core::Framebuffer<'a> {
+    /// Size in bytes of the visible frame
+    fn visible_frame_len(&self) -> usize {
+        (self.fix_screen_info.line_length * self.var_screen_info.yres) as usize
+    }
+
+    /// Copies the rows and columns covered by the native `region` from `src` to `dst`
+    fn copy_native_region(&self, region: &common::mxcfb_rect, src: *const u8, dst: *mut u8) {
+        let line_length = self.fix_screen_info.line_length as usize;
+        let bytespp = (self.var_screen_info.bits_per_pixel / 8) as usize;
+        let chunk_size = bytespp * region.width as usize;
+        for row in region.top..region.top + region.height {
+            let offset = row as usize * line_length + bytespp * region.left as usize;
+            unsafe {
+                src.add(offset)
+                    .copy_to_nonoverlapping(dst.add(offset), chunk_size);
+            }
+        }
+    }
+
+    /// Maps `region` onto the native display, failing if nothing of it is left on screen
+    fn native_region(&self, region: &common::mxcfb_rect) -> Result<common::mxcfb_rect> {
+        let native = self.orientation().to_native_rect(
+            region,
+            self.var_screen_info.yres,
+            self.var_screen_info.xres,
+        );
+        if native.width == 0 || native.height == 0 {
+            return Err(Error::Bounds("Region is empty or entirely out of bounds"));
+        }
+        Ok(native)
+    }
+}
+
+impl<'a> framebuffer::FramebufferBuffering for core::Framebuffer<'a> {
+    fn enable_back_buffer(&mut self) -> Result<()> {
+        if self.back_buffer.is_some() {
+            return Ok(());
+        }
+
+        let offset = self.visible_frame_len();
+        if self.backend.frame_len() < offset * 2
+            || self.var_screen_info.yres_virtual < self.var_screen_info.yres * 2
+        {
+            return Err(Error::Unsupported(
+                "The framebuffer memory can't hold a back buffer",
+            ));
+        }
+
+        let begin = self.backend.frame_ptr();
+        unsafe {
+            begin.copy_to_nonoverlapping(begin.add(offset), offset);
+        }
+        self.back_buffer = Some(offset);
+        Ok(())
+    }
+
+    fn disable_back_buffer(&mut self) {
+        self.back_buffer = None;
+    }
+
+    fn back_buffer_enabled(&self) -> bool {
+        self.back_buffer.is_some()
+    }
+
+    fn sync_back_buffer(&mut self, region: &common::mxcfb_rect) -> Result<()> {
+        let offset = match self.back_buffer {
+            Some(offset) => offset,
+            None => return Err(Error::Unsupported("The back buffer isn't enabled")),
+        };
+        let region = self.native_region(region)?;
+
+        let begin = self.backend.frame_ptr();
+        self.copy_native_region(&region, begin, unsafe { begin.add(offset) });
+        Ok(())
+    }
+
+    fn present(
+        &self,
+        region: &common::mxcfb_rect,
+        waveform_mode: common::waveform_mode,
+        temperature: common::display_temp,
+        dither_mode: common::dither_mode,
+        quant_bit: i32,
+        wait_completion: bool,
+    ) -> Result<u32> {
+        let offset = match self.back_buffer {
+            Some(offset) => offset,
+            None => return Err(Error::Unsupported("The back buffer isn't enabled")),
+        };
+        let update_region = self.native_region(region)?;
+
+        let marker = self.marker.fetch_add(1, Ordering::Relaxed);
+        let whole = mxcfb_update_data {
+            update_mode: common::update_mode::UPDATE_MODE_PARTIAL as u32,
+            update_marker: marker as u32,
+            waveform_mode: waveform_mode as u32,
+            temp: temperature as i32,
+            flags: common::EPDC_FLAG_USE_ALT_BUFFER | self.pxp_flags(PxpFlags::default()).bits(),
+            quant_bit,
+            dither_mode: dither_mode as i32,
+            update_region,
+            alt_buffer_data: mxcfb_alt_buffer_data {
+                phys_addr: (self.fix_screen_info.smem_start + offset) as u32,
+                width: self.var_screen_info.xres_virtual,
+                height: self.var_screen_info.yres,
+                alt_update_region: update_region,
+            },
+        };
+
+        self.backend.send_update(&whole)?;
+
+        // The EPDC reads the region from the back buffer, but everything else (later partial
+        // refreshes, dump_region with the back buffer disabled...) still uses the visible frame.
+        let begin = self.backend.frame_ptr();
+        self.copy_native_region(&update_region, unsafe { begin.add(offset) }, begin);
+
+        if wait_completion {
+            let mut markerdata = mxcfb_update_marker_data {
+                update_marker: whole.update_marker,
+                collision_test: 0,
+            };
+            self.backend.wait_update_complete(&mut markerdata)?;
+        }
+        Ok(whole.update_marker)
+    }
+}
@@ -0,0 +1,53 @@
+use framebuffer::gray::ToneCurve;
+
+/// Number of entries in the colormap of the EPDC framebuffer, one per 8-bit gray level
+pub const COLORMAP_LEN: u32 = 256;
+
+/// Entries of a framebuffer colormap as read and installed with `FBIOGETCMAP` and
+/// `FBIOPUTCMAP`, starting at index `start`. Intensities are 16-bit, 0xFFFF being full.
+/// The EPDC turns the colormap into the lookup table the PxP applies to the updates
+/// requested with `refresh::PxpFlags::use_cmap`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Colormap {
+    pub start: u32,
+    pub red: Vec<u16>,
+    pub green: Vec<u16>,
+    pub blue: Vec<u16>,
+}
+
+impl Colormap {
+    /// Maps every gray level to itself
+    pub fn identity() -> Colormap {
+        Colormap::from_tone_curve(&ToneCurve::identity())
+    }
+
+    /// Maps every gray level to the one `curve` gives for it, which lets the PxP apply
+    /// gamma and contrast adjustments to whole updates
+    pub fn from_tone_curve(curve: &ToneCurve) -> Colormap {
+        let entries: Vec<u16> = (0..COLORMAP_LEN)
+            .map(|v| u16::from(curve.apply(v as u8)) * 0x101)
+            .collect();
+        Colormap {
+            start: 0,
+            red: entries.clone(),
+            green: entries.clone(),
+            blue: entries,
+        }
+    }
+
+    /// Reduces the gray levels to `levels` evenly spaced ones, from black to white
+    pub fn posterize(levels: u8) -> Colormap {
+        let step = 255.0 / f32::from(levels.max(2) - 1);
+        Colormap::from_tone_curve(&ToneCurve::from_fn(|v| {
+            ((f32::from(v) / step).round() * step).round() as u8
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.red.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.red.is_empty()
+    }
+}
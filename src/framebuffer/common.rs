@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 #![allow(non_camel_case_types)]
+use framebuffer::gray;
 use framebuffer::mxcfb::*;
+use framebuffer::screeninfo::{Bitfield, VarScreeninfo};
 use std;
 
 /// This is to allow tests to run on systems with 64bit pointer types.
@@ -10,6 +12,8 @@ pub type NativeWidthType = u64;
 #[cfg(target_pointer_width = "32")]
 pub type NativeWidthType = u32;
 
+/// Geometry of the reMarkable. Prefer the `device::DeviceProfile` in use when
+/// the code has to work across tablet revisions.
 pub const DISPLAYWIDTH: u16 = 1404;
 pub const DISPLAYHEIGHT: u16 = 1872;
 
@@ -38,15 +42,21 @@ pub const FBIOPUTCMAP: NativeWidthType = 0x4605;
 pub const FBIOPAN_DISPLAY: NativeWidthType = 0x4606;
 pub const FBIO_CURSOR: NativeWidthType = 0x4608;
 
+/// A color to draw with. The panel only shows grays, so everything ends up drawn with its
+/// luminance (see `to_gray8`). `from_gray8` and `eink` are the natural ways to pick one.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum color {
     BLACK,
+    #[deprecated(note = "the panel can't show colors, use `RGB` or `from_gray8` instead")]
     RED,
+    #[deprecated(note = "the panel can't show colors, use `RGB` or `from_gray8` instead")]
     GREEN,
+    #[deprecated(note = "the panel can't show colors, use `RGB` or `from_gray8` instead")]
     BLUE,
     WHITE,
     NATIVE_COMPONENTS(u8, u8),
     RGB(u8, u8, u8),
+    /// A gray level where 0 is black and 255 white, like `RGB` and `from_gray8`
     GRAY(u8),
 }
 
@@ -60,6 +70,14 @@ impl color {
     }
 
     pub fn to_rgb8(self) -> [u8; 3] {
+        // No need to go through rgb565 and lose precision
+        match self {
+            color::WHITE => return [255; 3],
+            color::GRAY(level) => return [level; 3],
+            color::RGB(r8, g8, b8) => return [r8, g8, b8],
+            _ => {}
+        }
+
         // Components reversed because of the device
         let components = self.as_native();
 
@@ -73,7 +91,44 @@ impl color {
         [red, green, blue]
     }
 
+    /// Returns the luminance of the color, 0 being black and 255 white
+    pub fn to_gray8(self) -> u8 {
+        match self {
+            color::BLACK => 0x00,
+            color::WHITE => 0xFF,
+            color::GRAY(level) => level,
+            color::RGB(r8, g8, b8) => gray::luminance(r8, g8, b8),
+            _ => {
+                let rgb = self.to_rgb8();
+                gray::luminance(rgb[0], rgb[1], rgb[2])
+            }
+        }
+    }
+
+    /// The gray with the luminance `v`, 0 being black and 255 white
+    pub fn from_gray8(v: u8) -> color {
+        color::GRAY(v)
+    }
+
+    /// One of the levels of `gray::EINK_PALETTE`, 0 being black and 15 white
+    pub fn eink(level: u8) -> color {
+        color::from_gray8(gray::EINK_PALETTE[level.min(15) as usize])
+    }
+
+    /// Mixes `over` on top of `self` with an opacity of `alpha`, from 0.0 to 1.0
+    pub fn blend(self, over: color, alpha: f32) -> color {
+        let alpha = alpha.max(0.0).min(1.0);
+        let (below, above) = (self.to_rgb8(), over.to_rgb8());
+        let mix = |b: u8, a: u8| (f32::from(b) + (f32::from(a) - f32::from(b)) * alpha).round() as u8;
+        color::RGB(
+            mix(below[0], above[0]),
+            mix(below[1], above[1]),
+            mix(below[2], above[2]),
+        )
+    }
+
     #[inline]
+    #[allow(deprecated)]
     pub fn as_native(self) -> [u8; 2] {
         match self {
             color::BLACK => [0x00, 0x00],
@@ -81,7 +136,7 @@ impl color {
             color::GREEN => [0x00, 0x1F],
             color::BLUE => [0xF8, 0x00],
             color::WHITE => [0xFF, 0xFF],
-            color::GRAY(level) => color::rgb_to_native(255 - level, 255 - level, 255 - level),
+            color::GRAY(level) => color::rgb_to_native(level, level, level),
             color::NATIVE_COMPONENTS(c1, c2) => [c1, c2],
             color::RGB(r8, g8, b8) => color::rgb_to_native(r8, g8, b8)
         }
@@ -114,6 +169,132 @@ impl ::std::default::Default for color {
     }
 }
 
+/// Layout of the pixels in the framebuffer memory
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PixelFormat {
+    /// 16 bits per pixel, `rgb565_le`
+    Rgb565,
+    /// 8 bits per pixel, 0 being black and 255 white. The EPDC can skip the PxP
+    /// processing of the updates in this format.
+    Gray8,
+}
+
+impl PixelFormat {
+    /// Returns the format described by `var_screen_info`
+    pub fn from_screeninfo(var_screen_info: &VarScreeninfo) -> PixelFormat {
+        if var_screen_info.bits_per_pixel == 8 {
+            PixelFormat::Gray8
+        } else {
+            PixelFormat::Rgb565
+        }
+    }
+
+    #[inline]
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Gray8 => 1,
+        }
+    }
+
+    /// Updates the depth, grayscale flag and bitfields of `var_screen_info` to this format
+    pub fn apply(self, var_screen_info: &mut VarScreeninfo) {
+        let bitfield = |offset, length| Bitfield {
+            offset,
+            length,
+            msb_right: 0,
+        };
+        var_screen_info.bits_per_pixel = self.bytes_per_pixel() as u32 * 8;
+        match self {
+            PixelFormat::Rgb565 => {
+                var_screen_info.grayscale = 0;
+                var_screen_info.red = bitfield(11, 5);
+                var_screen_info.green = bitfield(5, 6);
+                var_screen_info.blue = bitfield(0, 5);
+            }
+            PixelFormat::Gray8 => {
+                var_screen_info.grayscale = 1;
+                var_screen_info.red = bitfield(0, 8);
+                var_screen_info.green = bitfield(0, 8);
+                var_screen_info.blue = bitfield(0, 8);
+            }
+        }
+    }
+
+    /// Decodes the pixel stored in the first `bytes_per_pixel()` bytes of `data`
+    #[inline]
+    pub fn decode(self, data: &[u8]) -> color {
+        match self {
+            PixelFormat::Rgb565 => color::NATIVE_COMPONENTS(data[0], data[1]),
+            PixelFormat::Gray8 => color::from_gray8(data[0]),
+        }
+    }
+
+    /// Appends the representation of `c` in this format to `out`
+    #[inline]
+    pub fn encode(self, c: color, out: &mut Vec<u8>) {
+        match self {
+            PixelFormat::Rgb565 => out.extend_from_slice(&c.as_native()),
+            PixelFormat::Gray8 => out.push(c.to_gray8()),
+        }
+    }
+}
+
+/// How the pixels written through `FramebufferIO` and `FramebufferDraw` are combined with
+/// the ones already in the framebuffer, see `core::Framebuffer::set_raster_op(..)`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RasterOp {
+    /// Replaces the existing pixel
+    Copy,
+    /// Flips the bits of the existing pixel that are set in the new one. Writing the same
+    /// content twice restores what was there, e.g. white inverts the area it covers.
+    Xor,
+    And,
+    Or,
+    /// Inverts the existing pixel, whatever the color written
+    Invert,
+    /// Multiplies the components of both pixels, which only ever darkens
+    Multiply,
+    /// Keeps the darkest components of both pixels, also known as darken
+    Min,
+    /// Keeps the lightest components of both pixels, also known as lighten
+    Max,
+}
+
+impl Default for RasterOp {
+    fn default() -> RasterOp {
+        RasterOp::Copy
+    }
+}
+
+impl RasterOp {
+    /// Combines the pixel `src` into `dest`, both encoded in `format`
+    #[inline]
+    pub fn apply(self, format: PixelFormat, dest: &mut [u8], src: &[u8]) {
+        let componentwise = |f: fn(u8, u8) -> u8, dest: &mut [u8]| match format {
+            PixelFormat::Gray8 => dest[0] = f(dest[0], src[0]),
+            PixelFormat::Rgb565 => {
+                let (d, s) = (format.decode(dest).to_rgb8(), format.decode(src).to_rgb8());
+                let combined = color::RGB(f(d[0], s[0]), f(d[1], s[1]), f(d[2], s[2]));
+                dest.copy_from_slice(&combined.as_native());
+            }
+        };
+        match self {
+            RasterOp::Copy => dest.copy_from_slice(src),
+            RasterOp::Xor => dest.iter_mut().zip(src).for_each(|(d, s)| *d ^= s),
+            RasterOp::And => dest.iter_mut().zip(src).for_each(|(d, s)| *d &= s),
+            RasterOp::Or => dest.iter_mut().zip(src).for_each(|(d, s)| *d |= s),
+            RasterOp::Invert => dest.iter_mut().for_each(|d| *d = !*d),
+            RasterOp::Multiply => componentwise(
+                |d, s| ((u16::from(d) * u16::from(s) + 127) / 255) as u8,
+                dest,
+            ),
+            RasterOp::Min => componentwise(::std::cmp::min, dest),
+            RasterOp::Max => componentwise(::std::cmp::max, dest),
+        }
+    }
+}
+
 ///
 /// If no processing required, skip update processing
 ///  No processing means:
@@ -182,6 +363,155 @@ impl mxcfb_rect {
         self.contains_point(rect.top, rect.left)
             && self.contains_point(rect.top + rect.height, rect.left + rect.width)
     }
+
+    /// Returns the area covered by both `self` and `rect`, which is empty (zero height
+    /// or width) if they don't overlap
+    pub fn intersection(&self, rect: &mxcfb_rect) -> mxcfb_rect {
+        let top = ::std::cmp::max(self.top, rect.top);
+        let left = ::std::cmp::max(self.left, rect.left);
+        let bottom = ::std::cmp::min(
+            self.top.saturating_add(self.height),
+            rect.top.saturating_add(rect.height),
+        );
+        let right = ::std::cmp::min(
+            self.left.saturating_add(self.width),
+            rect.left.saturating_add(rect.width),
+        );
+        mxcfb_rect {
+            top,
+            left,
+            height: bottom.saturating_sub(top),
+            width: right.saturating_sub(left),
+        }
+    }
+}
+
+/// Decides which parts of the area enclosed by an outline get filled
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FillRule {
+    /// A point is inside if the outline winds around it a non-zero number of times
+    NonZero,
+    /// A point is inside if a ray from it crosses the outline an odd number of times
+    EvenOdd,
+}
+
+impl ::std::default::Default for FillRule {
+    fn default() -> Self {
+        FillRule::NonZero
+    }
+}
+
+impl FillRule {
+    /// Tells whether a point with the winding number `winding` is inside
+    pub fn is_inside(self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding & 1 != 0,
+        }
+    }
+}
+
+/// Orientation of the content on the display, as a clockwise rotation from the
+/// native portrait orientation of the panel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Orientation {
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl ::std::default::Default for Orientation {
+    fn default() -> Self {
+        Orientation::Rotate0
+    }
+}
+
+impl Orientation {
+    pub fn is_landscape(self) -> bool {
+        self == Orientation::Rotate90 || self == Orientation::Rotate270
+    }
+
+    /// Returns the `(height, width)` of a display of `native_height` by `native_width`
+    /// pixels when seen in this orientation
+    pub fn dimensions(self, native_height: u32, native_width: u32) -> (u32, u32) {
+        if self.is_landscape() {
+            (native_width, native_height)
+        } else {
+            (native_height, native_width)
+        }
+    }
+
+    /// Maps `(y, x)` from this orientation onto the native display. Returns `(y, x)`.
+    pub fn to_native(self, y: u32, x: u32, native_height: u32, native_width: u32) -> (u32, u32) {
+        match self {
+            Orientation::Rotate0 => (y, x),
+            Orientation::Rotate90 => (x, native_width - 1 - y),
+            Orientation::Rotate180 => (native_height - 1 - y, native_width - 1 - x),
+            Orientation::Rotate270 => (native_height - 1 - x, y),
+        }
+    }
+
+    /// Maps `(y, x)` from the native display into this orientation. Returns `(y, x)`.
+    /// Positions past the far edges of the display end up on the opposite edges.
+    pub fn from_native(
+        self,
+        y: u32,
+        x: u32,
+        native_height: u32,
+        native_width: u32,
+    ) -> (u32, u32) {
+        let flip_y = || (native_height - 1).saturating_sub(y);
+        let flip_x = || (native_width - 1).saturating_sub(x);
+        match self {
+            Orientation::Rotate0 => (y, x),
+            Orientation::Rotate90 => (flip_x(), y),
+            Orientation::Rotate180 => (flip_y(), flip_x()),
+            Orientation::Rotate270 => (x, flip_y()),
+        }
+    }
+
+    /// Maps `rect` from this orientation onto the native display, clipping it to the
+    /// bounds of the display first
+    pub fn to_native_rect(
+        self,
+        rect: &mxcfb_rect,
+        native_height: u32,
+        native_width: u32,
+    ) -> mxcfb_rect {
+        let (height, width) = self.dimensions(native_height, native_width);
+        let top = std::cmp::min(rect.top, height);
+        let left = std::cmp::min(rect.left, width);
+        let bottom = std::cmp::min(rect.top.saturating_add(rect.height), height);
+        let right = std::cmp::min(rect.left.saturating_add(rect.width), width);
+        let (h, w) = (bottom - top, right - left);
+        match self {
+            Orientation::Rotate0 => mxcfb_rect {
+                top,
+                left,
+                height: h,
+                width: w,
+            },
+            Orientation::Rotate90 => mxcfb_rect {
+                top: left,
+                left: native_width - bottom,
+                height: w,
+                width: h,
+            },
+            Orientation::Rotate180 => mxcfb_rect {
+                top: native_height - bottom,
+                left: native_width - right,
+                height: h,
+                width: w,
+            },
+            Orientation::Rotate270 => mxcfb_rect {
+                top: native_height - right,
+                left: top,
+                height: w,
+                width: h,
+            },
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -1,148 +1,287 @@
 #![allow(dead_code)]
 
-use libc;
 use libc::ioctl;
-use mmap;
-use mmap::MemoryMap;
 
-use std::fs::{File, OpenOptions};
+use std::fs::File;
 use std::os::unix::io::AsRawFd;
 use std::sync::atomic::AtomicU32;
 
+use device::DeviceProfile;
+use error::{check_ioctl, Result};
 use framebuffer;
+use framebuffer::backend::DeviceBackend;
 use framebuffer::common::{
-    FBIOGET_FSCREENINFO, FBIOGET_VSCREENINFO, FBIOPUT_VSCREENINFO, MXCFB_DISABLE_EPDC_ACCESS,
-    MXCFB_ENABLE_EPDC_ACCESS, MXCFB_SET_AUTO_UPDATE_MODE, MXCFB_SET_UPDATE_SCHEME,
+    color, mxcfb_rect, Orientation, PixelFormat, RasterOp, FBIOGET_FSCREENINFO,
+    FBIOGET_VSCREENINFO, FBIOPUT_VSCREENINFO,
 };
+use framebuffer::cmap::{Colormap, COLORMAP_LEN};
+use framebuffer::gray::ToneCurve;
+use framebuffer::refresh::PxpFlags;
 use framebuffer::screeninfo::{FixScreeninfo, VarScreeninfo};
+use framebuffer::FramebufferBackend;
 
-use rusttype::{Font, FontCollection};
+use framebuffer::fonts::{FontHandle, FontRegistry};
+
+use rusttype::Font;
 
 /// Framebuffer struct containing the state (latest update marker etc.)
 /// along with the var/fix screeninfo structs.
 pub struct Framebuffer<'a> {
-    pub device: File,
-    pub frame: MemoryMap,
+    /// The display device the pixel memory and the refresh requests go to
+    pub backend: Box<dyn FramebufferBackend>,
     pub marker: AtomicU32,
-    pub default_font: Font<'a>,
+    /// The fonts `FramebufferDraw::draw_text` can render with
+    pub fonts: FontRegistry<'a>,
     /// Not updated as a result of calling `Framebuffer::put_var_screeninfo(..)`.
     /// It is your responsibility to update this when you call into that function
     /// like it has been done in `Framebuffer::new(..)`.
     pub var_screen_info: VarScreeninfo,
     pub fix_screen_info: FixScreeninfo,
+    /// Coordinates passed to `FramebufferIO`, `FramebufferDraw` and `FramebufferRefresh`
+    /// are in this orientation
+    orientation: Orientation,
+    /// Offset in bytes of the back buffer from the beginning of the framebuffer memory,
+    /// while drawing goes there (see `FramebufferBuffering`)
+    pub(crate) back_buffer: Option<usize>,
+    /// Areas drawing is restricted to, each one already intersected with the previous ones
+    clip_stack: Vec<mxcfb_rect>,
+    raster_op: RasterOp,
+    night_mode: bool,
+    tone_curve: ToneCurve,
+    text_background: Option<color>,
 }
 
 unsafe impl<'a> Send for Framebuffer<'a> {}
 unsafe impl<'a> Sync for Framebuffer<'a> {}
 
-impl<'a> framebuffer::FramebufferBase<'a> for Framebuffer<'a> {
-    fn new(path_to_device: &str) -> Framebuffer {
-        let device = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(path_to_device)
-            .unwrap();
-
-        let fix_screen_info = Framebuffer::get_fix_screeninfo(&device);
-        let mut var_screen_info = Framebuffer::get_var_screeninfo(&device);
-        var_screen_info.xres = 1872;
-        var_screen_info.yres = 1404;
-        var_screen_info.rotate = 1;
-        var_screen_info.width = 0xffff_ffff;
-        var_screen_info.height = 0xffff_ffff;
-        var_screen_info.pixclock = 160_000_000;
-        var_screen_info.left_margin = 32;
-        var_screen_info.right_margin = 326;
-        var_screen_info.upper_margin = 4;
-        var_screen_info.lower_margin = 12;
-        var_screen_info.hsync_len = 44;
-        var_screen_info.vsync_len = 1;
-        var_screen_info.sync = 0;
-        var_screen_info.vmode = 0; // FB_VMODE_NONINTERLACED
-        var_screen_info.accel_flags = 0;
-
-        Framebuffer::put_var_screeninfo(&device, &mut var_screen_info);
-
-        let frame_length = (fix_screen_info.line_length * var_screen_info.yres) as usize;
-        let mem_map = MemoryMap::new(
-            frame_length,
-            &[
-                mmap::MapOption::MapReadable,
-                mmap::MapOption::MapWritable,
-                mmap::MapOption::MapFd(device.as_raw_fd()),
-                mmap::MapOption::MapOffset(0),
-                mmap::MapOption::MapNonStandardFlags(libc::MAP_SHARED),
-            ],
-        ).unwrap();
-
-        // Load the font
-        let font_data = include_bytes!("../../assets/Roboto-Regular.ttf");
-        let collection = FontCollection::from_bytes(font_data as &[u8]);
+impl<'a> Framebuffer<'a> {
+    /// Creates a new instance of Framebuffer on top of an arbitrary `backend`, such as
+    /// a `backend::MemoryBackend` for running off-device.
+    pub fn from_backend(backend: Box<dyn FramebufferBackend>) -> Framebuffer<'a> {
+        let var_screen_info = backend.var_screeninfo();
+        let fix_screen_info = backend.fix_screeninfo();
+
         Framebuffer {
             marker: AtomicU32::new(1),
-            device,
-            frame: mem_map,
-            default_font: collection.into_font().unwrap(),
+            backend,
+            fonts: FontRegistry::new(),
             var_screen_info,
             fix_screen_info,
+            orientation: Orientation::Rotate0,
+            back_buffer: None,
+            clip_stack: Vec::new(),
+            raster_op: RasterOp::Copy,
+            night_mode: false,
+            tone_curve: ToneCurve::identity(),
+            text_background: Some(color::WHITE),
         }
     }
 
-    fn set_epdc_access(&mut self, state: bool) {
-        unsafe {
-            libc::ioctl(
-                self.device.as_raw_fd(),
-                if state {
-                    MXCFB_ENABLE_EPDC_ACCESS
-                } else {
-                    MXCFB_DISABLE_EPDC_ACCESS
-                },
-            );
-        };
+    /// Returns the built-in font, which `FontHandle::default()` refers to
+    #[deprecated(note = "the `default_font` field was replaced by `fonts`, use \
+                         `fonts.get(FontHandle::default())` instead")]
+    pub fn default_font(&self) -> &Font<'a> {
+        self.fonts.get(FontHandle::default())
     }
 
-    fn set_autoupdate_mode(&mut self, mode: u32) {
-        let m = mode.to_owned();
-        unsafe {
-            libc::ioctl(
-                self.device.as_raw_fd(),
-                MXCFB_SET_AUTO_UPDATE_MODE,
-                &m as *const u32,
-            );
-        };
+    /// Returns a pointer to the beginning of the frame that `FramebufferIO` and
+    /// `FramebufferDraw` currently operate on
+    pub fn draw_ptr(&self) -> *mut u8 {
+        let begin = self.backend.frame_ptr();
+        match self.back_buffer {
+            Some(offset) => unsafe { begin.add(offset) },
+            None => begin,
+        }
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Changes the orientation used for drawing and refreshing. Nothing already on the
+    /// display is redrawn.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::from_screeninfo(&self.var_screen_info)
+    }
+
+    /// Reconfigures the framebuffer to store its pixels in `format`. The contents of the
+    /// framebuffer memory aren't converted and the back buffer gets disabled.
+    pub fn set_pixel_format(&mut self, format: PixelFormat) -> Result<()> {
+        let mut var_screen_info = self.var_screen_info.clone();
+        format.apply(&mut var_screen_info);
+        self.backend.put_var_screeninfo(&var_screen_info)?;
+        self.var_screen_info = self.backend.var_screeninfo();
+        self.fix_screen_info = self.backend.fix_screeninfo();
+        self.back_buffer = None;
+        Ok(())
+    }
+
+    pub fn raster_op(&self) -> RasterOp {
+        self.raster_op
+    }
+
+    /// Changes how the pixels written from now on through `FramebufferIO` and `FramebufferDraw`
+    /// are combined with the existing ones, until the next call. `FramebufferIO::write_frame`,
+    /// `FramebufferIO::restore_region` and `FramebufferDraw::clear` always copy.
+    pub fn set_raster_op(&mut self, op: RasterOp) {
+        self.raster_op = op;
+    }
+
+    pub fn tone_curve(&self) -> &ToneCurve {
+        &self.tone_curve
+    }
+
+    /// Changes the curve `FramebufferDraw::draw_text`, `FramebufferDraw::draw_image` and
+    /// the UI elements pass their colors through, until the next call
+    pub fn set_tone_curve(&mut self, curve: ToneCurve) {
+        self.tone_curve = curve;
+    }
+
+    pub fn text_background(&self) -> Option<color> {
+        self.text_background
+    }
+
+    /// Changes what `FramebufferDraw::draw_text` fills the boxes of the glyphs with, until
+    /// the next call. The glyphs are antialiased against `background`, which replaces what
+    /// was underneath, so that new text can overwrite old text. With `None` only the glyphs
+    /// are blended onto the existing content. The default is white.
+    pub fn set_text_background(&mut self, background: Option<color>) {
+        self.text_background = background;
+    }
+
+    pub fn night_mode(&self) -> bool {
+        self.night_mode
+    }
+
+    /// Makes every refresh from now on show the display inverted, white on black, using
+    /// the EPDC. The framebuffer memory and what has already been refreshed are unchanged.
+    pub fn set_night_mode(&mut self, enabled: bool) {
+        self.night_mode = enabled;
+    }
+
+    /// Combines the `requested` PxP flags of a refresh with the night mode
+    pub(crate) fn pxp_flags(&self, requested: PxpFlags) -> PxpFlags {
+        PxpFlags {
+            invert: requested.invert != self.night_mode,
+            ..requested
+        }
+    }
+
+    /// Reads the whole framebuffer colormap
+    pub fn colormap(&self) -> Result<Colormap> {
+        self.backend.colormap(0, COLORMAP_LEN)
+    }
+
+    /// Installs `colormap` in the framebuffer. It only affects the refreshes requested with
+    /// `PxpFlags::use_cmap`, letting the PxP apply contrast curves or posterization without
+    /// touching the framebuffer memory.
+    pub fn set_colormap(&self, colormap: &Colormap) -> Result<()> {
+        self.backend.put_colormap(colormap)
+    }
+
+    /// Restricts everything drawn through `FramebufferIO::write_pixel` and `FramebufferDraw`
+    /// to `rect`, within the current clip rectangle, until the matching `pop_clip_rect()`.
+    /// The rectangle is in the current orientation and gets limited to the display.
+    pub fn push_clip_rect(&mut self, rect: mxcfb_rect) {
+        let clipped = self.drawable_rect().intersection(&rect);
+        self.clip_stack.push(clipped);
+    }
+
+    /// Restores the clip rectangle that was in effect before the last `push_clip_rect()`
+    /// and returns the one that was removed
+    pub fn pop_clip_rect(&mut self) -> Option<mxcfb_rect> {
+        self.clip_stack.pop()
+    }
+
+    /// The area drawing is currently restricted to, `None` if there is no restriction
+    pub fn clip_rect(&self) -> Option<mxcfb_rect> {
+        self.clip_stack.last().cloned()
     }
 
-    fn set_update_scheme(&mut self, scheme: u32) {
-        let s = scheme.to_owned();
-        unsafe {
-            libc::ioctl(
-                self.device.as_raw_fd(),
-                MXCFB_SET_UPDATE_SCHEME,
-                &s as *const u32,
-            );
+    /// The part of the display drawing can currently reach, within the clip rectangle
+    pub fn drawable_rect(&self) -> mxcfb_rect {
+        let (height, width) = self.dimensions();
+        let display = mxcfb_rect {
+            top: 0,
+            left: 0,
+            height,
+            width,
         };
+        match self.clip_stack.last() {
+            Some(clip) => display.intersection(clip),
+            None => display,
+        }
+    }
+
+    /// Tells whether the pixel at `(y, x)` lies on the display and within the clip rectangle
+    #[inline]
+    pub fn is_drawable(&self, y: usize, x: usize) -> bool {
+        let (h, w) = self.dimensions();
+        if y >= h as usize || x >= w as usize {
+            return false;
+        }
+        match self.clip_stack.last() {
+            Some(clip) => {
+                y >= clip.top as usize
+                    && y < (clip.top + clip.height) as usize
+                    && x >= clip.left as usize
+                    && x < (clip.left + clip.width) as usize
+            }
+            None => true,
+        }
     }
 
-    fn get_fix_screeninfo(device: &File) -> FixScreeninfo {
+    /// Returns the `(height, width)` of the display in the current orientation
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.orientation
+            .dimensions(self.var_screen_info.yres, self.var_screen_info.xres)
+    }
+
+    /// Opens the framebuffer device of `profile` and configures it accordingly
+    pub fn from_profile(profile: &DeviceProfile) -> Result<Framebuffer<'a>> {
+        let backend = DeviceBackend::open(&profile.framebuffer_path, profile)?;
+        Ok(Framebuffer::from_backend(box backend))
+    }
+}
+
+impl<'a> framebuffer::FramebufferBase<'a> for Framebuffer<'a> {
+    fn new(path_to_device: &str) -> Result<Framebuffer> {
+        let backend = DeviceBackend::open(path_to_device, &DeviceProfile::detect())?;
+        Ok(Framebuffer::from_backend(box backend))
+    }
+
+    fn set_epdc_access(&mut self, state: bool) -> Result<()> {
+        self.backend.set_epdc_access(state)
+    }
+
+    fn set_autoupdate_mode(&mut self, mode: u32) -> Result<()> {
+        self.backend.set_autoupdate_mode(mode)
+    }
+
+    fn set_update_scheme(&mut self, scheme: u32) -> Result<()> {
+        self.backend.set_update_scheme(scheme)
+    }
+
+    fn get_fix_screeninfo(device: &File) -> Result<FixScreeninfo> {
         let mut info: FixScreeninfo = Default::default();
         let result = unsafe { ioctl(device.as_raw_fd(), FBIOGET_FSCREENINFO, &mut info) };
-        if result != 0 {
-            panic!("FBIOGET_FSCREENINFO failed");
-        }
-        info
+        check_ioctl("FBIOGET_FSCREENINFO", result)?;
+        Ok(info)
     }
 
-    fn get_var_screeninfo(device: &File) -> VarScreeninfo {
+    fn get_var_screeninfo(device: &File) -> Result<VarScreeninfo> {
         let mut info: VarScreeninfo = Default::default();
         let result = unsafe { ioctl(device.as_raw_fd(), FBIOGET_VSCREENINFO, &mut info) };
-        if result != 0 {
-            panic!("FBIOGET_VSCREENINFO failed");
-        }
-        info
+        check_ioctl("FBIOGET_VSCREENINFO", result)?;
+        Ok(info)
     }
 
-    fn put_var_screeninfo(device: &File, var_screen_info: &mut VarScreeninfo) -> bool {
+    fn put_var_screeninfo(device: &File, var_screen_info: &mut VarScreeninfo) -> Result<()> {
         let result = unsafe { ioctl(device.as_raw_fd(), FBIOPUT_VSCREENINFO, var_screen_info) };
-        result == 0
+        check_ioctl("FBIOPUT_VSCREENINFO", result)
     }
 }
@@ -0,0 +1,128 @@
+/// Number of gray levels the output of a `Dither` is reduced to. Two levels suit the
+/// DU and A2 waveforms, four GL4 and sixteen GC16/GL16.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GrayLevels {
+    Two = 2,
+    Four = 4,
+    Sixteen = 16,
+}
+
+/// How `FramebufferDraw::draw_image` and `FramebufferDraw::fill_rect_dithered` reduce
+/// their content to the gray levels a waveform can display
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Dither {
+    /// Pixels are written as-is and left for the EPDC to quantize
+    None,
+    /// Error diffusion spreading all of the error, which preserves tones the best
+    FloydSteinberg(GrayLevels),
+    /// Error diffusion spreading 3/4 of the error, which keeps more contrast and
+    /// clean highlights and shadows
+    Atkinson(GrayLevels),
+    /// Ordered dithering with an 8x8 Bayer matrix. The pattern is aligned on the
+    /// framebuffer so that adjacent areas tile seamlessly.
+    Bayer(GrayLevels),
+}
+
+impl Default for Dither {
+    fn default() -> Dither {
+        Dither::None
+    }
+}
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Rounds `v` to the closest of `levels` evenly spaced gray levels
+#[inline]
+fn quantize(v: f32, levels: u8) -> u8 {
+    let step = 255.0 / f32::from(levels - 1);
+    ((v / step).round().max(0.0).min(f32::from(levels - 1)) * step).round() as u8
+}
+
+/// Spreads the quantization error of each pixel onto its unvisited neighbours,
+/// `kernel` listing their `(dx, dy)` offsets and share of the error
+fn diffuse(luma: &[u8], width: usize, height: usize, levels: u8, kernel: &[(i32, i32, f32)]) -> Vec<u8> {
+    let mut values: Vec<f32> = luma.iter().map(|&v| f32::from(v)).collect();
+    let mut output = vec![0u8; luma.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let quantized = quantize(values[index], levels);
+            let error = values[index] - f32::from(quantized);
+            output[index] = quantized;
+            for &(dx, dy, share) in kernel {
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if nx < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+                values[ny as usize * width + nx as usize] += error * share;
+            }
+        }
+    }
+    output
+}
+
+/// Reduces the `width` by `height` 8-bit luminance values in `luma` (0 being black) as
+/// described by `dither`. `(top, left)` is the position of the area on the framebuffer,
+/// which ordered dithering aligns its pattern on.
+pub fn dither_luma(
+    luma: &[u8],
+    width: usize,
+    height: usize,
+    top: usize,
+    left: usize,
+    dither: Dither,
+) -> Vec<u8> {
+    match dither {
+        Dither::None => luma.to_vec(),
+        Dither::FloydSteinberg(levels) => diffuse(
+            luma,
+            width,
+            height,
+            levels as u8,
+            &[
+                (1, 0, 7.0 / 16.0),
+                (-1, 1, 3.0 / 16.0),
+                (0, 1, 5.0 / 16.0),
+                (1, 1, 1.0 / 16.0),
+            ],
+        ),
+        Dither::Atkinson(levels) => diffuse(
+            luma,
+            width,
+            height,
+            levels as u8,
+            &[
+                (1, 0, 1.0 / 8.0),
+                (2, 0, 1.0 / 8.0),
+                (-1, 1, 1.0 / 8.0),
+                (0, 1, 1.0 / 8.0),
+                (1, 1, 1.0 / 8.0),
+                (0, 2, 1.0 / 8.0),
+            ],
+        ),
+        Dither::Bayer(levels) => {
+            let levels = levels as u8;
+            let step = 255.0 / f32::from(levels - 1);
+            luma.iter()
+                .enumerate()
+                .map(|(index, &v)| {
+                    let (y, x) = (top + index / width, left + index % width);
+                    let threshold = (f32::from(BAYER_8X8[y % 8][x % 8]) + 0.5) / 64.0;
+                    let level = (f32::from(v) / step + threshold - 0.5)
+                        .round()
+                        .max(0.0)
+                        .min(f32::from(levels - 1));
+                    (level * step).round() as u8
+                }).collect()
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std;
 
-use image::RgbImage;
+use image::{DynamicImage, RgbImage};
 use libc;
 use line_drawing;
 use rusttype::{point, Scale};
@@ -8,6 +8,17 @@ use rusttype::{point, Scale};
 use framebuffer;
 use framebuffer::common::*;
 use framebuffer::core;
+use framebuffer::dither::{self, Dither};
+use framebuffer::fill::FillStyle;
+use framebuffer::fonts::FontHandle;
+use framebuffer::gray;
+use framebuffer::imaging::{self, ImageFit, ScaleFilter};
+use framebuffer::ink::{self, StrokePoint};
+use framebuffer::path::Path;
+use framebuffer::raster::{self, CoverageMask, EdgeTable};
+use framebuffer::stroke::{self, LineCap, StrokeStyle};
+use framebuffer::svg::SvgImage;
+use framebuffer::text;
 use framebuffer::FramebufferIO;
 
 macro_rules! min {
@@ -44,15 +55,327 @@ fn sample_bezier(startpt: (f32, f32), ctrlpt: (f32, f32), endpt: (f32, f32)) ->
     points
 }
 
-impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
-    fn draw_image(&mut self, img: &RgbImage, top: usize, left: usize) -> mxcfb_rect {
-        for (x, y, pixel) in img.enumerate_pixels() {
-            self.write_pixel(
-                top + y as usize,
-                left + x as usize,
-                color::RGB(pixel.data[0], pixel.data[1], pixel.data[2]),
-            );
+/// Returns the smallest rectangle containing both `a` and `b`
+fn union_rect(a: mxcfb_rect, b: mxcfb_rect) -> mxcfb_rect {
+    let top = min!(a.top, b.top);
+    let left = min!(a.left, b.left);
+    let bottom = max!(a.top + a.height, b.top + b.height);
+    let right = max!(a.left + a.width, b.left + b.width);
+    mxcfb_rect {
+        top,
+        left,
+        height: bottom - top,
+        width: right - left,
+    }
+}
+
+/// Half width of the row `dy` rows away from the center of the ellipse with radii
+/// `rad_y` and `rad_x`, `None` if the row is outside of it
+fn ellipse_half_width(dy: i32, rad_y: i32, rad_x: i32) -> Option<i32> {
+    if rad_y < 0 || rad_x < 0 || dy.abs() > rad_y {
+        return None;
+    }
+    if rad_y == 0 {
+        return Some(rad_x);
+    }
+    let t = dy as f32 / rad_y as f32;
+    Some((rad_x as f32 * (1.0 - t * t).sqrt()).floor() as i32)
+}
+
+/// First and last columns of the row `row` of a rectangle of `height` and `width`
+/// whose corners are rounded with `radius`
+fn rounded_rect_span(row: i32, height: i32, width: i32, radius: f32) -> (i32, i32) {
+    let center = row as f32 + 0.5;
+    let distance = if center < radius {
+        radius - center
+    } else if center > height as f32 - radius {
+        center - (height as f32 - radius)
+    } else {
+        0.0
+    };
+    let inset = radius - (radius * radius - distance * distance).max(0.0).sqrt();
+    let first = max!((inset - 0.5).ceil() as i32, 0);
+    (first, width - 1 - first)
+}
+
+/// Offset moving the centerline of a stroke `width` pixels wide onto the pixel grid.
+/// Even widths can't be centered on a pixel, they extend further up and left.
+fn stroke_alignment(width: f32) -> f32 {
+    if width.round() as i32 % 2 == 0 {
+        -0.5
+    } else {
+        0.0
+    }
+}
+
+/// Normalizes an angle in degrees to `[0, 360)`
+fn normalize_degrees(angle: f32) -> f32 {
+    ((angle % 360.0) + 360.0) % 360.0
+}
+
+/// The part of a circle going clockwise from `start` over `sweep` degrees
+#[derive(Copy, Clone)]
+struct Sector {
+    start: f32,
+    sweep: f32,
+}
+
+impl Sector {
+    fn new(start_angle: f32, end_angle: f32) -> Sector {
+        let sweep = if end_angle - start_angle >= 360.0 {
+            360.0
+        } else {
+            normalize_degrees(end_angle - start_angle)
+        };
+        Sector {
+            start: normalize_degrees(start_angle),
+            sweep,
+        }
+    }
+
+    /// Tells whether the pixel `(dy, dx)` away from the center is in the sector. The center
+    /// itself is the apex of every sector.
+    fn contains(self, dy: i32, dx: i32) -> bool {
+        if dy == 0 && dx == 0 {
+            return true;
+        }
+        let angle = (dy as f32).atan2(dx as f32).to_degrees();
+        normalize_degrees(angle - self.start) <= self.sweep
+    }
+}
+
+/// Tracks the region covered by the spans written by scanline fills
+struct SpanBounds {
+    top: i32,
+    left: i32,
+    bottom: i32,
+    right: i32,
+}
+
+impl SpanBounds {
+    fn new() -> SpanBounds {
+        SpanBounds {
+            top: i32::max_value(),
+            left: i32::max_value(),
+            bottom: i32::min_value(),
+            right: i32::min_value(),
+        }
+    }
+
+    fn add(&mut self, y: i32, x0: i32, x1: i32) {
+        self.top = min!(self.top, y);
+        self.bottom = max!(self.bottom, y);
+        self.left = min!(self.left, x0);
+        self.right = max!(self.right, x1);
+    }
+
+    fn rect(&self) -> mxcfb_rect {
+        if self.bottom < self.top {
+            return mxcfb_rect::invalid();
+        }
+        mxcfb_rect {
+            top: self.top as u32,
+            left: self.left as u32,
+            height: (self.bottom - self.top + 1) as u32,
+            width: (self.right - self.left + 1) as u32,
+        }
+    }
+}
+
+impl<'a> core::Framebuffer<'a> {
+    /// Clips the span of pixels from `x0` to `x1` (inclusive) on row `y` to the framebuffer
+    /// and the clip rectangle. Returns `None` when nothing is left.
+    fn clip_span(&self, y: i32, x0: i32, x1: i32) -> Option<(i32, i32)> {
+        let area = self.drawable_rect();
+        let (x0, x1) = (
+            max!(x0, area.left as i32),
+            min!(x1, (area.left + area.width) as i32 - 1),
+        );
+        if y < area.top as i32 || y >= (area.top + area.height) as i32 || x0 > x1 {
+            return None;
+        }
+        Some((x0, x1))
+    }
+
+    /// Fills the pixels from `x0` to `x1` (inclusive) on row `y`, clipped to the framebuffer
+    /// and the clip rectangle
+    fn fill_span(&mut self, y: i32, x0: i32, x1: i32, v: color, bounds: &mut SpanBounds) {
+        if let Some((x0, x1)) = self.clip_span(y, x0, x1) {
+            self.fill_row(y as usize, x0 as usize, (x1 - x0 + 1) as usize, v);
+            bounds.add(y, x0, x1);
+        }
+    }
+
+    /// Paints the `(y, x0, x1)` spans of pixels with `style`, clipped like `fill_span`
+    fn paint_spans(&mut self, spans: &[(i32, i32, i32)], style: &FillStyle) -> mxcfb_rect {
+        let mut bounds = SpanBounds::new();
+        if let FillStyle::Solid(v) = *style {
+            for &(y, x0, x1) in spans {
+                self.fill_span(y, x0, x1, v, &mut bounds);
+            }
+            return bounds.rect();
+        }
+
+        let spans: Vec<(i32, i32, i32)> = spans
+            .iter()
+            .filter_map(|&(y, x0, x1)| self.clip_span(y, x0, x1).map(|(x0, x1)| (y, x0, x1)))
+            .collect();
+        for &(y, x0, x1) in &spans {
+            bounds.add(y, x0, x1);
+        }
+        let area = bounds.rect();
+        if area.width == 0 {
+            return area;
+        }
+        let (top, left, width) = (area.top as i32, area.left as i32, area.width as usize);
+        let colors = style.render(area.top as usize, area.left as usize, area.height as usize, width);
+        for &(y, x0, x1) in &spans {
+            let row = (y - top) as usize * width;
+            let mut x = x0;
+            // Writes each run of painted pixels at once
+            while x <= x1 {
+                let start = x;
+                let mut run = Vec::new();
+                while x <= x1 {
+                    match colors[row + (x - left) as usize] {
+                        Some(c) => run.push(c),
+                        None => break,
+                    }
+                    x += 1;
+                }
+                if !run.is_empty() {
+                    self.write_color_rows(y as usize, start as usize, run.len(), &run);
+                }
+                x += 1;
+            }
+        }
+        area
+    }
+
+    /// Paints the union of `polygons` according to `rule` with `style`, without
+    /// anti-aliasing. A pixel is painted if its center is inside.
+    fn paint_polygons(
+        &mut self,
+        polygons: &[Vec<(f32, f32)>],
+        rule: FillRule,
+        style: &FillStyle,
+    ) -> mxcfb_rect {
+        let edges = EdgeTable::new(polygons);
+        let (top, bottom) = match edges.vertical_bounds() {
+            Some(bounds) => bounds,
+            None => return mxcfb_rect::invalid(),
+        };
+        // Rows outside of the drawable area would all be clipped away
+        let area = self.drawable_rect();
+        let first = top.ceil().max(area.top as f32) as i32;
+        let end = bottom.ceil().min((area.top + area.height) as f32) as i32;
+        let mut spans = Vec::new();
+        for y in first..end {
+            for (x0, x1) in edges.spans(y as f32, rule) {
+                // Pixels whose center lies in [x0, x1)
+                spans.push((y, x0.ceil() as i32, x1.ceil() as i32 - 1));
+            }
+        }
+        self.paint_spans(&spans, style)
+    }
+
+    /// Fills `polygons` with anti-aliased edges according to `rule`
+    fn fill_polygons_antialiased(
+        &mut self,
+        polygons: &[Vec<(f32, f32)>],
+        rule: FillRule,
+        v: color,
+    ) -> mxcfb_rect {
+        let all_points: Vec<(f32, f32)> = polygons.iter().flat_map(|p| p.clone()).collect();
+        if all_points.is_empty() {
+            return mxcfb_rect::invalid();
+        }
+
+        let mut mask = CoverageMask::around(&all_points, 0.0, &self.drawable_rect());
+        mask.fill_polygons(polygons, rule);
+        mask.composite(self, v)
+    }
+
+    /// Writes `colors`, rows of `width` pixels, from `(top, left)` by encoding each row
+    /// into the pixel format of the framebuffer at once
+    fn write_color_rows(&mut self, top: usize, left: usize, width: usize, colors: &[color]) {
+        if width == 0 {
+            return;
+        }
+        let format = self.pixel_format();
+        let mut row = Vec::with_capacity(width * format.bytes_per_pixel());
+        for (i, pixels) in colors.chunks(width).enumerate() {
+            row.clear();
+            for &c in pixels {
+                format.encode(c, &mut row);
+            }
+            self.write_row(top + i, left, &row);
+        }
+    }
+
+    /// Fills the pixels of the ellipse with the radii `outer` (vertical, horizontal) centered
+    /// at `(cy, cx)` that aren't part of the ellipse with the radii `inner`, keeping only
+    /// those within `sector` if there is one
+    fn fill_ellipse_region(
+        &mut self,
+        cy: i32,
+        cx: i32,
+        outer: (i32, i32),
+        inner: Option<(i32, i32)>,
+        sector: Option<Sector>,
+        v: color,
+        bounds: &mut SpanBounds,
+    ) {
+        for dy in -outer.0..=outer.0 {
+            let outer_half = match ellipse_half_width(dy, outer.0, outer.1) {
+                Some(half) => half,
+                None => continue,
+            };
+            let inner_half = inner.and_then(|(ry, rx)| ellipse_half_width(dy, ry, rx));
+            let spans = match inner_half {
+                Some(half) => vec![(-outer_half, -half - 1), (half + 1, outer_half)],
+                None => vec![(-outer_half, outer_half)],
+            };
+            for (first, last) in spans {
+                let sector = match sector {
+                    Some(sector) => sector,
+                    None => {
+                        self.fill_span(cy + dy, cx + first, cx + last, v, bounds);
+                        continue;
+                    }
+                };
+                // Fill the runs of consecutive pixels within the sector
+                let mut run_start = None;
+                for dx in first..=last + 1 {
+                    let inside = dx <= last && sector.contains(dy, dx);
+                    match (run_start, inside) {
+                        (None, true) => run_start = Some(dx),
+                        (Some(start), false) => {
+                            self.fill_span(cy + dy, cx + start, cx + dx - 1, v, bounds);
+                            run_start = None;
+                        }
+                        _ => {}
+                    }
+                }
+            }
         }
+    }
+}
+
+impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
+    fn draw_image(&mut self, img: &RgbImage, top: usize, left: usize, dither: Dither) -> mxcfb_rect {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let luma: Vec<u8> = img
+            .pixels()
+            .map(|p| {
+                self.tone_curve()
+                    .apply(gray::luminance(p.data[0], p.data[1], p.data[2]))
+            }).collect();
+        let colors: Vec<color> = dither::dither_luma(&luma, width, height, top, left, dither)
+            .into_iter()
+            .map(color::from_gray8)
+            .collect();
+        self.write_color_rows(top, left, width, &colors);
         mxcfb_rect {
             top: top as u32,
             left: left as u32,
@@ -61,6 +384,71 @@ impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
         }
     }
 
+    fn draw_image_scaled(
+        &mut self,
+        img: &DynamicImage,
+        dest: mxcfb_rect,
+        filter: ScaleFilter,
+        fit: ImageFit,
+        dither: Dither,
+    ) -> mxcfb_rect {
+        let (scaled, placement) = imaging::scale_to_rect(img, &dest, filter, fit);
+        let (top, left) = (placement.top as usize, placement.left as usize);
+        let (width, height) = (placement.width as usize, placement.height as usize);
+
+        // Blend everything first so that dithering sees the composited result
+        let mut blended: Vec<Option<color>> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let p = scaled.get_pixel(x as u32, y as u32).data;
+                let source = self.tone_curve().map(color::RGB(p[0], p[1], p[2]));
+                // Pixels that can't be drawn have nothing to blend with either
+                let drawable = self.is_drawable(top + y, left + x);
+                blended.push(match p[3] {
+                    _ if !drawable => None,
+                    0 => None,
+                    255 => Some(source),
+                    alpha => Some(
+                        self.read_pixel(top + y, left + x)
+                            .blend(source, f32::from(alpha) / 255.0),
+                    ),
+                });
+            }
+        }
+
+        if dither == Dither::None {
+            for (i, c) in blended.iter().enumerate() {
+                if let Some(c) = *c {
+                    self.write_pixel(top + i / width, left + i % width, c);
+                }
+            }
+        } else {
+            // Fully transparent pixels still take part so that the error diffuses evenly
+            let luma: Vec<u8> = blended
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    let (y, x) = (top + i / width, left + i % width);
+                    match *c {
+                        Some(c) => c.to_gray8(),
+                        None if self.is_drawable(y, x) => self.read_pixel(y, x).to_gray8(),
+                        None => 0xFF,
+                    }
+                }).collect();
+            let dithered = dither::dither_luma(&luma, width, height, top, left, dither);
+            for (i, &v) in dithered.iter().enumerate() {
+                if blended[i].is_some() {
+                    self.write_pixel(top + i / width, left + i % width, color::from_gray8(v));
+                }
+            }
+        }
+        placement
+    }
+
+    fn draw_svg(&mut self, svg: &SvgImage, dest: mxcfb_rect) -> mxcfb_rect {
+        svg.render(self, &dest)
+    }
+
     fn draw_line(
         &mut self,
         y0: i32,
@@ -70,6 +458,20 @@ impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
         width: usize,
         v: color,
     ) -> mxcfb_rect {
+        if width > 1 {
+            let shift = stroke_alignment(width as f32);
+            let points = [
+                (x0 as f32 + shift, y0 as f32 + shift),
+                (x1 as f32 + shift, y1 as f32 + shift),
+            ];
+            let style = StrokeStyle {
+                cap: LineCap::Square,
+                ..StrokeStyle::new(width as f32)
+            };
+            let polygons = stroke::outline(&points, false, &style);
+            return self.paint_polygons(&polygons, FillRule::NonZero, &FillStyle::Solid(v));
+        }
+
         // Create local variables for moving start point
         let mut x0 = x0;
         let mut y0 = y0;
@@ -146,17 +548,90 @@ impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
     }
 
     fn fill_circle(&mut self, y: usize, x: usize, rad: usize, v: color) -> mxcfb_rect {
-        for current in { 1..rad + 1 } {
-            for (x, y) in line_drawing::BresenhamCircle::new(x as i32, y as i32, current as i32) {
-                self.write_pixel(y as usize, x as usize, v);
-            }
-        }
-        mxcfb_rect {
-            top: y as u32 - rad as u32,
-            left: x as u32 - rad as u32,
-            width: 2 * rad as u32,
-            height: 2 * rad as u32,
-        }
+        self.fill_ellipse(y, x, rad, rad, v)
+    }
+
+    fn fill_ellipse(&mut self, y: usize, x: usize, rad_y: usize, rad_x: usize, v: color) -> mxcfb_rect {
+        let mut bounds = SpanBounds::new();
+        self.fill_ellipse_region(
+            y as i32,
+            x as i32,
+            (rad_y as i32, rad_x as i32),
+            None,
+            None,
+            v,
+            &mut bounds,
+        );
+        bounds.rect()
+    }
+
+    fn draw_ellipse(
+        &mut self,
+        y: usize,
+        x: usize,
+        rad_y: usize,
+        rad_x: usize,
+        border_px: usize,
+        v: color,
+    ) -> mxcfb_rect {
+        let (ry, rx, border) = (rad_y as i32, rad_x as i32, border_px as i32);
+        let mut bounds = SpanBounds::new();
+        self.fill_ellipse_region(
+            y as i32,
+            x as i32,
+            (ry, rx),
+            Some((ry - border, rx - border)),
+            None,
+            v,
+            &mut bounds,
+        );
+        bounds.rect()
+    }
+
+    fn draw_arc(
+        &mut self,
+        y: usize,
+        x: usize,
+        rad: usize,
+        start_angle: f32,
+        end_angle: f32,
+        border_px: usize,
+        v: color,
+    ) -> mxcfb_rect {
+        let (rad, border) = (rad as i32, border_px as i32);
+        let mut bounds = SpanBounds::new();
+        self.fill_ellipse_region(
+            y as i32,
+            x as i32,
+            (rad, rad),
+            Some((rad - border, rad - border)),
+            Some(Sector::new(start_angle, end_angle)),
+            v,
+            &mut bounds,
+        );
+        bounds.rect()
+    }
+
+    fn fill_pie(
+        &mut self,
+        y: usize,
+        x: usize,
+        rad: usize,
+        start_angle: f32,
+        end_angle: f32,
+        v: color,
+    ) -> mxcfb_rect {
+        let mut bounds = SpanBounds::new();
+        self.fill_ellipse_region(
+            y as i32,
+            x as i32,
+            (rad as i32, rad as i32),
+            None,
+            Some(Sector::new(start_angle, end_angle)),
+            v,
+            &mut bounds,
+        );
+        bounds.rect()
     }
 
     fn draw_bezier(
@@ -197,11 +672,114 @@ impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
         }
     }
 
+    fn draw_line_antialiased(
+        &mut self,
+        y0: f32,
+        x0: f32,
+        y1: f32,
+        x1: f32,
+        width: f32,
+        v: color,
+    ) -> mxcfb_rect {
+        let points = [(x0, y0), (x1, y1)];
+        let mut mask = CoverageMask::around(&points, width, &self.drawable_rect());
+        mask.stroke_polyline(&points, width);
+        mask.composite(self, v)
+    }
+
+    fn draw_bezier_antialiased(
+        &mut self,
+        startpt: (f32, f32),
+        ctrlpt: (f32, f32),
+        endpt: (f32, f32),
+        width: f32,
+        v: color,
+    ) -> mxcfb_rect {
+        let points = raster::flatten_quadratic(startpt, ctrlpt, endpt);
+        let mut mask = CoverageMask::around(&points, width, &self.drawable_rect());
+        mask.stroke_polyline(&points, width);
+        mask.composite(self, v)
+    }
+
+    fn stroke_path(&mut self, path: &Path, width: f32, v: color) -> mxcfb_rect {
+        let polylines = path.flatten();
+        let all_points: Vec<(f32, f32)> = polylines.iter().flat_map(|p| p.points.clone()).collect();
+        if all_points.is_empty() {
+            return mxcfb_rect::invalid();
+        }
+
+        let mut mask = CoverageMask::around(&all_points, width, &self.drawable_rect());
+        for polyline in &polylines {
+            let mut points = polyline.points.clone();
+            if polyline.closed {
+                points.push(points[0]);
+            }
+            mask.stroke_polyline(&points, width);
+        }
+        mask.composite(self, v)
+    }
+
+    fn draw_ink_stroke(&mut self, points: &[StrokePoint], v: color) -> mxcfb_rect {
+        let polygons = ink::outline(&ink::smooth(points));
+        self.fill_polygons_antialiased(&polygons, FillRule::NonZero, v)
+    }
+
+    fn draw_polyline(
+        &mut self,
+        points: &[(f32, f32)],
+        closed: bool,
+        style: &StrokeStyle,
+        v: color,
+    ) -> mxcfb_rect {
+        let polygons = stroke::outline(points, closed, style);
+        self.fill_polygons_antialiased(&polygons, FillRule::NonZero, v)
+    }
+
+    fn stroke_path_styled(&mut self, path: &Path, style: &StrokeStyle, v: color) -> mxcfb_rect {
+        let polygons: Vec<Vec<(f32, f32)>> = path
+            .flatten()
+            .iter()
+            .flat_map(|polyline| stroke::outline(&polyline.points, polyline.closed, style))
+            .collect();
+        self.fill_polygons_antialiased(&polygons, FillRule::NonZero, v)
+    }
+
+    fn fill_path(&mut self, path: &Path, rule: FillRule, v: color) -> mxcfb_rect {
+        let polygons: Vec<Vec<(f32, f32)>> = path
+            .flatten()
+            .into_iter()
+            .map(|p| p.points)
+            .filter(|points| points.len() > 2)
+            .collect();
+        let all_points: Vec<(f32, f32)> = polygons.iter().flat_map(|p| p.clone()).collect();
+        if all_points.is_empty() {
+            return mxcfb_rect::invalid();
+        }
+
+        let mut mask = CoverageMask::around(&all_points, 0.0, &self.drawable_rect());
+        mask.fill_polygons(&polygons, rule);
+        mask.composite(self, v)
+    }
+
+    fn fill_polygon(&mut self, points: &[(f32, f32)], rule: FillRule, v: color) -> mxcfb_rect {
+        self.paint_polygons(&[points.to_vec()], rule, &FillStyle::Solid(v))
+    }
+
+    fn fill_polygon_styled(
+        &mut self,
+        points: &[(f32, f32)],
+        rule: FillRule,
+        style: &FillStyle,
+    ) -> mxcfb_rect {
+        self.paint_polygons(&[points.to_vec()], rule, style)
+    }
+
     fn draw_text(
         &mut self,
         y: usize,
         x: usize,
         text: String,
+        font: FontHandle,
         size: usize,
         col: color,
         dryrun: bool,
@@ -214,17 +792,15 @@ impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
         // The starting positioning of the glyphs (top left corner)
         let start = point(x as f32, y as f32);
 
-        let dfont = &mut self.default_font.clone();
+        let dfont = &mut self.fonts.get(font).clone();
 
         let mut min_y = y;
         let mut max_y = y;
         let mut min_x = x;
         let mut max_x = x;
 
-        let components = col.to_rgb8();
-        let c1 = f32::from(255 - components[0]);
-        let c2 = f32::from(255 - components[1]);
-        let c3 = f32::from(255 - components[2]);
+        let col = self.tone_curve().map(col);
+        let background = self.text_background().map(|c| self.tone_curve().map(c));
 
         // Loop through the glyphs in the text, positing each one on a line
         for glyph in dfont.layout(&text, scale, start) {
@@ -251,13 +827,23 @@ impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
                     continue;
                 }
 
+                // Coverage blends the text onto its background, or onto what is underneath
+                // when there is none
                 glyph.draw(|x, y, v| {
-                    let mult = (1.0 - v).min(1.0);
-                    self.write_pixel(
+                    let (y, x) = (
                         (y + bounding_box.min.y as u32) as usize,
                         (x + bounding_box.min.x as u32) as usize,
-                        color::RGB((c1 * mult) as u8, (c2 * mult) as u8, (c3 * mult) as u8),
-                    )
+                    );
+                    if !self.is_drawable(y, x) {
+                        return;
+                    }
+                    let below = match background {
+                        Some(c) => c,
+                        None if v <= 0.0 => return,
+                        None => self.read_pixel(y, x),
+                    };
+                    let blended = below.blend(col, v);
+                    self.write_pixel(y, x, color::from_gray8(blended.to_gray8()))
                 });
             }
         }
@@ -270,6 +856,52 @@ impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
         }
     }
 
+    fn draw_paragraph(
+        &mut self,
+        rect: mxcfb_rect,
+        text: &str,
+        style: &text::ParagraphStyle,
+        dryrun: bool,
+    ) -> text::TextLayout {
+        let mut layout = text::layout_text(&self.fonts, text, rect, style);
+        let font = self.fonts.get(style.font).clone();
+        let scale = Scale::uniform(style.size as f32);
+
+        let mut drawn: Option<mxcfb_rect> = None;
+        for line in &layout.lines {
+            if line.text.is_empty() {
+                continue;
+            }
+            // Justified lines are drawn word by word to honor the stretched spacing
+            let words: Vec<&str> = if style.alignment == text::TextAlignment::Justify {
+                line.text.split(' ').collect()
+            } else {
+                vec![line.text.as_str()]
+            };
+            let mut x = line.left as f32;
+            for word in words {
+                let area = self.draw_text(
+                    line.baseline as usize,
+                    x as usize,
+                    word.to_owned(),
+                    style.font,
+                    style.size,
+                    style.color,
+                    dryrun,
+                );
+                drawn = Some(match drawn {
+                    Some(d) => union_rect(d, area),
+                    None => area,
+                });
+                x += text::advance_width(&font, scale, word) + line.word_spacing;
+            }
+        }
+        if let Some(d) = drawn {
+            layout.rect = d;
+        }
+        layout
+    }
+
     fn draw_rect(
         &mut self,
         y: usize,
@@ -279,61 +911,141 @@ impl<'a> framebuffer::FramebufferDraw for core::Framebuffer<'a> {
         border_px: usize,
         c: color,
     ) {
-        // top horizontal
-        self.draw_line(
-            y as i32,
-            x as i32,
-            y as i32,
-            (x + width) as i32,
-            border_px,
-            c,
-        );
+        self.draw_rect_styled(y, x, height, width, &StrokeStyle::new(border_px as f32), c);
+    }
 
-        // left vertical
-        self.draw_line(
-            y as i32,
-            x as i32,
-            (y + height) as i32,
-            x as i32,
-            border_px,
-            c,
-        );
+    fn draw_rect_styled(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        style: &StrokeStyle,
+        c: color,
+    ) -> mxcfb_rect {
+        let shift = stroke_alignment(style.width);
+        let (top, left) = (y as f32 + shift, x as f32 + shift);
+        let (bottom, right) = (top + height as f32, left + width as f32);
+        let corners = [(left, top), (right, top), (right, bottom), (left, bottom)];
+        let polygons = stroke::outline(&corners, true, style);
+        self.paint_polygons(&polygons, FillRule::NonZero, &FillStyle::Solid(c))
+    }
 
-        // bottom horizontal
-        self.draw_line(
-            (y + height) as i32,
-            x as i32,
-            (y + height) as i32,
-            (x + width) as i32,
-            border_px,
-            c,
-        );
+    fn draw_rounded_rect(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        radius: usize,
+        border_px: usize,
+        c: color,
+    ) -> mxcfb_rect {
+        let (top, left) = (y as i32, x as i32);
+        let (height, width, border) = (height as i32, width as i32, border_px as i32);
+        let radius = (radius as f32).min(height as f32 / 2.0).min(width as f32 / 2.0);
+        let (inner_height, inner_width) = (height - 2 * border, width - 2 * border);
+        let inner_radius = (radius - border as f32).max(0.0);
 
-        // right vertical
-        self.draw_line(
-            y as i32,
-            (x + width) as i32,
-            (y + height) as i32,
-            (x + width) as i32,
-            border_px,
-            c,
-        );
+        let mut bounds = SpanBounds::new();
+        for row in 0..height {
+            let (first, last) = rounded_rect_span(row, height, width, radius);
+            let inner_row = row - border;
+            if inner_height <= 0 || inner_width <= 0 || inner_row < 0 || inner_row >= inner_height {
+                self.fill_span(top + row, left + first, left + last, c, &mut bounds);
+                continue;
+            }
+            let (inner_first, inner_last) =
+                rounded_rect_span(inner_row, inner_height, inner_width, inner_radius);
+            self.fill_span(top + row, left + first, left + border + inner_first - 1, c, &mut bounds);
+            self.fill_span(top + row, left + border + inner_last + 1, left + last, c, &mut bounds);
+        }
+        bounds.rect()
+    }
+
+    fn fill_rounded_rect(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        radius: usize,
+        c: color,
+    ) -> mxcfb_rect {
+        let (height, width) = (height as i32, width as i32);
+        let radius = (radius as f32).min(height as f32 / 2.0).min(width as f32 / 2.0);
+        let mut bounds = SpanBounds::new();
+        for row in 0..height {
+            let (first, last) = rounded_rect_span(row, height, width, radius);
+            self.fill_span(y as i32 + row, x as i32 + first, x as i32 + last, c, &mut bounds);
+        }
+        bounds.rect()
+    }
+
+    fn fill_rect_dithered(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        c: color,
+        dither: Dither,
+    ) -> mxcfb_rect {
+        let luma = vec![c.to_gray8(); height * width];
+        let colors: Vec<color> = dither::dither_luma(&luma, width, height, y, x, dither)
+            .into_iter()
+            .map(color::from_gray8)
+            .collect();
+        self.write_color_rows(y, x, width, &colors);
+        mxcfb_rect {
+            top: y as u32,
+            left: x as u32,
+            height: height as u32,
+            width: width as u32,
+        }
+    }
+
+    fn fill_rect_styled(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        style: &FillStyle,
+    ) -> mxcfb_rect {
+        if width == 0 {
+            return mxcfb_rect::invalid();
+        }
+        let (y, x) = (y as i32, x as i32);
+        let spans: Vec<(i32, i32, i32)> = (y..y + height as i32)
+            .map(|row| (row, x, x + width as i32 - 1))
+            .collect();
+        self.paint_spans(&spans, style)
     }
 
     fn fill_rect(&mut self, y: usize, x: usize, height: usize, width: usize, c: color) {
         for ypos in y..y + height {
-            for xpos in x..x + width {
-                self.write_pixel(ypos, xpos, c);
-            }
+            self.fill_row(ypos, x, width, c);
         }
     }
 
     fn clear(&mut self) {
+        if self.clip_rect().is_some() {
+            // Like the whole frame, the clip rectangle is wiped whatever the raster operation
+            let area = self.drawable_rect();
+            let op = self.raster_op();
+            self.set_raster_op(RasterOp::Copy);
+            for y in area.top..area.top + area.height {
+                self.fill_row(y as usize, area.left as usize, area.width as usize, color::WHITE);
+            }
+            self.set_raster_op(op);
+            return;
+        }
         let h = self.var_screen_info.yres as usize;
         let line_length = self.fix_screen_info.line_length as usize;
         unsafe {
             libc::memset(
-                self.frame.data() as *mut libc::c_void,
+                self.draw_ptr() as *mut libc::c_void,
                 std::i32::MAX,
                 line_length * h,
             );
@@ -0,0 +1,150 @@
+use framebuffer::common::color;
+use framebuffer::dither::{self, Dither, GrayLevels};
+
+/// Orientation of the lines of `FillStyle::Hatch`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HatchDirection {
+    Horizontal,
+    Vertical,
+    /// Lines going up to the right, like `/`
+    Diagonal,
+    /// Lines going down to the right, like `\`
+    AntiDiagonal,
+    /// Both horizontal and vertical lines
+    Cross,
+    /// Both diagonal directions
+    DiagonalCross,
+}
+
+/// What the area of a shape is painted with, see `FramebufferDraw::fill_rect_styled` and
+/// `FramebufferDraw::fill_polygon_styled`. Patterns are anchored at the framebuffer origin,
+/// not at the shape, so neighbouring shapes line up. A `None` background leaves the
+/// existing content visible between the lines or squares of the pattern.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FillStyle {
+    Solid(color),
+    /// The gray level with the luminance `level` rendered with black and white dots only,
+    /// which keeps the area crisp under the DU and A2 waveforms
+    Halftone(u8),
+    /// Lines `thickness` pixels thick repeated every `spacing` pixels
+    Hatch {
+        direction: HatchDirection,
+        spacing: usize,
+        thickness: usize,
+        foreground: color,
+        background: Option<color>,
+    },
+    /// Alternating squares of `size` pixels, the top left one being `foreground`
+    Checkerboard {
+        size: usize,
+        foreground: color,
+        background: Option<color>,
+    },
+    /// Transition from `from` at the `(x, y)` point `start` to `to` at `end`, perpendicular
+    /// to the line joining them. The colors are then reduced with `dither` unless it is
+    /// `Dither::None`.
+    LinearGradient {
+        start: (f32, f32),
+        end: (f32, f32),
+        from: color,
+        to: color,
+        dither: Dither,
+    },
+}
+
+impl FillStyle {
+    /// Renders the fill of the `height` by `width` area at `(top, left)`, row by row.
+    /// Pixels left as `None` aren't painted.
+    pub fn render(
+        &self,
+        top: usize,
+        left: usize,
+        height: usize,
+        width: usize,
+    ) -> Vec<Option<color>> {
+        let len = height * width;
+        let position = |index: usize| (top + index / width, left + index % width);
+        match *self {
+            FillStyle::Solid(c) => vec![Some(c); len],
+            FillStyle::Halftone(level) => {
+                let luma = vec![level; len];
+                dither::dither_luma(&luma, width, height, top, left, Dither::Bayer(GrayLevels::Two))
+                    .into_iter()
+                    .map(|v| Some(color::from_gray8(v)))
+                    .collect()
+            }
+            FillStyle::Hatch {
+                direction,
+                spacing,
+                thickness,
+                foreground,
+                background,
+            } => {
+                let spacing = spacing.max(1) as isize;
+                let on_line = |v: isize| ((v % spacing) + spacing) % spacing < thickness as isize;
+                (0..len)
+                    .map(|index| {
+                        let (y, x) = position(index);
+                        let (y, x) = (y as isize, x as isize);
+                        let covered = match direction {
+                            HatchDirection::Horizontal => on_line(y),
+                            HatchDirection::Vertical => on_line(x),
+                            HatchDirection::Diagonal => on_line(x + y),
+                            HatchDirection::AntiDiagonal => on_line(x - y),
+                            HatchDirection::Cross => on_line(y) || on_line(x),
+                            HatchDirection::DiagonalCross => on_line(x + y) || on_line(x - y),
+                        };
+                        if covered {
+                            Some(foreground)
+                        } else {
+                            background
+                        }
+                    }).collect()
+            }
+            FillStyle::Checkerboard {
+                size,
+                foreground,
+                background,
+            } => {
+                let size = size.max(1);
+                (0..len)
+                    .map(|index| {
+                        let (y, x) = position(index);
+                        if (y / size + x / size) % 2 == 0 {
+                            Some(foreground)
+                        } else {
+                            background
+                        }
+                    }).collect()
+            }
+            FillStyle::LinearGradient {
+                start,
+                end,
+                from,
+                to,
+                dither,
+            } => {
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                let length2 = dx * dx + dy * dy;
+                let colors: Vec<color> = (0..len)
+                    .map(|index| {
+                        let (y, x) = position(index);
+                        let t = if length2 == 0.0 {
+                            1.0
+                        } else {
+                            ((x as f32 - start.0) * dx + (y as f32 - start.1) * dy) / length2
+                        };
+                        from.blend(to, t)
+                    }).collect();
+                if dither == Dither::None {
+                    return colors.into_iter().map(Some).collect();
+                }
+                let luma: Vec<u8> = colors.iter().map(|c| c.to_gray8()).collect();
+                dither::dither_luma(&luma, width, height, top, left, dither)
+                    .into_iter()
+                    .map(|v| Some(color::from_gray8(v)))
+                    .collect()
+            }
+        }
+    }
+}
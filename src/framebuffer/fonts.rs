@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::Read;
+
+use rusttype::{Font, FontCollection};
+
+use error::{Error, Result};
+
+/// Weight of a typeface, with the usual OpenType numeric values
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FontWeight {
+    Thin = 100,
+    ExtraLight = 200,
+    Light = 300,
+    Regular = 400,
+    Medium = 500,
+    SemiBold = 600,
+    Bold = 700,
+    ExtraBold = 800,
+    Black = 900,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+}
+
+/// Refers to a font loaded into a `FontRegistry`. The default handle refers to the
+/// built-in Roboto Regular, which is always available.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FontHandle(usize);
+
+struct RegisteredFont<'a> {
+    name: String,
+    weight: FontWeight,
+    style: FontStyle,
+    font: Font<'a>,
+}
+
+/// The fonts `FramebufferDraw::draw_text` can render with, looked up by name, weight
+/// and style
+pub struct FontRegistry<'a> {
+    fonts: Vec<RegisteredFont<'a>>,
+}
+
+impl<'a> FontRegistry<'a> {
+    /// Creates a registry containing only the built-in Roboto Regular
+    pub fn new() -> FontRegistry<'a> {
+        let font_data = include_bytes!("../../assets/Roboto-Regular.ttf");
+        let collection = FontCollection::from_bytes(font_data as &[u8]);
+        FontRegistry {
+            fonts: vec![RegisteredFont {
+                name: "Roboto".to_owned(),
+                weight: FontWeight::Regular,
+                style: FontStyle::Normal,
+                font: collection.into_font().unwrap(),
+            }],
+        }
+    }
+
+    /// Registers the TrueType or OpenType font in `data` under `name`, `weight` and `style`.
+    /// If the data is a collection, its first font is used.
+    pub fn load_bytes(
+        &mut self,
+        name: &str,
+        weight: FontWeight,
+        style: FontStyle,
+        data: Vec<u8>,
+    ) -> Result<FontHandle> {
+        let collection = FontCollection::from_bytes(data);
+        let font = collection.into_font().ok_or_else(|| Error::Parse {
+            attribute: "font".to_owned(),
+            value: name.to_owned(),
+        })?;
+        self.fonts.push(RegisteredFont {
+            name: name.to_owned(),
+            weight,
+            style,
+            font,
+        });
+        Ok(FontHandle(self.fonts.len() - 1))
+    }
+
+    /// Same as `load_bytes(..)` but reads the font from the file at `path`
+    pub fn load_file(
+        &mut self,
+        name: &str,
+        weight: FontWeight,
+        style: FontStyle,
+        path: &str,
+    ) -> Result<FontHandle> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        self.load_bytes(name, weight, style, data)
+    }
+
+    /// Looks up the font registered as `name`. If there is no exact match for `weight`
+    /// and `style`, the closest weight is picked, preferring fonts with the requested style.
+    pub fn find(&self, name: &str, weight: FontWeight, style: FontStyle) -> Option<FontHandle> {
+        let distance = |f: &RegisteredFont| {
+            let weight_distance = (f.weight as i32 - weight as i32).abs();
+            if f.style == style {
+                weight_distance
+            } else {
+                weight_distance + 1000
+            }
+        };
+        self.fonts
+            .iter()
+            .enumerate()
+            .filter(|&(_, f)| f.name == name)
+            .min_by_key(|&(_, f)| distance(f))
+            .map(|(i, _)| FontHandle(i))
+    }
+
+    /// Returns the font `handle` refers to, or the built-in font if it isn't
+    /// part of this registry
+    pub fn get(&self, handle: FontHandle) -> &Font<'a> {
+        match self.fonts.get(handle.0) {
+            Some(f) => &f.font,
+            None => {
+                warn!("Unknown font handle {0:?}, using the default font", handle);
+                &self.fonts[0].font
+            }
+        }
+    }
+
+    /// Returns the built-in font
+    pub fn default_font(&self) -> &Font<'a> {
+        &self.fonts[0].font
+    }
+}
+
+impl<'a> Default for FontRegistry<'a> {
+    fn default() -> FontRegistry<'a> {
+        FontRegistry::new()
+    }
+}
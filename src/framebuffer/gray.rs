@@ -0,0 +1,138 @@
+use framebuffer::common::color;
+
+/// Luminance of the 16 gray levels the panel displays with the GC16 and GL16 waveforms,
+/// from black to white. `dither::GrayLevels::Sixteen` reduces to exactly these.
+pub const EINK_PALETTE: [u8; 16] = [
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE,
+    0xFF,
+];
+
+/// Returns the index in `EINK_PALETTE` of the level closest to `luma`
+#[inline]
+pub fn nearest_level(luma: u8) -> usize {
+    ((u32::from(luma) + 8) / 17) as usize
+}
+
+/// Rounds `luma` to the closest level of `EINK_PALETTE`
+#[inline]
+pub fn quantize(luma: u8) -> u8 {
+    EINK_PALETTE[nearest_level(luma)]
+}
+
+/// Linear light intensity of every sRGB component value, 65535 being full
+const SRGB_TO_LINEAR: [u16; 256] = [
+    0, 20, 40, 60, 80, 99, 119, 139, 159, 179, 199, 219, 241, 264, 288, 313, 340, 367, 396, 427,
+    458, 491, 526, 562, 599, 637, 677, 718, 761, 805, 851, 898, 947, 997, 1048, 1101, 1156, 1212,
+    1270, 1330, 1391, 1453, 1517, 1583, 1651, 1720, 1790, 1863, 1937, 2013, 2090, 2170, 2250, 2333,
+    2418, 2504, 2592, 2681, 2773, 2866, 2961, 3058, 3157, 3258, 3360, 3464, 3570, 3678, 3788, 3900,
+    4014, 4129, 4247, 4366, 4488, 4611, 4736, 4864, 4993, 5124, 5257, 5392, 5530, 5669, 5810, 5953,
+    6099, 6246, 6395, 6547, 6700, 6856, 7014, 7174, 7335, 7500, 7666, 7834, 8004, 8177, 8352, 8528,
+    8708, 8889, 9072, 9258, 9445, 9635, 9828, 10022, 10219, 10417, 10619, 10822, 11028, 11235,
+    11446, 11658, 11873, 12090, 12309, 12530, 12754, 12980, 13209, 13440, 13673, 13909, 14146,
+    14387, 14629, 14874, 15122, 15371, 15623, 15878, 16135, 16394, 16656, 16920, 17187, 17456,
+    17727, 18001, 18277, 18556, 18837, 19121, 19407, 19696, 19987, 20281, 20577, 20876, 21177,
+    21481, 21787, 22096, 22407, 22721, 23038, 23357, 23678, 24002, 24329, 24658, 24990, 25325,
+    25662, 26001, 26344, 26688, 27036, 27386, 27739, 28094, 28452, 28813, 29176, 29542, 29911,
+    30282, 30656, 31033, 31412, 31794, 32179, 32567, 32957, 33350, 33745, 34143, 34544, 34948,
+    35355, 35764, 36176, 36591, 37008, 37429, 37852, 38278, 38706, 39138, 39572, 40009, 40449,
+    40891, 41337, 41785, 42236, 42690, 43147, 43606, 44069, 44534, 45002, 45473, 45947, 46423,
+    46903, 47385, 47871, 48359, 48850, 49344, 49841, 50341, 50844, 51349, 51858, 52369, 52884,
+    53401, 53921, 54445, 54971, 55500, 56032, 56567, 57105, 57646, 58190, 58737, 59287, 59840,
+    60396, 60955, 61517, 62082, 62650, 63221, 63795, 64372, 64952, 65535,
+];
+
+/// Linear intensities halfway between consecutive sRGB component values, so that the
+/// number of them below an intensity is its rounded sRGB value
+const LINEAR_THRESHOLDS: [u16; 255] = [
+    10, 30, 50, 70, 90, 109, 129, 149, 169, 189, 209, 230, 252, 276, 300, 326, 353, 382, 411, 442,
+    475, 508, 543, 580, 618, 657, 697, 739, 783, 828, 874, 922, 971, 1022, 1075, 1129, 1184, 1241,
+    1300, 1360, 1422, 1485, 1550, 1617, 1685, 1755, 1826, 1900, 1975, 2051, 2130, 2210, 2292, 2375,
+    2460, 2547, 2636, 2727, 2819, 2914, 3010, 3107, 3207, 3309, 3412, 3517, 3624, 3733, 3844, 3957,
+    4071, 4188, 4306, 4427, 4549, 4673, 4800, 4928, 5058, 5190, 5325, 5461, 5599, 5739, 5881, 6026,
+    6172, 6320, 6471, 6623, 6778, 6935, 7093, 7254, 7417, 7582, 7750, 7919, 8090, 8264, 8440, 8618,
+    8798, 8980, 9165, 9351, 9540, 9731, 9925, 10120, 10318, 10518, 10720, 10924, 11131, 11340,
+    11551, 11765, 11981, 12199, 12419, 12642, 12867, 13094, 13324, 13556, 13790, 14027, 14266,
+    14508, 14751, 14998, 15246, 15497, 15750, 16006, 16264, 16525, 16788, 17053, 17321, 17591,
+    17864, 18139, 18416, 18696, 18979, 19264, 19551, 19841, 20134, 20429, 20726, 21026, 21329,
+    21634, 21941, 22251, 22564, 22879, 23197, 23517, 23840, 24165, 24493, 24824, 25157, 25493,
+    25831, 26172, 26516, 26862, 27211, 27562, 27916, 28273, 28632, 28994, 29359, 29726, 30096,
+    30469, 30844, 31222, 31603, 31986, 32372, 32761, 33153, 33547, 33944, 34344, 34746, 35151,
+    35559, 35970, 36383, 36799, 37218, 37640, 38064, 38492, 38922, 39354, 39790, 40228, 40670,
+    41114, 41560, 42010, 42463, 42918, 43376, 43837, 44301, 44768, 45237, 45709, 46185, 46663,
+    47144, 47628, 48114, 48604, 49097, 49592, 50091, 50592, 51096, 51603, 52113, 52626, 53142,
+    53661, 54183, 54707, 55235, 55766, 56299, 56836, 57375, 57918, 58463, 59012, 59563, 60118,
+    60675, 61235, 61799, 62365, 62935, 63507, 64083, 64661, 65243,
+];
+
+/// Rec. 709 coefficients in 16-bit fixed point, adding up to 65536
+const LUMA_WEIGHTS: [u32; 3] = [13_933, 46_871, 4_732];
+
+fn linear_to_srgb(v: u16) -> u8 {
+    match LINEAR_THRESHOLDS.binary_search(&v) {
+        Ok(i) => (i + 1) as u8,
+        Err(i) => i as u8,
+    }
+}
+
+/// Returns how light the sRGB color `(r8, g8, b8)` looks, 0 being black and 255 white.
+/// The components are weighed in linear light with the Rec. 709 coefficients, so that
+/// saturated colors keep their perceived lightness once shown in gray.
+pub fn luminance(r8: u8, g8: u8, b8: u8) -> u8 {
+    if r8 == g8 && g8 == b8 {
+        return r8;
+    }
+    let linear = LUMA_WEIGHTS[0] * u32::from(SRGB_TO_LINEAR[r8 as usize])
+        + LUMA_WEIGHTS[1] * u32::from(SRGB_TO_LINEAR[g8 as usize])
+        + LUMA_WEIGHTS[2] * u32::from(SRGB_TO_LINEAR[b8 as usize]);
+    linear_to_srgb(((linear + 0x8000) >> 16) as u16)
+}
+
+/// Lookup table remapping luminances before they are written, to make up for how the
+/// panel renders gray levels. See `core::Framebuffer::set_tone_curve`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToneCurve {
+    table: Vec<u8>,
+}
+
+impl Default for ToneCurve {
+    fn default() -> ToneCurve {
+        ToneCurve::identity()
+    }
+}
+
+impl ToneCurve {
+    /// Leaves every luminance unchanged
+    pub fn identity() -> ToneCurve {
+        ToneCurve::from_fn(|v| v)
+    }
+
+    /// Applies `gamma` then scales the result around the middle gray by `contrast`. A gamma
+    /// above 1.0 lightens the midtones, which e-ink tends to show too dark, and a contrast
+    /// above 1.0 pushes the tones towards black and white. Both are neutral at 1.0.
+    pub fn new(gamma: f32, contrast: f32) -> ToneCurve {
+        let gamma = if gamma > 0.0 { gamma } else { 1.0 };
+        ToneCurve::from_fn(|v| {
+            let corrected = (f32::from(v) / 255.0).powf(1.0 / gamma);
+            let contrasted = (corrected - 0.5) * contrast + 0.5;
+            (contrasted * 255.0).round().max(0.0).min(255.0) as u8
+        })
+    }
+
+    /// Builds the table from `f`, called once for every luminance
+    pub fn from_fn<F: Fn(u8) -> u8>(f: F) -> ToneCurve {
+        ToneCurve {
+            table: (0..=255u8).map(f).collect(),
+        }
+    }
+
+    #[inline]
+    pub fn apply(&self, luma: u8) -> u8 {
+        self.table[luma as usize]
+    }
+
+    /// Returns the gray `c` is drawn with
+    #[inline]
+    pub fn map(&self, c: color) -> color {
+        color::from_gray8(self.apply(c.to_gray8()))
+    }
+}
@@ -0,0 +1,98 @@
+use image::imageops;
+use image::{DynamicImage, RgbaImage};
+
+use framebuffer::common::mxcfb_rect;
+
+/// Resampling filter used when scaling images
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScaleFilter {
+    /// Fastest, keeps hard edges (e.g. pixel art, line drawings) but aliases
+    Nearest,
+    Bilinear,
+    /// Sharpest for photos, slowest
+    Lanczos,
+}
+
+impl ScaleFilter {
+    fn filter_type(self) -> imageops::FilterType {
+        match self {
+            ScaleFilter::Nearest => imageops::FilterType::Nearest,
+            ScaleFilter::Bilinear => imageops::FilterType::Triangle,
+            ScaleFilter::Lanczos => imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// How an image is fitted into a destination rectangle
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFit {
+    /// Scales each axis independently to cover the rectangle exactly
+    Stretch,
+    /// Keeps the aspect ratio and scales the image to the largest size that fits in the
+    /// rectangle, centering it
+    Fit,
+    /// Keeps the aspect ratio and scales the image to the smallest size that covers the
+    /// rectangle, cropping what overflows on both sides
+    Fill,
+}
+
+/// Scales `img` for the `dest` rectangle as described by `filter` and `fit`. Returns the
+/// scaled image along with the region of `dest` it should be drawn at.
+pub fn scale_to_rect(
+    img: &DynamicImage,
+    dest: &mxcfb_rect,
+    filter: ScaleFilter,
+    fit: ImageFit,
+) -> (RgbaImage, mxcfb_rect) {
+    let rgba = img.to_rgba();
+    let (src_width, src_height) = (rgba.width(), rgba.height());
+    if src_width == 0 || src_height == 0 || dest.width == 0 || dest.height == 0 {
+        return (
+            RgbaImage::new(0, 0),
+            mxcfb_rect {
+                top: dest.top,
+                left: dest.left,
+                height: 0,
+                width: 0,
+            },
+        );
+    }
+
+    let horizontal = dest.width as f32 / src_width as f32;
+    let vertical = dest.height as f32 / src_height as f32;
+    let (width, height) = match fit {
+        ImageFit::Stretch => (dest.width, dest.height),
+        ImageFit::Fit | ImageFit::Fill => {
+            let scale = if fit == ImageFit::Fit {
+                horizontal.min(vertical)
+            } else {
+                horizontal.max(vertical)
+            };
+            (
+                ((src_width as f32 * scale).round() as u32).max(1),
+                ((src_height as f32 * scale).round() as u32).max(1),
+            )
+        }
+    };
+
+    let mut scaled = if (width, height) == (src_width, src_height) {
+        rgba
+    } else {
+        imageops::resize(&rgba, width, height, filter.filter_type())
+    };
+
+    if fit == ImageFit::Fill {
+        let (x, y) = ((width - dest.width) / 2, (height - dest.height) / 2);
+        let cropped = imageops::crop(&mut scaled, x, y, dest.width, dest.height).to_image();
+        return (cropped, *dest);
+    }
+
+    let (width, height) = (width.min(dest.width), height.min(dest.height));
+    let placement = mxcfb_rect {
+        top: dest.top + (dest.height - height) / 2,
+        left: dest.left + (dest.width - width) / 2,
+        height,
+        width,
+    };
+    (scaled, placement)
+}
@@ -0,0 +1,101 @@
+use framebuffer::raster;
+
+/// Highest pressure reported by the digitizer in `WacomEvent::Draw`
+pub const MAX_PRESSURE: u16 = 4095;
+
+/// A point of a variable width stroke, see `FramebufferDraw::draw_ink_stroke`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StrokePoint {
+    pub x: f32,
+    pub y: f32,
+    /// Width of the stroke at this point
+    pub width: f32,
+}
+
+impl StrokePoint {
+    pub fn new(y: f32, x: f32, width: f32) -> StrokePoint {
+        StrokePoint { x, y, width }
+    }
+
+    /// Creates a point at `(y, x)` whose width grows linearly with `pressure`, from
+    /// `min_width` when barely touching to `max_width` at `MAX_PRESSURE`
+    pub fn from_pressure(y: u16, x: u16, pressure: u16, min_width: f32, max_width: f32) -> StrokePoint {
+        let t = f32::from(pressure.min(MAX_PRESSURE)) / f32::from(MAX_PRESSURE);
+        StrokePoint::new(f32::from(y), f32::from(x), min_width + (max_width - min_width) * t)
+    }
+
+    fn position(&self) -> (f32, f32) {
+        (self.x, self.y)
+    }
+}
+
+/// Interpolates `points` with a Catmull-Rom spline so that the stroke bends smoothly
+/// through them. Widths are interpolated linearly between consecutive points.
+pub fn smooth(points: &[StrokePoint]) -> Vec<StrokePoint> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut smoothed = vec![points[0]];
+    for i in 0..points.len() - 1 {
+        let p0 = points[if i == 0 { 0 } else { i - 1 }];
+        let (p1, p2) = (points[i], points[i + 1]);
+        let p3 = points[(i + 2).min(points.len() - 1)];
+        let length = ((p2.x - p1.x).powi(2) + (p2.y - p1.y).powi(2)).sqrt();
+        let steps = (length / 3.0).ceil().max(1.0) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            let spline = |a: f32, b: f32, c: f32, d: f32| {
+                0.5 * (2.0 * b
+                    + (c - a) * t
+                    + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2
+                    + (3.0 * b - a - 3.0 * c + d) * t3)
+            };
+            smoothed.push(StrokePoint {
+                x: spline(p0.x, p1.x, p2.x, p3.x),
+                y: spline(p0.y, p1.y, p2.y, p3.y),
+                width: p1.width + (p2.width - p1.width) * t,
+            });
+        }
+    }
+    smoothed
+}
+
+/// Returns the outline of the stroke through `points` as polygons to be filled with
+/// `FillRule::NonZero`. Every segment becomes the hull of the discs at both of its ends,
+/// which tapers the stroke from one width to the other. Discs round every point off so
+/// that sharp turns have no gaps. All polygons wind the same way so that their overlaps
+/// don't cancel out.
+pub fn outline(points: &[StrokePoint]) -> Vec<Vec<(f32, f32)>> {
+    let mut polygons: Vec<Vec<(f32, f32)>> = points
+        .iter()
+        .filter(|p| p.width > 0.0)
+        .map(|p| raster::disc(p.position(), p.width / 2.0))
+        .collect();
+
+    for pair in points.windows(2) {
+        let (p0, p1) = (pair[0], pair[1]);
+        let (r0, r1) = (p0.width.max(0.0) / 2.0, p1.width.max(0.0) / 2.0);
+        let (dx, dy) = (p1.x - p0.x, p1.y - p0.y);
+        let distance = (dx * dx + dy * dy).sqrt();
+        // When a disc contains the other one, it is all there is to draw
+        if distance <= (r0 - r1).abs() {
+            continue;
+        }
+        // Normals of the lines tangent to both discs
+        let (ux, uy) = (dx / distance, dy / distance);
+        let sin = (r0 - r1) / distance;
+        let cos = (1.0 - sin * sin).sqrt();
+        let left = (sin * ux - cos * uy, sin * uy + cos * ux);
+        let right = (sin * ux + cos * uy, sin * uy - cos * ux);
+        polygons.push(vec![
+            (p0.x + r0 * left.0, p0.y + r0 * left.1),
+            (p1.x + r1 * left.0, p1.y + r1 * left.1),
+            (p1.x + r1 * right.0, p1.y + r1 * right.1),
+            (p0.x + r0 * right.0, p0.y + r0 * right.1),
+        ]);
+    }
+
+    raster::wind_clockwise(&mut polygons);
+    polygons
+}
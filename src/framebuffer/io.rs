@@ -1,78 +1,227 @@
 #![allow(dead_code)]
+use error::{Error, Result};
 use framebuffer;
 use framebuffer::common;
 
+impl<'a> framebuffer::core::Framebuffer<'a> {
+    /// Maps `(y, x)` from the current orientation onto the framebuffer memory
+    #[inline]
+    fn native_point(&self, y: usize, x: usize) -> (usize, usize) {
+        let (y, x) = self.orientation().to_native(
+            y as u32,
+            x as u32,
+            self.var_screen_info.yres,
+            self.var_screen_info.xres,
+        );
+        (y as usize, x as usize)
+    }
+
+    /// Clips the row of `width` pixels starting at `(y, x)` to the display and the clip
+    /// rectangle, returning the visible columns as `first..end`
+    fn clip_row(&self, y: usize, x: usize, width: usize) -> Option<(usize, usize)> {
+        let (h, w) = self.dimensions();
+        let (mut top, mut bottom, mut left, mut right) = (0, h as usize, 0, w as usize);
+        if let Some(clip) = self.clip_rect() {
+            top = ::std::cmp::max(top, clip.top as usize);
+            bottom = ::std::cmp::min(bottom, (clip.top + clip.height) as usize);
+            left = ::std::cmp::max(left, clip.left as usize);
+            right = ::std::cmp::min(right, (clip.left + clip.width) as usize);
+        }
+        let (first, end) = (::std::cmp::max(x, left), ::std::cmp::min(x + width, right));
+        if y < top || y >= bottom || first >= end {
+            None
+        } else {
+            Some((first, end))
+        }
+    }
+
+    /// Writes the encoded pixel `pixel` at the native position `(y, x)`, combined with the
+    /// existing one according to `op`
+    #[inline]
+    fn write_native(&self, y: usize, x: usize, pixel: &[u8], op: common::RasterOp) {
+        let offset = y * self.fix_screen_info.line_length as usize + x * pixel.len();
+        unsafe {
+            let dest = self.draw_ptr().add(offset);
+            if op == common::RasterOp::Copy {
+                dest.copy_from_nonoverlapping(pixel.as_ptr(), pixel.len());
+            } else {
+                let dest = ::std::slice::from_raw_parts_mut(dest, pixel.len());
+                op.apply(self.pixel_format(), dest, pixel);
+            }
+        }
+    }
+}
+
 impl<'a> framebuffer::FramebufferIO for framebuffer::core::Framebuffer<'a> {
     fn write_frame(&mut self, frame: &[u8]) {
+        let available = self.backend.frame_len() - self.back_buffer.unwrap_or(0);
+        let len = ::std::cmp::min(frame.len(), available);
         unsafe {
-            let begin = self.frame.data() as *mut u8;
-            for (i, elem) in frame.iter().enumerate() {
-                begin.offset(i as isize).write_volatile(*elem);
+            self.draw_ptr()
+                .copy_from_nonoverlapping(frame.as_ptr(), len);
+        }
+    }
+
+    fn fill_row(&mut self, y: usize, x: usize, width: usize, col: common::color) {
+        let (first, end) = match self.clip_row(y, x, width) {
+            Some(range) => range,
+            None => return,
+        };
+        let mut pixel = Vec::with_capacity(2);
+        self.pixel_format().encode(col, &mut pixel);
+
+        let op = self.raster_op();
+        match self.orientation() {
+            // Rows stay rows, only reversed when upside down
+            common::Orientation::Rotate0 | common::Orientation::Rotate180
+                if op == common::RasterOp::Copy =>
+            {
+                let (native_y, x0) = self.native_point(y, first);
+                let (_, x1) = self.native_point(y, end - 1);
+                let len = (end - first) * pixel.len();
+                let offset = native_y * self.fix_screen_info.line_length as usize
+                    + ::std::cmp::min(x0, x1) * pixel.len();
+                let begin = unsafe { self.draw_ptr().add(offset) };
+                if pixel.iter().all(|&b| b == pixel[0]) {
+                    unsafe {
+                        begin.write_bytes(pixel[0], len);
+                    }
+                } else {
+                    let row: Vec<u8> = pixel.iter().cycle().take(len).cloned().collect();
+                    unsafe {
+                        begin.copy_from_nonoverlapping(row.as_ptr(), len);
+                    }
+                }
+            }
+            _ => {
+                for x in first..end {
+                    let (native_y, native_x) = self.native_point(y, x);
+                    self.write_native(native_y, native_x, &pixel, op);
+                }
+            }
+        }
+    }
+
+    fn write_row(&mut self, y: usize, x: usize, data: &[u8]) {
+        let bytespp = self.pixel_format().bytes_per_pixel();
+        let (first, end) = match self.clip_row(y, x, data.len() / bytespp) {
+            Some(range) => range,
+            None => return,
+        };
+        let data = &data[(first - x) * bytespp..(end - x) * bytespp];
+
+        let op = self.raster_op();
+        if self.orientation() == common::Orientation::Rotate0 && op == common::RasterOp::Copy {
+            let offset = y * self.fix_screen_info.line_length as usize + first * bytespp;
+            unsafe {
+                self.draw_ptr()
+                    .add(offset)
+                    .copy_from_nonoverlapping(data.as_ptr(), data.len());
             }
+            return;
+        }
+        for (i, pixel) in data.chunks(bytespp).enumerate() {
+            let (native_y, native_x) = self.native_point(y, first + i);
+            self.write_native(native_y, native_x, pixel, op);
         }
     }
 
     #[inline]
     fn write_pixel(&mut self, y: usize, x: usize, col: framebuffer::common::color) {
-        let w = self.var_screen_info.xres as usize;
-        let h = self.var_screen_info.yres as usize;
-        if y >= h || x >= w {
+        if !self.is_drawable(y, x) {
+            return;
+        }
+        let (y, x) = self.native_point(y, x);
+        let format = self.pixel_format();
+        let op = self.raster_op();
+        if op != common::RasterOp::Copy {
+            let encoded = match format {
+                common::PixelFormat::Rgb565 => col.as_native(),
+                common::PixelFormat::Gray8 => [col.to_gray8(), 0],
+            };
+            self.write_native(y, x, &encoded[..format.bytes_per_pixel()], op);
             return;
         }
         let line_length = self.fix_screen_info.line_length as usize;
-        let bytespp = (self.var_screen_info.bits_per_pixel / 8) as usize;
-        let curr_index = (y * line_length + x * bytespp) as isize;
+        let curr_index = (y * line_length + x * format.bytes_per_pixel()) as isize;
 
-        let begin = self.frame.data() as *mut u8;
-        let components = col.as_native();
+        let begin = self.draw_ptr();
         unsafe {
-            begin.offset(curr_index).write_volatile(components[0]);
-            begin.offset(curr_index + 1).write_volatile(components[1]);
+            match format {
+                common::PixelFormat::Rgb565 => {
+                    let components = col.as_native();
+                    begin.offset(curr_index).write_volatile(components[0]);
+                    begin.offset(curr_index + 1).write_volatile(components[1]);
+                }
+                common::PixelFormat::Gray8 => {
+                    begin.offset(curr_index).write_volatile(col.to_gray8());
+                }
+            }
         }
     }
 
     fn read_pixel(&self, y: usize, x: usize) -> framebuffer::common::color {
-        let w = self.var_screen_info.xres as usize;
-        let h = self.var_screen_info.yres as usize;
-        if y >= h || x >= w {
+        let (h, w) = self.dimensions();
+        if y >= h as usize || x >= w as usize {
             error!("Attempting to read pixel out of range. Returning a white pixel.");
             return framebuffer::common::color::WHITE;
         }
+        let (y, x) = self.native_point(y, x);
         let line_length = self.fix_screen_info.line_length as usize;
-        let bytespp = (self.var_screen_info.bits_per_pixel / 8) as usize;
-        let curr_index = y * line_length + x * bytespp;
-
-        let begin = self.frame.data() as *mut u8;
-        let (c1, c2) = unsafe {
-            (
-                begin.offset(curr_index as isize).read_volatile(),
-                begin.offset((curr_index + 1) as isize).read_volatile(),
-            )
-        };
-        framebuffer::common::color::NATIVE_COMPONENTS(c1, c2)
+        let format = self.pixel_format();
+        let curr_index = (y * line_length + x * format.bytes_per_pixel()) as isize;
+
+        let begin = self.draw_ptr();
+        unsafe {
+            match format {
+                common::PixelFormat::Rgb565 => common::color::NATIVE_COMPONENTS(
+                    begin.offset(curr_index).read_volatile(),
+                    begin.offset(curr_index + 1).read_volatile(),
+                ),
+                common::PixelFormat::Gray8 => {
+                    common::color::from_gray8(begin.offset(curr_index).read_volatile())
+                }
+            }
+        }
     }
 
     fn read_offset(&self, ofst: isize) -> u8 {
         unsafe {
-            let begin = self.frame.data() as *mut u8;
+            let begin = self.draw_ptr();
             begin.offset(ofst).read_volatile()
         }
     }
 
-    fn dump_region(&self, rect: common::mxcfb_rect) -> Result<Vec<u8>, &'static str> {
+    fn dump_region(&self, rect: common::mxcfb_rect) -> Result<Vec<u8>> {
         if rect.width == 0 || rect.height == 0 {
-            return Err("Unable to dump a region with zero height/width");
+            return Err(Error::Bounds(
+                "Unable to dump a region with zero height/width",
+            ));
+        }
+        let (height, width) = self.dimensions();
+        if rect.top + rect.height > height {
+            return Err(Error::Bounds("Vertically out of bounds"));
         }
-        if rect.top + rect.height > self.var_screen_info.height {
-            return Err("Vertically out of bounds");
+        if rect.left + rect.width > width {
+            return Err(Error::Bounds("Horizontally out of bounds"));
         }
-        if rect.left + rect.width > self.var_screen_info.width {
-            return Err("Horizontally out of bounds");
+
+        // Rows of a rotated region aren't contiguous in the framebuffer
+        let format = self.pixel_format();
+        let bytespp = format.bytes_per_pixel();
+        if self.orientation() != common::Orientation::Rotate0 {
+            let mut outbuffer =
+                Vec::with_capacity(rect.height as usize * rect.width as usize * bytespp);
+            for y in rect.top..rect.top + rect.height {
+                for x in rect.left..rect.left + rect.width {
+                    format.encode(self.read_pixel(y as usize, x as usize), &mut outbuffer);
+                }
+            }
+            return Ok(outbuffer);
         }
 
         let line_length = self.fix_screen_info.line_length as u32;
-        let bytespp = (self.var_screen_info.bits_per_pixel / 8) as usize;
-        let inbuffer = self.frame.data();
+        let inbuffer = self.draw_ptr();
         let mut outbuffer: Vec<u8> =
             Vec::with_capacity(rect.height as usize * rect.width as usize * bytespp);
         let outbuffer_ptr = outbuffer.as_mut_ptr();
@@ -95,40 +244,63 @@ impl<'a> framebuffer::FramebufferIO for framebuffer::core::Framebuffer<'a> {
         Ok(outbuffer)
     }
 
-    fn restore_region(
-        &mut self,
-        rect: common::mxcfb_rect,
-        data: &[u8],
-    ) -> Result<u32, &'static str> {
+    fn restore_region(&mut self, rect: common::mxcfb_rect, data: &[u8]) -> Result<u32> {
         if rect.width == 0 || rect.height == 0 {
-            return Err("Unable to restore a region with zero height/width");
+            return Err(Error::Bounds(
+                "Unable to restore a region with zero height/width",
+            ));
         }
-        if rect.top + rect.height > self.var_screen_info.height {
-            return Err("Vertically out of bounds");
+        let (height, width) = self.dimensions();
+        if rect.top + rect.height > height {
+            return Err(Error::Bounds("Vertically out of bounds"));
         }
-        if rect.left + rect.width > self.var_screen_info.width {
-            return Err("Horizontally out of bounds");
+        if rect.left + rect.width > width {
+            return Err(Error::Bounds("Horizontally out of bounds"));
         }
 
-        let bytespp = (self.var_screen_info.bits_per_pixel / 8) as usize;
+        let format = self.pixel_format();
+        let bytespp = format.bytes_per_pixel();
         if data.len() as u32 != rect.width * rect.height * bytespp as u32 {
-            return Err("Cannot restore region due to mismatched size");
+            return Err(Error::Bounds(
+                "Cannot restore region due to mismatched size",
+            ));
         }
 
-        let line_length = self.fix_screen_info.line_length as u32;
-        let chunk_size = bytespp * rect.width as usize;
-        let outbuffer = self.frame.data();
+        // Rows of a rotated region aren't contiguous in the framebuffer
+        if self.orientation() != common::Orientation::Rotate0 {
+            let mut pixels = data.chunks(bytespp);
+            for y in rect.top..rect.top + rect.height {
+                for x in rect.left..rect.left + rect.width {
+                    let pixel = pixels.next().unwrap();
+                    if self.is_drawable(y as usize, x as usize) {
+                        let (native_y, native_x) = self.native_point(y as usize, x as usize);
+                        self.write_native(native_y, native_x, pixel, common::RasterOp::Copy);
+                    }
+                }
+            }
+            return Ok(data.len() as u32);
+        }
+
+        // Only the part within the clip rectangle is copied, like in other orientations
+        let area = rect.intersection(&self.drawable_rect());
+        if area.width == 0 || area.height == 0 {
+            return Ok(data.len() as u32);
+        }
+        let line_length = self.fix_screen_info.line_length as usize;
+        let row_length = bytespp * rect.width as usize;
+        let chunk_size = bytespp * area.width as usize;
+        let skipped = bytespp * (area.left - rect.left) as usize;
+        let outbuffer = self.draw_ptr();
         let inbuffer = data.as_ptr();
-        let mut written: u32 = 0;
-        for y in 0..rect.height {
-            let curr_index = (y + rect.top) * line_length + (bytespp * rect.left as usize) as u32;
+        for y in area.top..area.top + area.height {
+            let curr_index = y as usize * line_length + bytespp * area.left as usize;
+            let read_index = (y - rect.top) as usize * row_length + skipped;
             unsafe {
                 outbuffer
-                    .add(curr_index as usize)
-                    .copy_from(inbuffer.add(written as usize), chunk_size);
+                    .add(curr_index)
+                    .copy_from(inbuffer.add(read_index), chunk_size);
             }
-            written += chunk_size as u32;
         }
-        Ok(written)
+        Ok(data.len() as u32)
     }
 }
@@ -4,34 +4,86 @@ pub mod screeninfo;
 
 pub mod storage;
 
+pub mod fonts;
+
+pub mod text;
+
+pub mod raster;
+
+pub mod path;
+
+pub mod ink;
+
+pub mod stroke;
+
+pub mod dither;
+
+pub mod gray;
+
+pub mod cmap;
+
+pub mod fill;
+
+pub mod imaging;
+
+pub mod svg;
+
 pub mod io;
 
 use image;
+use error::Result;
 pub trait FramebufferIO {
     /// Writes an arbitrary length frame into the framebuffer
     fn write_frame(&mut self, frame: &[u8]);
-    /// Writes a single pixel at `(y, x)` with value `v`
+    /// Writes a single pixel at `(y, x)` with value `v`, unless it is outside of the
+    /// display or of `core::Framebuffer::clip_rect()`
     fn write_pixel(&mut self, y: usize, x: usize, v: common::color);
+    /// Fills the row of `width` pixels starting at `(y, x)` with `v`. The row is clipped
+    /// once and the color encoded once, which is much faster than `write_pixel` in a loop.
+    fn fill_row(&mut self, y: usize, x: usize, width: usize, v: common::color);
+    /// Writes `data`, a row of pixels already encoded in `core::Framebuffer::pixel_format()`,
+    /// starting at `(y, x)`. Pixels outside of the display or of the clip rectangle are skipped.
+    fn write_row(&mut self, y: usize, x: usize, data: &[u8]);
     /// Reads the value of the pixel at `(y, x)`
     fn read_pixel(&self, y: usize, x: usize) -> common::color;
     /// Reads the value at offset `ofst` from the mmapp'ed framebuffer region
     fn read_offset(&self, ofst: isize) -> u8;
     /// Dumps the contents of the specified rectangle into a `Vec<u8>` from which
     /// you can later create a CompressedCanvasState or pass to restore_region().
-    /// The pixel format is rgb565_le.
-    fn dump_region(&self, rect: common::mxcfb_rect) -> Result<Vec<u8>, &'static str>;
-    /// Restores into the framebuffer the contents of the specified rectangle from a u8 slice
-    fn restore_region(
-        &mut self,
-        rect: common::mxcfb_rect,
-        data: &[u8],
-    ) -> Result<u32, &'static str>;
+    /// The pixel format is the one the framebuffer is configured with, see
+    /// `core::Framebuffer::pixel_format()`.
+    fn dump_region(&self, rect: common::mxcfb_rect) -> Result<Vec<u8>>;
+    /// Restores into the framebuffer the contents of the specified rectangle from a u8 slice.
+    /// Pixels outside of the clip rectangle are left untouched, in every orientation.
+    fn restore_region(&mut self, rect: common::mxcfb_rect, data: &[u8]) -> Result<u32>;
 }
 
 pub mod draw;
 pub trait FramebufferDraw {
-    /// Draws `img` at y=top, x=left coordinates with 1:1 scaling
-    fn draw_image(&mut self, img: &image::RgbImage, top: usize, left: usize) -> common::mxcfb_rect;
+    /// Draws `img` at y=top, x=left coordinates with 1:1 scaling. Its pixels are turned into
+    /// their luminance, passed through `core::Framebuffer::tone_curve` and reduced to gray
+    /// levels with `dither` unless it is `Dither::None`.
+    fn draw_image(
+        &mut self,
+        img: &image::RgbImage,
+        top: usize,
+        left: usize,
+        dither: dither::Dither,
+    ) -> common::mxcfb_rect;
+    /// Draws `img`, which may be in any color type, scaled into `dest` with `filter` and
+    /// `fit`. Transparent pixels are alpha-blended onto the existing content, and the result
+    /// is reduced with `dither` unless it is `Dither::None`. Returns the region drawn.
+    fn draw_image_scaled(
+        &mut self,
+        img: &image::DynamicImage,
+        dest: common::mxcfb_rect,
+        filter: imaging::ScaleFilter,
+        fit: imaging::ImageFit,
+        dither: dither::Dither,
+    ) -> common::mxcfb_rect;
+    /// Draws `svg` scaled to fit in `dest`, centered and keeping its aspect ratio.
+    /// Nothing is drawn outside of `dest`. Returns the region that has been modified.
+    fn draw_svg(&mut self, svg: &svg::SvgImage, dest: common::mxcfb_rect) -> common::mxcfb_rect;
     /// Draws a straight line
     fn draw_line(
         &mut self,
@@ -50,7 +102,7 @@ pub trait FramebufferDraw {
         rad: usize,
         c: common::color,
     ) -> common::mxcfb_rect;
-    /// Fills a circle
+    /// Fills a circle, one scanline at a time
     fn fill_circle(
         &mut self,
         y: usize,
@@ -58,6 +110,51 @@ pub trait FramebufferDraw {
         rad: usize,
         c: common::color,
     ) -> common::mxcfb_rect;
+    /// Fills an ellipse centered at `(y, x)` with the vertical radius `rad_y` and the
+    /// horizontal radius `rad_x`, one scanline at a time
+    fn fill_ellipse(
+        &mut self,
+        y: usize,
+        x: usize,
+        rad_y: usize,
+        rad_x: usize,
+        c: common::color,
+    ) -> common::mxcfb_rect;
+    /// Draws the outline of the ellipse filled by `fill_ellipse` with `border_px` thickness.
+    /// The border grows inwards.
+    fn draw_ellipse(
+        &mut self,
+        y: usize,
+        x: usize,
+        rad_y: usize,
+        rad_x: usize,
+        border_px: usize,
+        c: common::color,
+    ) -> common::mxcfb_rect;
+    /// Draws the part of the circle of radius `rad` centered at `(y, x)` going clockwise from
+    /// `start_angle` to `end_angle`, in degrees from the 3 o'clock position, with `border_px`
+    /// thickness growing inwards
+    fn draw_arc(
+        &mut self,
+        y: usize,
+        x: usize,
+        rad: usize,
+        start_angle: f32,
+        end_angle: f32,
+        border_px: usize,
+        c: common::color,
+    ) -> common::mxcfb_rect;
+    /// Fills the slice of the circle of radius `rad` centered at `(y, x)` going clockwise from
+    /// `start_angle` to `end_angle`, in degrees from the 3 o'clock position
+    fn fill_pie(
+        &mut self,
+        y: usize,
+        x: usize,
+        rad: usize,
+        start_angle: f32,
+        end_angle: f32,
+        c: common::color,
+    ) -> common::mxcfb_rect;
     /// Draws a bezier curve begining at `startpt`, with control point `ctrlpt`, ending at `endpt` with `color`
     fn draw_bezier(
         &mut self,
@@ -67,16 +164,100 @@ pub trait FramebufferDraw {
         width: usize,
         v: common::color,
     ) -> common::mxcfb_rect;
-    /// Draws `text` at `(y, x)` with `color` using `scale`
+    /// Anti-aliased counterpart of `draw_line`, blending `v` into the existing content
+    /// according to how much of each pixel the line covers. Lines up to 1px wide are drawn
+    /// with Wu's algorithm, thicker ones get round caps. Coordinates may be fractional.
+    fn draw_line_antialiased(
+        &mut self,
+        y0: f32,
+        x0: f32,
+        y1: f32,
+        x1: f32,
+        width: f32,
+        v: common::color,
+    ) -> common::mxcfb_rect;
+    /// Anti-aliased counterpart of `draw_bezier`
+    fn draw_bezier_antialiased(
+        &mut self,
+        startpt: (f32, f32),
+        ctrlpt: (f32, f32),
+        endpt: (f32, f32),
+        width: f32,
+        v: common::color,
+    ) -> common::mxcfb_rect;
+    /// Draws the outline of `path` with an anti-aliased stroke of `width`.
+    /// Returns the region that has been modified.
+    fn stroke_path(&mut self, path: &path::Path, width: f32, v: common::color) -> common::mxcfb_rect;
+    /// Draws a smooth stroke through `points` whose width varies along its length, as
+    /// given by the width of each point (e.g. from the pen pressure, see
+    /// `ink::StrokePoint::from_pressure`). Returns the region that has been modified.
+    fn draw_ink_stroke(&mut self, points: &[ink::StrokePoint], v: common::color) -> common::mxcfb_rect;
+    /// Draws the outline of `path` with anti-aliased edges, with the caps, joins and
+    /// dashes of `style`. Returns the region that has been modified.
+    fn stroke_path_styled(
+        &mut self,
+        path: &path::Path,
+        style: &stroke::StrokeStyle,
+        v: common::color,
+    ) -> common::mxcfb_rect;
+    /// Draws the polyline through the `(x, y)` vertices `points` with anti-aliased edges,
+    /// with the caps, joins and dashes of `style`. A `closed` polyline has its last point
+    /// joined to the first one. Returns the region that has been modified.
+    fn draw_polyline(
+        &mut self,
+        points: &[(f32, f32)],
+        closed: bool,
+        style: &stroke::StrokeStyle,
+        v: common::color,
+    ) -> common::mxcfb_rect;
+    /// Fills the area enclosed by `path` with anti-aliased edges according to `rule`,
+    /// implicitly closing open subpaths. Returns the region that has been modified.
+    fn fill_path(
+        &mut self,
+        path: &path::Path,
+        rule: common::FillRule,
+        v: common::color,
+    ) -> common::mxcfb_rect;
+    /// Fills the polygon with the `(x, y)` vertices `points` according to `rule`, using
+    /// scanline rasterization. A pixel is filled if its center is inside the polygon.
+    fn fill_polygon(
+        &mut self,
+        points: &[(f32, f32)],
+        rule: common::FillRule,
+        v: common::color,
+    ) -> common::mxcfb_rect;
+    /// Paints the polygon with the `(x, y)` vertices `points` according to `rule` with
+    /// `style`. A pixel is painted if its center is inside the polygon.
+    fn fill_polygon_styled(
+        &mut self,
+        points: &[(f32, f32)],
+        rule: common::FillRule,
+        style: &fill::FillStyle,
+    ) -> common::mxcfb_rect;
+    /// Draws `text` at `(y, x)` with `color` using `font` from `core::Framebuffer::fonts`
+    /// at `size`. The color goes through `core::Framebuffer::tone_curve` and the glyphs are
+    /// antialiased against `core::Framebuffer::text_background`, or blended onto the
+    /// existing content when it is `None`.
     fn draw_text(
         &mut self,
         y: usize,
         x: usize,
         text: String,
+        font: fonts::FontHandle,
         size: usize,
         col: common::color,
         dryrun: bool,
     ) -> common::mxcfb_rect;
+    /// Lays out `text` inside `rect` with word wrapping, explicit newlines, alignment,
+    /// line height and ellipsis truncation as described by `style`, then draws it unless
+    /// `dryrun` is set. The returned layout contains the positioned lines and the drawn region.
+    fn draw_paragraph(
+        &mut self,
+        rect: common::mxcfb_rect,
+        text: &str,
+        style: &text::ParagraphStyle,
+        dryrun: bool,
+    ) -> text::TextLayout;
     /// Draws a 1px border rectangle of `height` and `width` at `(y, x)` with `border_px` border thickness
     fn draw_rect(
         &mut self,
@@ -87,34 +268,124 @@ pub trait FramebufferDraw {
         border_px: usize,
         c: common::color,
     );
+    /// Draws the outline of the rectangle of `height` and `width` at `(y, x)` with the width,
+    /// joins and dashes of `style`, without anti-aliasing. The border is centered on the
+    /// edges of the rectangle. Returns the region that has been modified.
+    fn draw_rect_styled(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        style: &stroke::StrokeStyle,
+        c: common::color,
+    ) -> common::mxcfb_rect;
+    /// Draws the outline of a rectangle of `height` and `width` at `(y, x)` whose corners
+    /// are rounded with `radius`. The `border_px` thick border grows inwards.
+    fn draw_rounded_rect(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        radius: usize,
+        border_px: usize,
+        c: common::color,
+    ) -> common::mxcfb_rect;
+    /// Fills a rectangle of `height` and `width` at `(y, x)` whose corners are rounded with `radius`
+    fn fill_rounded_rect(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        radius: usize,
+        c: common::color,
+    ) -> common::mxcfb_rect;
+    /// Fills rectangle of `height` and `width` at `(y, x)` with the gray level of `c`
+    /// rendered with `dither`, for shades the waveform in use can't display
+    fn fill_rect_dithered(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        c: common::color,
+        dither: dither::Dither,
+    ) -> common::mxcfb_rect;
+    /// Paints the rectangle of `height` and `width` at `(y, x)` with `style`.
+    /// Returns the region that has been modified.
+    fn fill_rect_styled(
+        &mut self,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        style: &fill::FillStyle,
+    ) -> common::mxcfb_rect;
     /// Fills rectangle of `height` and `width` at `(y, x)`
     fn fill_rect(&mut self, y: usize, x: usize, height: usize, width: usize, c: common::color);
-    /// Clears the framebuffer however does not perform a refresh
+    /// Clears the framebuffer, only within the clip rectangle when one is set, however does
+    /// not perform a refresh
     fn clear(&mut self);
 }
 
+pub mod backend;
+/// The display device underneath a `core::Framebuffer`. It provides the pixel memory
+/// that `FramebufferIO` and `FramebufferDraw` write into, and receives the update
+/// requests issued by `FramebufferRefresh`.
+pub trait FramebufferBackend: Send + Sync {
+    /// Returns a pointer to the beginning of the pixel memory
+    fn frame_ptr(&self) -> *mut u8;
+    /// Returns the length of the pixel memory in bytes
+    fn frame_len(&self) -> usize;
+    /// Returns the FixScreeninfo describing the pixel memory
+    fn fix_screeninfo(&self) -> screeninfo::FixScreeninfo;
+    /// Returns the VarScreeninfo describing the pixel memory
+    fn var_screeninfo(&self) -> screeninfo::VarScreeninfo;
+    /// Toggles the EPD Controller
+    fn set_epdc_access(&self, state: bool) -> Result<()>;
+    /// Toggles autoupdate mode
+    fn set_autoupdate_mode(&self, mode: u32) -> Result<()>;
+    /// Toggles update scheme
+    fn set_update_scheme(&self, scheme: u32) -> Result<()>;
+    /// Submits `update` to the display
+    fn send_update(&self, update: &mxcfb::mxcfb_update_data) -> Result<()>;
+    /// Blocks until the update with `markerdata.update_marker` has been completed and fills
+    /// in `markerdata.collision_test`
+    fn wait_update_complete(&self, markerdata: &mut mxcfb::mxcfb_update_marker_data)
+        -> Result<()>;
+    /// Reconfigures the device with `var_screen_info`. `var_screeninfo()` and
+    /// `fix_screeninfo()` return the resulting configuration afterwards.
+    fn put_var_screeninfo(&mut self, var_screen_info: &screeninfo::VarScreeninfo) -> Result<()>;
+    /// Reads `len` entries of the colormap starting at index `start`
+    fn colormap(&self, start: u32, len: u32) -> Result<cmap::Colormap>;
+    /// Overwrites the entries of the colormap covered by `colormap`
+    fn put_colormap(&self, colormap: &cmap::Colormap) -> Result<()>;
+}
+
 use std;
 pub mod core;
 pub trait FramebufferBase<'a> {
     /// Creates a new instance of Framebuffer
-    fn new(path_to_device: &str) -> core::Framebuffer;
+    fn new(path_to_device: &str) -> Result<core::Framebuffer>;
     /// Toggles the EPD Controller (see https://wiki.mobileread.com/wiki/EPD_controller)
-    fn set_epdc_access(&mut self, state: bool);
+    fn set_epdc_access(&mut self, state: bool) -> Result<()>;
     /// Toggles autoupdate mode
-    fn set_autoupdate_mode(&mut self, mode: u32);
+    fn set_autoupdate_mode(&mut self, mode: u32) -> Result<()>;
     /// Toggles update scheme
-    fn set_update_scheme(&mut self, scheme: u32);
+    fn set_update_scheme(&mut self, scheme: u32) -> Result<()>;
     /// Creates a FixScreeninfo struct and fills it using ioctl
-    fn get_fix_screeninfo(device: &std::fs::File) -> screeninfo::FixScreeninfo;
+    fn get_fix_screeninfo(device: &std::fs::File) -> Result<screeninfo::FixScreeninfo>;
     /// Creates a VarScreeninfo struct and fills it using ioctl
-    fn get_var_screeninfo(device: &std::fs::File) -> screeninfo::VarScreeninfo;
+    fn get_var_screeninfo(device: &std::fs::File) -> Result<screeninfo::VarScreeninfo>;
     /// Makes the proper ioctl call to set the VarScreenInfo.
     /// You must first update the contents of self.var_screen_info
     /// and then call this function.
     fn put_var_screeninfo(
         device: &std::fs::File,
         var_screen_info: &mut screeninfo::VarScreeninfo,
-    ) -> bool;
+    ) -> Result<()>;
 }
 
 pub mod refresh;
@@ -128,7 +399,7 @@ pub trait FramebufferRefresh {
         dither_mode: common::dither_mode,
         quant_bit: i32,
         wait_completion: bool,
-    ) -> u32;
+    ) -> Result<u32>;
 
     /// Refreshes the given `region` with the provided parameters. If `mode` is `DryRun` or
     /// `Wait`, this function won't return before the `DryRun`'s collision_test or
@@ -166,11 +437,63 @@ pub trait FramebufferRefresh {
         dither_mode: common::dither_mode,
         quant_bit: i32,
         force_full_refresh: bool,
-    ) -> u32;
+    ) -> Result<u32>;
+
+    /// Same as `partial_refresh`, with the PxP additionally inverting the region or forcing
+    /// it to black and white as requested by `pxp_flags`
+    fn partial_refresh_with_flags(
+        &self,
+        region: &common::mxcfb_rect,
+        mode: refresh::PartialRefreshMode,
+        waveform_mode: common::waveform_mode,
+        temperature: common::display_temp,
+        dither_mode: common::dither_mode,
+        quant_bit: i32,
+        force_full_refresh: bool,
+        pxp_flags: refresh::PxpFlags,
+    ) -> Result<u32>;
+
+    /// Shows `region` with its gray levels inverted by the EPDC rather than by rewriting
+    /// the framebuffer memory, so the next refresh of that area brings the original content
+    /// back. Inverted twice in night mode. Returns the marker.
+    fn invert_region(
+        &self,
+        region: &common::mxcfb_rect,
+        waveform_mode: common::waveform_mode,
+        wait_completion: bool,
+    ) -> Result<u32>;
 
     /// Takes a marker returned by `partial_refresh` and blocks until that
     /// refresh has been reflected on the display.
     /// Returns the collusion_test result which is supposed to be
     /// related to the collusion information.
-    fn wait_refresh_complete(&self, marker: u32) -> u32;
+    fn wait_refresh_complete(&self, marker: u32) -> Result<u32>;
+}
+
+pub mod buffering;
+pub trait FramebufferBuffering {
+    /// Redirects `FramebufferIO` and `FramebufferDraw` into a back buffer located right
+    /// after the visible frame in the framebuffer memory, starting with a copy of what
+    /// is currently displayed. Fails if the framebuffer memory can't hold a second frame.
+    fn enable_back_buffer(&mut self) -> Result<()>;
+    /// Sends drawing back to the visible frame
+    fn disable_back_buffer(&mut self);
+    /// Returns true if drawing currently goes to the back buffer
+    fn back_buffer_enabled(&self) -> bool;
+    /// Copies `region` of the visible frame into the back buffer, discarding what has been
+    /// drawn there since the last `present`
+    fn sync_back_buffer(&mut self, region: &common::mxcfb_rect) -> Result<()>;
+    /// Shows `region` of the back buffer on the display in a single update using the EPDC's
+    /// alternate buffer support, then copies it into the visible frame so that later
+    /// refreshes pick it up. If `wait_completion` is set to true, doesn't return before the
+    /// update has been completed. Returns the marker.
+    fn present(
+        &self,
+        region: &common::mxcfb_rect,
+        waveform_mode: common::waveform_mode,
+        temperature: common::display_temp,
+        dither_mode: common::dither_mode,
+        quant_bit: i32,
+        wait_completion: bool,
+    ) -> Result<u32>;
 }
@@ -0,0 +1,267 @@
+use std::f32::consts::PI;
+
+use framebuffer::raster;
+
+/// A segment of a `Path`. Points are `(x, y)`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    MoveTo((f32, f32)),
+    LineTo((f32, f32)),
+    /// Quadratic bezier curve with a control point, ending at the second point
+    QuadTo((f32, f32), (f32, f32)),
+    /// Cubic bezier curve with two control points, ending at the third point
+    CubicTo((f32, f32), (f32, f32), (f32, f32)),
+    /// Closes the current subpath with a line back to its first point
+    Close,
+}
+
+/// A polyline approximating a subpath, as produced by `Path::flatten`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<(f32, f32)>,
+    pub closed: bool,
+}
+
+/// A vector shape made of lines and curves, rendered with `FramebufferDraw::stroke_path`
+/// and `FramebufferDraw::fill_path`. Points are `(x, y)` and may be fractional.
+/// The builder methods can be chained, e.g. `path.move_to(a).line_to(b).close()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+    /// First point of the current subpath
+    start: Option<(f32, f32)>,
+    current: Option<(f32, f32)>,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// The point the next segment starts from, `None` until the first `move_to`
+    pub fn current_point(&self) -> Option<(f32, f32)> {
+        self.current
+    }
+
+    /// Starts a new subpath at `pt`
+    pub fn move_to(&mut self, pt: (f32, f32)) -> &mut Path {
+        self.segments.push(PathSegment::MoveTo(pt));
+        self.start = Some(pt);
+        self.current = Some(pt);
+        self
+    }
+
+    /// Adds a straight line to `pt`. Without a current point, this is a `move_to`.
+    pub fn line_to(&mut self, pt: (f32, f32)) -> &mut Path {
+        if self.current.is_none() {
+            return self.move_to(pt);
+        }
+        self.segments.push(PathSegment::LineTo(pt));
+        self.current = Some(pt);
+        self
+    }
+
+    /// Adds a quadratic bezier curve to `pt` with control point `ctrl`
+    pub fn quad_to(&mut self, ctrl: (f32, f32), pt: (f32, f32)) -> &mut Path {
+        if self.current.is_none() {
+            self.move_to(ctrl);
+        }
+        self.segments.push(PathSegment::QuadTo(ctrl, pt));
+        self.current = Some(pt);
+        self
+    }
+
+    /// Adds a cubic bezier curve to `pt` with control points `ctrl1` and `ctrl2`
+    pub fn cubic_to(&mut self, ctrl1: (f32, f32), ctrl2: (f32, f32), pt: (f32, f32)) -> &mut Path {
+        if self.current.is_none() {
+            self.move_to(ctrl1);
+        }
+        self.segments.push(PathSegment::CubicTo(ctrl1, ctrl2, pt));
+        self.current = Some(pt);
+        self
+    }
+
+    /// Adds an elliptical arc to `pt`, with the same parameters as the SVG `A` command:
+    /// radii `rx` and `ry`, the ellipse being rotated by `rotation` radians. Out of the four
+    /// possible arcs, `large_arc` picks the one spanning more than 180 degrees and `sweep`
+    /// the one drawn in the direction of increasing angles (clockwise on screen).
+    /// The arc is approximated with cubic bezier curves.
+    pub fn arc_to(
+        &mut self,
+        rx: f32,
+        ry: f32,
+        rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        pt: (f32, f32),
+    ) -> &mut Path {
+        let from = match self.current {
+            Some(from) => from,
+            None => return self.move_to(pt),
+        };
+        if from == pt {
+            return self;
+        }
+        let (mut rx, mut ry) = (rx.abs(), ry.abs());
+        if rx == 0.0 || ry == 0.0 {
+            return self.line_to(pt);
+        }
+
+        // Conversion to center parameterization, see the appendix F.6.5 of the SVG specification
+        let (sin_phi, cos_phi) = rotation.sin_cos();
+        let (dx2, dy2) = ((from.0 - pt.0) / 2.0, (from.1 - pt.1) / 2.0);
+        let x1p = cos_phi * dx2 + sin_phi * dy2;
+        let y1p = -sin_phi * dx2 + cos_phi * dy2;
+
+        // Radii too small to join both points are scaled up
+        let lambda = (x1p * x1p) / (rx * rx) + (y1p * y1p) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+
+        let num = rx * rx * ry * ry - rx * rx * y1p * y1p - ry * ry * x1p * x1p;
+        let den = rx * rx * y1p * y1p + ry * ry * x1p * x1p;
+        let mut coef = (num / den).max(0.0).sqrt();
+        if large_arc == sweep {
+            coef = -coef;
+        }
+        let cxp = coef * rx * y1p / ry;
+        let cyp = -coef * ry * x1p / rx;
+        let cx = cos_phi * cxp - sin_phi * cyp + (from.0 + pt.0) / 2.0;
+        let cy = sin_phi * cxp + cos_phi * cyp + (from.1 + pt.1) / 2.0;
+
+        let angle = |u: (f32, f32), v: (f32, f32)| (u.0 * v.1 - u.1 * v.0).atan2(u.0 * v.0 + u.1 * v.1);
+        let u = ((x1p - cxp) / rx, (y1p - cyp) / ry);
+        let v = ((-x1p - cxp) / rx, (-y1p - cyp) / ry);
+        let theta = angle((1.0, 0.0), u);
+        let mut delta = angle(u, v);
+        if !sweep && delta > 0.0 {
+            delta -= 2.0 * PI;
+        } else if sweep && delta < 0.0 {
+            delta += 2.0 * PI;
+        }
+
+        // One cubic per quarter of ellipse at most
+        let count = (delta.abs() / (PI / 2.0)).ceil().max(1.0) as usize;
+        let step = delta / count as f32;
+        let k = 4.0 / 3.0 * (step / 4.0).tan();
+        let point = |t: f32| {
+            let (sin_t, cos_t) = t.sin_cos();
+            (
+                cx + rx * cos_t * cos_phi - ry * sin_t * sin_phi,
+                cy + rx * cos_t * sin_phi + ry * sin_t * cos_phi,
+            )
+        };
+        let derivative = |t: f32| {
+            let (sin_t, cos_t) = t.sin_cos();
+            (
+                -rx * sin_t * cos_phi - ry * cos_t * sin_phi,
+                -rx * sin_t * sin_phi + ry * cos_t * cos_phi,
+            )
+        };
+        for i in 0..count {
+            let (t1, t2) = (theta + step * i as f32, theta + step * (i + 1) as f32);
+            let (p1, d1) = (point(t1), derivative(t1));
+            let (p2, d2) = (point(t2), derivative(t2));
+            let end = if i + 1 == count { pt } else { p2 };
+            self.cubic_to(
+                (p1.0 + k * d1.0, p1.1 + k * d1.1),
+                (p2.0 - k * d2.0, p2.1 - k * d2.1),
+                end,
+            );
+        }
+        self
+    }
+
+    /// Closes the current subpath. The next segment starts from its first point.
+    pub fn close(&mut self) -> &mut Path {
+        if self.current.is_some() {
+            self.segments.push(PathSegment::Close);
+            self.current = self.start;
+        }
+        self
+    }
+
+    /// Returns a copy of the path with every point, control points included, passed
+    /// through `map`. Curves are only mapped exactly by affine transformations.
+    pub fn transformed<F: Fn((f32, f32)) -> (f32, f32)>(&self, map: F) -> Path {
+        let segments = self
+            .segments
+            .iter()
+            .map(|segment| match *segment {
+                PathSegment::MoveTo(pt) => PathSegment::MoveTo(map(pt)),
+                PathSegment::LineTo(pt) => PathSegment::LineTo(map(pt)),
+                PathSegment::QuadTo(ctrl, pt) => PathSegment::QuadTo(map(ctrl), map(pt)),
+                PathSegment::CubicTo(ctrl1, ctrl2, pt) => {
+                    PathSegment::CubicTo(map(ctrl1), map(ctrl2), map(pt))
+                }
+                PathSegment::Close => PathSegment::Close,
+            }).collect();
+        Path {
+            segments,
+            start: self.start.map(&map),
+            current: self.current.map(&map),
+        }
+    }
+
+    /// Approximates every subpath with a polyline
+    pub fn flatten(&self) -> Vec<Polyline> {
+        let mut polylines: Vec<Polyline> = Vec::new();
+        let mut current = Polyline {
+            points: Vec::new(),
+            closed: false,
+        };
+        let mut last = (0.0, 0.0);
+        for segment in &self.segments {
+            match *segment {
+                PathSegment::MoveTo(pt) => {
+                    if current.points.len() > 1 {
+                        polylines.push(current);
+                    }
+                    current = Polyline {
+                        points: vec![pt],
+                        closed: false,
+                    };
+                    last = pt;
+                }
+                PathSegment::LineTo(pt) => {
+                    current.points.push(pt);
+                    last = pt;
+                }
+                PathSegment::QuadTo(ctrl, pt) => {
+                    current
+                        .points
+                        .extend(raster::flatten_quadratic(last, ctrl, pt).into_iter().skip(1));
+                    last = pt;
+                }
+                PathSegment::CubicTo(ctrl1, ctrl2, pt) => {
+                    current
+                        .points
+                        .extend(raster::flatten_cubic(last, ctrl1, ctrl2, pt).into_iter().skip(1));
+                    last = pt;
+                }
+                PathSegment::Close => {
+                    let start = current.points.first().cloned();
+                    if let Some(start) = start {
+                        current.closed = true;
+                        polylines.push(current);
+                        current = Polyline {
+                            points: vec![start],
+                            closed: false,
+                        };
+                        last = start;
+                    }
+                }
+            }
+        }
+        if current.points.len() > 1 {
+            polylines.push(current);
+        }
+        polylines
+    }
+}
@@ -0,0 +1,416 @@
+use std::f32::consts::PI;
+
+use framebuffer::common::{color, mxcfb_rect, FillRule};
+use framebuffer::core;
+use framebuffer::FramebufferIO;
+
+/// Number of sub-scanlines sampled per row when filling polygons
+const FILL_SUBSAMPLES: usize = 4;
+
+struct Edge {
+    top: f32,
+    bottom: f32,
+    /// Horizontal position at `top`
+    x: f32,
+    /// Horizontal distance covered per unit of height
+    slope: f32,
+    /// 1 if the edge goes down, -1 if it goes up
+    direction: i32,
+}
+
+/// The non-horizontal edges of a set of polygons, sorted by their top, from which the
+/// spans covered on each scanline are computed. Points are `(x, y)`.
+pub struct EdgeTable {
+    edges: Vec<Edge>,
+    top: f32,
+    bottom: f32,
+}
+
+impl EdgeTable {
+    /// Collects the edges of `polygons`, each of them being implicitly closed
+    pub fn new(polygons: &[Vec<(f32, f32)>]) -> EdgeTable {
+        let mut edges = Vec::new();
+        for polygon in polygons {
+            for i in 0..polygon.len() {
+                let (p, q) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+                if p.1 == q.1 {
+                    continue;
+                }
+                let (upper, lower, direction) = if p.1 < q.1 { (p, q, 1) } else { (q, p, -1) };
+                edges.push(Edge {
+                    top: upper.1,
+                    bottom: lower.1,
+                    x: upper.0,
+                    slope: (lower.0 - upper.0) / (lower.1 - upper.1),
+                    direction,
+                });
+            }
+        }
+        edges.sort_by(|a, b| a.top.partial_cmp(&b.top).unwrap_or(::std::cmp::Ordering::Equal));
+        EdgeTable {
+            top: edges.iter().fold(::std::f32::MAX, |m, e| m.min(e.top)),
+            bottom: edges.iter().fold(::std::f32::MIN, |m, e| m.max(e.bottom)),
+            edges,
+        }
+    }
+
+    /// Vertical extent of the polygons as `(top, bottom)`, `None` if they enclose nothing
+    pub fn vertical_bounds(&self) -> Option<(f32, f32)> {
+        if self.edges.is_empty() {
+            None
+        } else {
+            Some((self.top, self.bottom))
+        }
+    }
+
+    /// Returns the horizontal spans inside the polygons on the horizontal line at `y`,
+    /// as `(start, end)` pairs ordered from left to right
+    pub fn spans(&self, y: f32, rule: FillRule) -> Vec<(f32, f32)> {
+        let mut crossings: Vec<(f32, i32)> = self
+            .edges
+            .iter()
+            .take_while(|e| e.top <= y)
+            .filter(|e| y < e.bottom)
+            .map(|e| (e.x + (y - e.top) * e.slope, e.direction))
+            .collect();
+        crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+        let mut spans = Vec::new();
+        let mut winding = 0;
+        let mut span_start = 0.0;
+        for (x, direction) in crossings {
+            let was_inside = rule.is_inside(winding);
+            winding += direction;
+            let inside = rule.is_inside(winding);
+            if !was_inside && inside {
+                span_start = x;
+            } else if was_inside && !inside {
+                spans.push((span_start, x));
+            }
+        }
+        spans
+    }
+}
+
+/// Converts `v` to an integer within `[min, max]`, including when it is too large for an `i32`
+#[inline]
+fn clamp(v: f32, min: i32, max: i32) -> i32 {
+    v.max(min as f32).min(max as f32) as i32
+}
+
+/// Per-pixel coverage of a shape over a rectangular area. Shapes are rasterized into the
+/// mask first and then blended onto the framebuffer in a single pass, so that overlapping
+/// parts (e.g. the joints of a polyline) don't get blended twice.
+/// Points are `(x, y)` with pixel centers on integer coordinates.
+pub struct CoverageMask {
+    top: i32,
+    left: i32,
+    height: i32,
+    width: i32,
+    coverage: Vec<f32>,
+}
+
+impl CoverageMask {
+    /// Creates an empty mask covering `height` by `width` pixels from `(top, left)`
+    pub fn new(top: i32, left: i32, height: i32, width: i32) -> CoverageMask {
+        let (height, width) = (height.max(0), width.max(0));
+        CoverageMask {
+            top,
+            left,
+            height,
+            width,
+            coverage: vec![0.0; (height * width) as usize],
+        }
+    }
+
+    /// Creates an empty mask large enough for a stroke of `width` through `points`, limited
+    /// to `area` (see `core::Framebuffer::drawable_rect`) so that shapes reaching far off
+    /// the display don't allocate more than it
+    pub fn around(points: &[(f32, f32)], width: f32, area: &mxcfb_rect) -> CoverageMask {
+        let margin = (width / 2.0).ceil() + 2.0;
+        let min_x = points.iter().fold(::std::f32::MAX, |m, p| m.min(p.0)).floor() - margin;
+        let min_y = points.iter().fold(::std::f32::MAX, |m, p| m.min(p.1)).floor() - margin;
+        let max_x = points.iter().fold(::std::f32::MIN, |m, p| m.max(p.0)).ceil() + margin;
+        let max_y = points.iter().fold(::std::f32::MIN, |m, p| m.max(p.1)).ceil() + margin;
+        let (area_top, area_left) = (area.top as i32, area.left as i32);
+        let area_bottom = area_top + area.height as i32;
+        let area_right = area_left + area.width as i32;
+        let top = clamp(min_y, area_top, area_bottom);
+        let left = clamp(min_x, area_left, area_right);
+        CoverageMask::new(
+            top,
+            left,
+            clamp(max_y + 1.0, area_top, area_bottom) - top,
+            clamp(max_x + 1.0, area_left, area_right) - left,
+        )
+    }
+
+    /// Raises the coverage of `(y, x)` to `value` unless it is already higher
+    #[inline]
+    pub fn add(&mut self, y: i32, x: i32, value: f32) {
+        let (row, col) = (y - self.top, x - self.left);
+        if row < 0 || col < 0 || row >= self.height || col >= self.width {
+            return;
+        }
+        let cell = &mut self.coverage[(row * self.width + col) as usize];
+        if value > *cell {
+            *cell = value.min(1.0);
+        }
+    }
+
+    /// Rasterizes a 1px wide line from `p0` to `p1` with Xiaolin Wu's algorithm
+    pub fn wu_line(&mut self, p0: (f32, f32), p1: (f32, f32)) {
+        let (mut x0, mut y0) = p0;
+        let (mut x1, mut y1) = p1;
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        if steep {
+            ::std::mem::swap(&mut x0, &mut y0);
+            ::std::mem::swap(&mut x1, &mut y1);
+        }
+        if x0 > x1 {
+            ::std::mem::swap(&mut x0, &mut x1);
+            ::std::mem::swap(&mut y0, &mut y1);
+        }
+        let dx = x1 - x0;
+        let gradient = if dx == 0.0 { 1.0 } else { (y1 - y0) / dx };
+
+        let plot = |mask: &mut CoverageMask, x: i32, y: i32, c: f32| {
+            if steep {
+                mask.add(x, y, c)
+            } else {
+                mask.add(y, x, c)
+            }
+        };
+
+        // Endpoints are weighted by how much of their pixel the line spans
+        let endpoint = |mask: &mut CoverageMask, x: f32, y: f32, first: bool| -> (i32, f32) {
+            let xend = x.round();
+            let yend = y + gradient * (xend - x);
+            let xgap = if first {
+                1.0 - (x + 0.5).fract()
+            } else {
+                (x + 0.5).fract()
+            };
+            let ypx = yend.floor();
+            plot(mask, xend as i32, ypx as i32, (1.0 - yend.fract()) * xgap);
+            plot(mask, xend as i32, ypx as i32 + 1, yend.fract() * xgap);
+            (xend as i32, yend)
+        };
+        let (xpx1, yend1) = endpoint(self, x0, y0, true);
+        let (xpx2, _) = endpoint(self, x1, y1, false);
+
+        // Only the part of the line within the mask is walked
+        let (first, end) = if steep {
+            (self.top, self.top + self.height)
+        } else {
+            (self.left, self.left + self.width)
+        };
+        let start = (xpx1 + 1).max(first);
+        let mut intery = yend1 + gradient * (start - xpx1) as f32;
+        for x in start..xpx2.min(end) {
+            let ypx = intery.floor();
+            plot(self, x, ypx as i32, 1.0 - intery.fract());
+            plot(self, x, ypx as i32 + 1, intery.fract());
+            intery += gradient;
+        }
+    }
+
+    /// Rasterizes a segment from `p0` to `p1` of `width` with round caps. The coverage of
+    /// each pixel is derived from the distance between its center and the segment.
+    pub fn stroke_segment(&mut self, p0: (f32, f32), p1: (f32, f32), width: f32) {
+        let half = width / 2.0;
+        let reach = half + 1.0;
+        // Only the part of the segment within the mask is scanned
+        let (last_row, last_col) = (self.top + self.height - 1, self.left + self.width - 1);
+        let top = clamp((p0.1.min(p1.1) - reach).floor(), self.top, last_row + 1);
+        let bottom = clamp((p0.1.max(p1.1) + reach).ceil(), self.top - 1, last_row);
+        let left = clamp((p0.0.min(p1.0) - reach).floor(), self.left, last_col + 1);
+        let right = clamp((p0.0.max(p1.0) + reach).ceil(), self.left - 1, last_col);
+
+        let (dx, dy) = (p1.0 - p0.0, p1.1 - p0.1);
+        let len2 = dx * dx + dy * dy;
+        for y in top..=bottom {
+            for x in left..=right {
+                let (px, py) = (x as f32 - p0.0, y as f32 - p0.1);
+                let t = if len2 == 0.0 {
+                    0.0
+                } else {
+                    ((px * dx + py * dy) / len2).max(0.0).min(1.0)
+                };
+                let (ex, ey) = (px - t * dx, py - t * dy);
+                let distance = (ex * ex + ey * ey).sqrt();
+                let c = (half + 0.5 - distance).max(0.0).min(1.0);
+                if c > 0.0 {
+                    self.add(y, x, c);
+                }
+            }
+        }
+    }
+
+    /// Rasterizes a polyline through `points` with a stroke of `width`. Strokes up to 1px
+    /// wide are drawn with Wu's algorithm.
+    pub fn stroke_polyline(&mut self, points: &[(f32, f32)], width: f32) {
+        if points.len() == 1 {
+            self.stroke_segment(points[0], points[0], width);
+        }
+        for pair in points.windows(2) {
+            if width <= 1.0 {
+                self.wu_line(pair[0], pair[1]);
+            } else {
+                self.stroke_segment(pair[0], pair[1], width);
+            }
+        }
+    }
+
+    /// Rasterizes the area enclosed by `polygons` according to `rule`. Every polygon is
+    /// implicitly closed. Each row is sampled along a few sub-scanlines, and the horizontal
+    /// coverage of the spans is exact.
+    pub fn fill_polygons(&mut self, polygons: &[Vec<(f32, f32)>], rule: FillRule) {
+        let edges = EdgeTable::new(polygons);
+        let mut row_coverage = vec![0.0f32; self.width as usize];
+        for row in 0..self.height {
+            let y = self.top + row;
+            for v in row_coverage.iter_mut() {
+                *v = 0.0;
+            }
+            for sample in 0..FILL_SUBSAMPLES {
+                let sy = y as f32 - 0.5 + (sample as f32 + 0.5) / FILL_SUBSAMPLES as f32;
+                for (x0, x1) in edges.spans(sy, rule) {
+                    self.accumulate_span(&mut row_coverage, x0, x1);
+                }
+            }
+            for col in 0..self.width {
+                let value = row_coverage[col as usize];
+                if value > 0.0 {
+                    self.add(y, self.left + col, value);
+                }
+            }
+        }
+    }
+
+    /// Adds the coverage of the span from `x0` to `x1` on one sub-scanline to `row_coverage`
+    fn accumulate_span(&self, row_coverage: &mut [f32], x0: f32, x1: f32) {
+        let first = ((x0 + 0.5).floor() as i32).max(self.left);
+        let last = ((x1 + 0.5).floor() as i32).min(self.left + self.width - 1);
+        for x in first..=last {
+            let overlap = x1.min(x as f32 + 0.5) - x0.max(x as f32 - 0.5);
+            if overlap > 0.0 {
+                row_coverage[(x - self.left) as usize] += overlap / FILL_SUBSAMPLES as f32;
+            }
+        }
+    }
+
+    /// Blends `c` onto the framebuffer, weighted by the coverage of each pixel.
+    /// Returns the region that has been modified.
+    pub fn composite(&self, fb: &mut core::Framebuffer, c: color) -> mxcfb_rect {
+        let (fb_height, fb_width) = fb.dimensions();
+        let (mut min_y, mut min_x, mut max_y, mut max_x) = (i32::max_value(), i32::max_value(), -1, -1);
+        for row in 0..self.height {
+            let y = self.top + row;
+            if y < 0 || y >= fb_height as i32 {
+                continue;
+            }
+            for col in 0..self.width {
+                let x = self.left + col;
+                let coverage = self.coverage[(row * self.width + col) as usize];
+                if coverage <= 0.0 || x < 0 || x >= fb_width as i32 {
+                    continue;
+                }
+                let (yu, xu) = (y as usize, x as usize);
+                if !fb.is_drawable(yu, xu) {
+                    continue;
+                }
+                let blended = if coverage >= 1.0 {
+                    c
+                } else {
+                    fb.read_pixel(yu, xu).blend(c, coverage)
+                };
+                fb.write_pixel(yu, xu, blended);
+                min_y = min_y.min(y);
+                min_x = min_x.min(x);
+                max_y = max_y.max(y);
+                max_x = max_x.max(x);
+            }
+        }
+
+        if max_y < 0 {
+            return mxcfb_rect::invalid();
+        }
+        mxcfb_rect {
+            top: min_y as u32,
+            left: min_x as u32,
+            height: (max_y - min_y + 1) as u32,
+            width: (max_x - min_x + 1) as u32,
+        }
+    }
+}
+
+/// Approximates the quadratic bezier curve from `p0` to `p2` with control point `p1`
+/// with a polyline whose segments are a few pixels long
+pub fn flatten_quadratic(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32)) -> Vec<(f32, f32)> {
+    let distance = |a: (f32, f32), b: (f32, f32)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+    let steps = ((distance(p0, p1) + distance(p1, p2)) / 3.0).ceil().max(1.0) as usize;
+    (0..=steps)
+        .map(|i| {
+            let t = i as f32 / steps as f32;
+            let mt = 1.0 - t;
+            (
+                mt * mt * p0.0 + 2.0 * mt * t * p1.0 + t * t * p2.0,
+                mt * mt * p0.1 + 2.0 * mt * t * p1.1 + t * t * p2.1,
+            )
+        }).collect()
+}
+
+/// Approximates the cubic bezier curve from `p0` to `p3` with control points `p1` and `p2`
+/// with a polyline whose segments are a few pixels long
+pub fn flatten_cubic(
+    p0: (f32, f32),
+    p1: (f32, f32),
+    p2: (f32, f32),
+    p3: (f32, f32),
+) -> Vec<(f32, f32)> {
+    let distance = |a: (f32, f32), b: (f32, f32)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+    let steps = ((distance(p0, p1) + distance(p1, p2) + distance(p2, p3)) / 3.0)
+        .ceil()
+        .max(1.0) as usize;
+    (0..=steps)
+        .map(|i| {
+            let t = i as f32 / steps as f32;
+            let mt = 1.0 - t;
+            let (a, b, c, d) = (mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t);
+            (
+                a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            )
+        }).collect()
+}
+
+/// Approximates the disc of `radius` around `center` with a polygon
+pub fn disc(center: (f32, f32), radius: f32) -> Vec<(f32, f32)> {
+    let sides = (PI * radius).ceil().max(8.0) as usize;
+    (0..sides)
+        .map(|i| {
+            let (sin, cos) = (2.0 * PI * i as f32 / sides as f32).sin_cos();
+            (center.0 + radius * cos, center.1 + radius * sin)
+        }).collect()
+}
+
+/// Twice the signed area of `polygon`, positive when it winds clockwise on screen
+pub fn signed_area(polygon: &[(f32, f32)]) -> f32 {
+    let n = polygon.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+            a.0 * b.1 - b.0 * a.1
+        }).sum()
+}
+
+/// Reverses the polygons winding counterclockwise, so that filling them all with
+/// `FillRule::NonZero` paints their union
+pub fn wind_clockwise(polygons: &mut [Vec<(f32, f32)>]) {
+    for polygon in polygons.iter_mut() {
+        if signed_area(polygon) < 0.0 {
+            polygon.reverse();
+        }
+    }
+}
@@ -1,8 +1,6 @@
-use libc;
-
-use std::os::unix::io::AsRawFd;
 use std::sync::atomic::Ordering;
 
+use error::Result;
 use framebuffer;
 use framebuffer::common;
 use framebuffer::core;
@@ -14,6 +12,36 @@ pub enum PartialRefreshMode {
     Wait,
 }
 
+/// Transformations the PxP applies to the pixels of a refresh on their way to the display,
+/// leaving the framebuffer memory untouched
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PxpFlags {
+    /// Shows black as white and white as black
+    pub invert: bool,
+    /// Shows every pixel as either black or white
+    pub monochrome: bool,
+    /// Maps the gray levels through the colormap installed with
+    /// `core::Framebuffer::set_colormap`
+    pub use_cmap: bool,
+}
+
+impl PxpFlags {
+    /// The `EPDC_FLAG_*` bits to put in `mxcfb_update_data::flags`
+    pub fn bits(self) -> u32 {
+        let mut bits = 0;
+        if self.invert {
+            bits |= common::EPDC_FLAG_ENABLE_INVERSION;
+        }
+        if self.monochrome {
+            bits |= common::EPDC_FLAG_FORCE_MONOCHROME;
+        }
+        if self.use_cmap {
+            bits |= common::EPDC_FLAG_USE_CMAP;
+        }
+        bits
+    }
+}
+
 impl<'a> framebuffer::FramebufferRefresh for core::Framebuffer<'a> {
     fn full_refresh(
         &self,
@@ -22,7 +50,7 @@ impl<'a> framebuffer::FramebufferRefresh for core::Framebuffer<'a> {
         dither_mode: common::dither_mode,
         quant_bit: i32,
         wait_completion: bool,
-    ) -> u32 {
+    ) -> Result<u32> {
         let screen = common::mxcfb_rect {
             top: 0,
             left: 0,
@@ -35,35 +63,23 @@ impl<'a> framebuffer::FramebufferRefresh for core::Framebuffer<'a> {
             update_marker: marker as u32,
             waveform_mode: waveform_mode as u32,
             temp: temperature as i32,
-            flags: 0,
+            flags: self.pxp_flags(PxpFlags::default()).bits(),
             quant_bit,
             dither_mode: dither_mode as i32,
             update_region: screen,
             ..Default::default()
         };
 
-        let pt: *const mxcfb_update_data = &whole;
-        unsafe {
-            libc::ioctl(self.device.as_raw_fd(), common::MXCFB_SEND_UPDATE, pt);
-        }
+        self.backend.send_update(&whole)?;
 
         if wait_completion {
             let mut markerdata = mxcfb_update_marker_data {
                 update_marker: whole.update_marker,
                 collision_test: 0,
             };
-            unsafe {
-                if libc::ioctl(
-                    self.device.as_raw_fd(),
-                    common::MXCFB_WAIT_FOR_UPDATE_COMPLETE,
-                    &mut markerdata,
-                ) < 0
-                {
-                    warn!("WAIT_FOR_UPDATE_COMPLETE failed after a full_refresh(..)");
-                }
-            }
+            self.backend.wait_update_complete(&mut markerdata)?;
         }
-        whole.update_marker
+        Ok(whole.update_marker)
     }
 
     fn partial_refresh(
@@ -75,16 +91,40 @@ impl<'a> framebuffer::FramebufferRefresh for core::Framebuffer<'a> {
         dither_mode: common::dither_mode,
         quant_bit: i32,
         force_full_refresh: bool,
-    ) -> u32 {
-        let mut update_region = region.to_owned();
+    ) -> Result<u32> {
+        self.partial_refresh_with_flags(
+            region,
+            mode,
+            waveform_mode,
+            temperature,
+            dither_mode,
+            quant_bit,
+            force_full_refresh,
+            PxpFlags::default(),
+        )
+    }
+
+    fn partial_refresh_with_flags(
+        &self,
+        region: &common::mxcfb_rect,
+        mode: PartialRefreshMode,
+        waveform_mode: common::waveform_mode,
+        temperature: common::display_temp,
+        dither_mode: common::dither_mode,
+        quant_bit: i32,
+        force_full_refresh: bool,
+        pxp_flags: PxpFlags,
+    ) -> Result<u32> {
+        let (xres, yres) = (self.var_screen_info.xres, self.var_screen_info.yres);
+        let (height, width) = self.dimensions();
 
         // No accounting for this, out of bounds, entirely ignored
-        if update_region.left >= u32::from(common::DISPLAYWIDTH)
-            || update_region.top >= u32::from(common::DISPLAYHEIGHT)
-        {
-            return 0;
+        if region.left >= width || region.top >= height {
+            return Ok(0);
         }
 
+        let mut update_region = self.orientation().to_native_rect(region, yres, xres);
+
         if update_region.width < 1 {
             update_region.width = 1
         }
@@ -94,14 +134,14 @@ impl<'a> framebuffer::FramebufferRefresh for core::Framebuffer<'a> {
 
         // Dont try to refresh OOB horizontally
         let max_x = update_region.left + update_region.width;
-        if max_x > u32::from(common::DISPLAYWIDTH) {
-            update_region.width -= max_x - u32::from(common::DISPLAYWIDTH);
+        if max_x > xres {
+            update_region.width -= max_x - xres;
         }
 
         // Dont try to refresh OOB vertically
         let max_y = update_region.top + update_region.height;
-        if max_y > u32::from(common::DISPLAYHEIGHT) {
-            update_region.height -= max_y - u32::from(common::DISPLAYHEIGHT);
+        if max_y > yres {
+            update_region.height -= max_y - yres;
         }
 
         let update_mode = if force_full_refresh {
@@ -119,17 +159,14 @@ impl<'a> framebuffer::FramebufferRefresh for core::Framebuffer<'a> {
             flags: match mode {
                 PartialRefreshMode::DryRun => common::EPDC_FLAG_TEST_COLLISION as u32,
                 _ => 0,
-            },
+            } | self.pxp_flags(pxp_flags).bits(),
             quant_bit,
             dither_mode: dither_mode as i32,
             update_region,
             ..Default::default()
         };
 
-        let pt: *const mxcfb_update_data = &whole;
-        unsafe {
-            libc::ioctl(self.device.as_raw_fd(), common::MXCFB_SEND_UPDATE, pt);
-        }
+        self.backend.send_update(&whole)?;
 
         match mode {
             PartialRefreshMode::Wait | PartialRefreshMode::DryRun => {
@@ -137,37 +174,44 @@ impl<'a> framebuffer::FramebufferRefresh for core::Framebuffer<'a> {
                     update_marker: whole.update_marker,
                     collision_test: 0,
                 };
-                unsafe {
-                    if libc::ioctl(
-                        self.device.as_raw_fd(),
-                        common::MXCFB_WAIT_FOR_UPDATE_COMPLETE,
-                        &mut markerdata,
-                    ) < 0
-                    {
-                        warn!("WAIT_FOR_UPDATE_COMPLETE failed after a partial_refresh(..)");
-                    }
-                }
-                markerdata.collision_test
+                self.backend.wait_update_complete(&mut markerdata)?;
+                Ok(markerdata.collision_test)
             }
-            PartialRefreshMode::Async => whole.update_marker,
+            PartialRefreshMode::Async => Ok(whole.update_marker),
         }
     }
 
-    fn wait_refresh_complete(&self, marker: u32) -> u32 {
+    fn invert_region(
+        &self,
+        region: &common::mxcfb_rect,
+        waveform_mode: common::waveform_mode,
+        wait_completion: bool,
+    ) -> Result<u32> {
+        self.partial_refresh_with_flags(
+            region,
+            if wait_completion {
+                PartialRefreshMode::Wait
+            } else {
+                PartialRefreshMode::Async
+            },
+            waveform_mode,
+            common::display_temp::TEMP_USE_REMARKABLE_DRAW,
+            common::dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+            0,
+            false,
+            PxpFlags {
+                invert: true,
+                ..PxpFlags::default()
+            },
+        )
+    }
+
+    fn wait_refresh_complete(&self, marker: u32) -> Result<u32> {
         let mut markerdata = mxcfb_update_marker_data {
             update_marker: marker,
             collision_test: 0,
         };
-        unsafe {
-            if libc::ioctl(
-                self.device.as_raw_fd(),
-                common::MXCFB_WAIT_FOR_UPDATE_COMPLETE,
-                &mut markerdata,
-            ) < 0
-            {
-                warn!("WAIT_FOR_UPDATE_COMPLETE failed");
-            }
-        };
-        markerdata.collision_test
+        self.backend.wait_update_complete(&mut markerdata)?;
+        Ok(markerdata.collision_test)
     }
 }
@@ -64,6 +64,19 @@ pub struct FixScreeninfo {
     pub reserved: [u16; 2],
 }
 
+/// Struct as defined in /usr/include/linux/fb.h, pointing to `len` entries of each
+/// component. `transp` may be null.
+#[repr(C)]
+#[derive(Debug)]
+pub struct FbCmap {
+    pub start: u32,
+    pub len: u32,
+    pub red: *mut u16,
+    pub green: *mut u16,
+    pub blue: *mut u16,
+    pub transp: *mut u16,
+}
+
 impl ::std::default::Default for Bitfield {
     fn default() -> Self {
         unsafe { ::std::mem::zeroed() }
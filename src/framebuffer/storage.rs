@@ -42,16 +42,23 @@ impl CompressedCanvasState {
 use framebuffer::common;
 use image;
 
-pub fn rgbimage_from_u8_slice(w: u32, h: u32, buff: &[u8]) -> Option<image::RgbImage> {
-    // rgb565 is the input so it is 16bits (2 bytes) per pixel
-    let input_bytespp = 2;
+/// Converts the output of FramebufferIO::dump_region(..) into an RgbImage. `format` is
+/// the pixel format of the framebuffer the region was dumped from. Returns `None` if
+/// `buff` doesn't hold `w` by `h` pixels in that format.
+pub fn rgbimage_from_u8_slice(
+    w: u32,
+    h: u32,
+    buff: &[u8],
+    format: common::PixelFormat,
+) -> Option<image::RgbImage> {
+    let input_bytespp = format.bytes_per_pixel() as u32;
     let input_line_len = w * input_bytespp;
     if h * input_line_len != buff.len() as u32 {
         return None;
     }
     Some(image::ImageBuffer::from_fn(w, h, |x, y| {
-        let in_index: usize = ((y * input_line_len) + ((input_bytespp * x) as u32)) as usize;
-        let data = common::color::NATIVE_COMPONENTS(buff[in_index], buff[in_index + 1]).to_rgb8();
+        let in_index: usize = ((y * input_line_len) + (input_bytespp * x)) as usize;
+        let data = format.decode(&buff[in_index..]).to_rgb8();
         image::Rgb(data)
     }))
 }
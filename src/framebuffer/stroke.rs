@@ -0,0 +1,261 @@
+use framebuffer::raster;
+
+/// Shape drawn at the ends of open strokes and dashes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineCap {
+    /// Ends exactly at the endpoint
+    Butt,
+    /// Ends with a half disc around the endpoint
+    Round,
+    /// Extends past the endpoint by half of the width
+    Square,
+}
+
+/// Shape drawn where two segments of a stroke meet
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineJoin {
+    /// Extends the outer edges until they meet, falling back to `Bevel` for corners
+    /// sharper than `StrokeStyle::miter_limit`
+    Miter,
+    Round,
+    /// Cuts the corner off with a straight line
+    Bevel,
+}
+
+/// Describes how `FramebufferDraw::draw_polyline` and `FramebufferDraw::stroke_path_styled`
+/// outline their shapes
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Largest ratio of the miter length to the stroke width, 4 by default
+    pub miter_limit: f32,
+    /// Alternating lengths of dashes and gaps, the stroke being solid when it is empty
+    pub dash_array: Vec<f32>,
+    /// Distance into the dash pattern at which the stroke starts
+    pub dash_offset: f32,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> StrokeStyle {
+        StrokeStyle {
+            width: 1.0,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            miter_limit: 4.0,
+            dash_array: Vec::new(),
+            dash_offset: 0.0,
+        }
+    }
+}
+
+impl StrokeStyle {
+    /// A solid stroke of `width` with butt caps and miter joins
+    pub fn new(width: f32) -> StrokeStyle {
+        StrokeStyle {
+            width,
+            ..StrokeStyle::default()
+        }
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+/// Splits the polyline through `points` into the dashes described by `dash_array` and
+/// `dash_offset`. Returns the polyline itself when the pattern is empty or invalid.
+pub fn dash(
+    points: &[(f32, f32)],
+    closed: bool,
+    dash_array: &[f32],
+    dash_offset: f32,
+) -> Vec<Vec<(f32, f32)>> {
+    let mut points = points.to_vec();
+    let total: f32 = dash_array.iter().sum();
+    if dash_array.is_empty() || total <= 0.0 || dash_array.iter().any(|&d| d < 0.0) {
+        return vec![points];
+    }
+    if closed && !points.is_empty() {
+        let first = points[0];
+        points.push(first);
+    }
+    // An odd number of lengths is repeated to get as many dashes as gaps
+    let pattern: Vec<f32> = if dash_array.len() % 2 == 1 {
+        dash_array.iter().chain(dash_array.iter()).cloned().collect()
+    } else {
+        dash_array.to_vec()
+    };
+    let total: f32 = pattern.iter().sum();
+
+    // Find where in the pattern the stroke starts
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    let mut offset = ((dash_offset % total) + total) % total;
+    while offset > 0.0 {
+        if offset < remaining {
+            remaining -= offset;
+            break;
+        }
+        offset -= remaining;
+        index = (index + 1) % pattern.len();
+        remaining = pattern[index];
+    }
+
+    let mut dashes = Vec::new();
+    let mut current: Vec<(f32, f32)> = match points.first() {
+        Some(&first) if index % 2 == 0 => vec![first],
+        _ => Vec::new(),
+    };
+    for pair in points.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let length = distance(a, b);
+        let mut travelled = 0.0;
+        while length - travelled > remaining {
+            travelled += remaining;
+            let t = travelled / length;
+            let pt = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            // Either ends the current dash or starts the next one
+            current.push(pt);
+            if index % 2 == 0 {
+                dashes.push(::std::mem::replace(&mut current, Vec::new()));
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= length - travelled;
+        if index % 2 == 0 {
+            current.push(b);
+        }
+    }
+    if index % 2 == 0 && !current.is_empty() {
+        dashes.push(current);
+    }
+    dashes
+}
+
+/// Returns the outline of a stroke through `points` drawn with `style` as polygons to be
+/// filled with `FillRule::NonZero`. Each segment, join and cap is a separate polygon, all
+/// of them winding the same way so that their overlaps don't cancel out.
+pub fn outline(points: &[(f32, f32)], closed: bool, style: &StrokeStyle) -> Vec<Vec<(f32, f32)>> {
+    let mut polygons = Vec::new();
+    if style.width <= 0.0 {
+        return polygons;
+    }
+    if style.dash_array.is_empty() {
+        outline_polyline(points, closed, style, &mut polygons);
+    } else {
+        for dash in dash(points, closed, &style.dash_array, style.dash_offset) {
+            outline_polyline(&dash, false, style, &mut polygons);
+        }
+    }
+    raster::wind_clockwise(&mut polygons);
+    polygons
+}
+
+fn outline_polyline(
+    points: &[(f32, f32)],
+    closed: bool,
+    style: &StrokeStyle,
+    polygons: &mut Vec<Vec<(f32, f32)>>,
+) {
+    let half = style.width / 2.0;
+    let mut points: Vec<(f32, f32)> = points.to_vec();
+    points.dedup();
+    if closed && points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+
+    match points.len() {
+        0 => return,
+        1 => {
+            // A zero-length stroke only shows its caps
+            let pt = points[0];
+            match style.cap {
+                LineCap::Butt => {}
+                LineCap::Round => polygons.push(raster::disc(pt, half)),
+                LineCap::Square => polygons.push(vec![
+                    (pt.0 - half, pt.1 - half),
+                    (pt.0 + half, pt.1 - half),
+                    (pt.0 + half, pt.1 + half),
+                    (pt.0 - half, pt.1 + half),
+                ]),
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let closed = closed && points.len() > 2;
+    let count = if closed { points.len() } else { points.len() - 1 };
+    // Unit direction and normal of each segment
+    let directions: Vec<((f32, f32), (f32, f32))> = (0..count)
+        .map(|i| {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            let length = distance(a, b);
+            let u = ((b.0 - a.0) / length, (b.1 - a.1) / length);
+            (u, (-u.1, u.0))
+        }).collect();
+
+    for i in 0..count {
+        let (mut a, mut b) = (points[i], points[(i + 1) % points.len()]);
+        let (u, n) = directions[i];
+        if !closed && style.cap == LineCap::Square {
+            if i == 0 {
+                a = (a.0 - u.0 * half, a.1 - u.1 * half);
+            }
+            if i == count - 1 {
+                b = (b.0 + u.0 * half, b.1 + u.1 * half);
+            }
+        }
+        polygons.push(vec![
+            (a.0 + n.0 * half, a.1 + n.1 * half),
+            (b.0 + n.0 * half, b.1 + n.1 * half),
+            (b.0 - n.0 * half, b.1 - n.1 * half),
+            (a.0 - n.0 * half, a.1 - n.1 * half),
+        ]);
+    }
+
+    // Joins between consecutive segments, including the closing one
+    let joins = if closed { 0..count } else { 1..count };
+    for i in joins {
+        let vertex = points[i];
+        let (u1, n1) = directions[(i + count - 1) % count];
+        let (u2, n2) = directions[i];
+        let cross = u1.0 * u2.1 - u1.1 * u2.0;
+        if cross.abs() < 1e-6 && u1.0 * u2.0 + u1.1 * u2.1 > 0.0 {
+            continue;
+        }
+        if style.join == LineJoin::Round {
+            polygons.push(raster::disc(vertex, half));
+            continue;
+        }
+        // The gap to fill is on the outer side of the turn
+        let side = if cross > 0.0 { -half } else { half };
+        let outer1 = (vertex.0 + n1.0 * side, vertex.1 + n1.1 * side);
+        let outer2 = (vertex.0 + n2.0 * side, vertex.1 + n2.1 * side);
+        let bisector = (n1.0 + n2.0, n1.1 + n2.1);
+        let bisector_length = (bisector.0 * bisector.0 + bisector.1 * bisector.1).sqrt();
+        // Ratio of the miter length to the stroke width
+        let ratio = if bisector_length > 0.0 {
+            2.0 / bisector_length
+        } else {
+            ::std::f32::INFINITY
+        };
+        if style.join == LineJoin::Miter && ratio <= style.miter_limit {
+            let tip = (
+                vertex.0 + bisector.0 / bisector_length * side * ratio,
+                vertex.1 + bisector.1 / bisector_length * side * ratio,
+            );
+            polygons.push(vec![vertex, outer1, tip, outer2]);
+        } else {
+            polygons.push(vec![vertex, outer1, outer2]);
+        }
+    }
+
+    if !closed && style.cap == LineCap::Round {
+        polygons.push(raster::disc(points[0], half));
+        polygons.push(raster::disc(points[points.len() - 1], half));
+    }
+}
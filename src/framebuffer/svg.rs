@@ -0,0 +1,855 @@
+//! A small SVG renderer meant for icons and simple illustrations. It supports paths, the
+//! basic shapes, fills, strokes, transforms and text set in the built-in font. Gradients,
+//! patterns, clipping, masks, filters, opacity, `<use>` references and CSS stylesheets
+//! are ignored.
+
+use std::fs::File;
+use std::io::Read;
+
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+use error::{Error, Result};
+use framebuffer::common::{color, mxcfb_rect, FillRule};
+use framebuffer::core;
+use framebuffer::fonts::FontHandle;
+use framebuffer::path::Path;
+use framebuffer::stroke::{LineCap, LineJoin, StrokeStyle};
+use framebuffer::text;
+use framebuffer::FramebufferDraw;
+
+fn parse_error(attribute: &str, value: &str) -> Error {
+    Error::Parse {
+        attribute: attribute.to_owned(),
+        value: value.to_owned(),
+    }
+}
+
+/// Affine transformation mapping `(x, y)` onto `(a*x + c*y + e, b*x + d*y + f)`
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Transform {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+    f: f32,
+}
+
+impl Transform {
+    fn identity() -> Transform {
+        Transform::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+
+    fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Transform {
+        Transform { a, b, c, d, e, f }
+    }
+
+    /// Returns the transformation applying `other` first, then `self`
+    fn then(self, other: Transform) -> Transform {
+        Transform {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+
+    fn apply(self, pt: (f32, f32)) -> (f32, f32) {
+        (
+            self.a * pt.0 + self.c * pt.1 + self.e,
+            self.b * pt.0 + self.d * pt.1 + self.f,
+        )
+    }
+
+    /// How much lengths are scaled on average, used for stroke widths and font sizes
+    fn scale_factor(self) -> f32 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+
+    /// Parses the value of a `transform` attribute, e.g. `translate(10 20) rotate(45)`
+    fn parse(value: &str) -> Result<Transform> {
+        let mut transform = Transform::identity();
+        let mut rest = value.trim();
+        while !rest.is_empty() {
+            let open = rest.find('(').ok_or_else(|| parse_error("transform", value))?;
+            let close = rest.find(')').ok_or_else(|| parse_error("transform", value))?;
+            if close < open {
+                return Err(parse_error("transform", value));
+            }
+            let name = rest[..open].trim_matches(|c: char| c.is_whitespace() || c == ',');
+            let args = Cursor::new(&rest[open + 1..close]).numbers();
+            let arg = |i: usize| args.get(i).cloned();
+            let step = match (name, args.len()) {
+                ("matrix", 6) => Transform::new(args[0], args[1], args[2], args[3], args[4], args[5]),
+                ("translate", 1) | ("translate", 2) => {
+                    Transform::new(1.0, 0.0, 0.0, 1.0, args[0], arg(1).unwrap_or(0.0))
+                }
+                ("scale", 1) | ("scale", 2) => {
+                    Transform::new(args[0], 0.0, 0.0, arg(1).unwrap_or(args[0]), 0.0, 0.0)
+                }
+                ("rotate", 1) | ("rotate", 3) => {
+                    let (sin, cos) = args[0].to_radians().sin_cos();
+                    let (cx, cy) = (arg(1).unwrap_or(0.0), arg(2).unwrap_or(0.0));
+                    Transform::new(1.0, 0.0, 0.0, 1.0, cx, cy)
+                        .then(Transform::new(cos, sin, -sin, cos, 0.0, 0.0))
+                        .then(Transform::new(1.0, 0.0, 0.0, 1.0, -cx, -cy))
+                }
+                ("skewX", 1) => Transform::new(1.0, 0.0, args[0].to_radians().tan(), 1.0, 0.0, 0.0),
+                ("skewY", 1) => Transform::new(1.0, args[0].to_radians().tan(), 0.0, 1.0, 0.0, 0.0),
+                _ => return Err(parse_error("transform", value)),
+            };
+            transform = transform.then(step);
+            rest = rest[close + 1..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        }
+        Ok(transform)
+    }
+}
+
+/// Reads the numbers and flags of path data, point lists and transform arguments
+struct Cursor<'s> {
+    data: &'s [u8],
+    pos: usize,
+}
+
+impl<'s> Cursor<'s> {
+    fn new(data: &'s str) -> Cursor<'s> {
+        Cursor {
+            data: data.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.pos < self.data.len()
+            && (self.data[self.pos].is_ascii_whitespace() || self.data[self.pos] == b',')
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.pos >= self.data.len()
+    }
+
+    /// Returns the next character if it is a path command letter, consuming it
+    fn command(&mut self) -> Option<char> {
+        self.skip_separators();
+        match self.data.get(self.pos) {
+            Some(&c) if c.is_ascii_alphabetic() && c != b'e' && c != b'E' => {
+                self.pos += 1;
+                Some(c as char)
+            }
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.pos;
+        let digits = |cursor: &mut Cursor| {
+            let from = cursor.pos;
+            while cursor.pos < cursor.data.len() && cursor.data[cursor.pos].is_ascii_digit() {
+                cursor.pos += 1;
+            }
+            cursor.pos > from
+        };
+        if let Some(&b'+') | Some(&b'-') = self.data.get(self.pos) {
+            self.pos += 1;
+        }
+        let mut valid = digits(self);
+        if self.data.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            valid |= digits(self);
+        }
+        if !valid {
+            self.pos = start;
+            return None;
+        }
+        if let Some(&b'e') | Some(&b'E') = self.data.get(self.pos) {
+            let mantissa_end = self.pos;
+            self.pos += 1;
+            if let Some(&b'+') | Some(&b'-') = self.data.get(self.pos) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                self.pos = mantissa_end;
+            }
+        }
+        ::std::str::from_utf8(&self.data[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+    }
+
+    /// Reads an arc flag, which may not be separated from what follows it
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let flag = match self.data.get(self.pos) {
+            Some(&b'0') => false,
+            Some(&b'1') => true,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(flag)
+    }
+
+    fn point(&mut self) -> Option<(f32, f32)> {
+        let x = self.number()?;
+        Some((x, self.number()?))
+    }
+
+    fn numbers(mut self) -> Vec<f32> {
+        let mut numbers = Vec::new();
+        while let Some(n) = self.number() {
+            numbers.push(n);
+        }
+        numbers
+    }
+}
+
+/// Parses the `d` attribute of a `<path>`
+fn parse_path_data(data: &str) -> Result<Path> {
+    let error = || parse_error("d", data);
+    let mut cursor = Cursor::new(data);
+    let mut path = Path::new();
+    let mut command = None;
+    let mut start = (0.0, 0.0);
+    // Second control point of the last curve, reflected by the smooth curve commands
+    let mut last_ctrl: Option<(char, (f32, f32))> = None;
+
+    while !cursor.at_end() {
+        command = match cursor.command() {
+            Some(c) => Some(c),
+            // Repeated arguments continue the previous command, a move being followed by lines
+            None => match command {
+                Some('M') => Some('L'),
+                Some('m') => Some('l'),
+                Some('Z') | Some('z') | None => return Err(error()),
+                other => other,
+            },
+        };
+        let c = command.unwrap();
+        let current = path.current_point().unwrap_or((0.0, 0.0));
+        let relative = c.is_ascii_lowercase();
+        let offset = |pt: (f32, f32)| {
+            if relative {
+                (pt.0 + current.0, pt.1 + current.1)
+            } else {
+                pt
+            }
+        };
+        let reflected = |kinds: &str| match last_ctrl {
+            Some((kind, ctrl)) if kinds.contains(kind) => {
+                (2.0 * current.0 - ctrl.0, 2.0 * current.1 - ctrl.1)
+            }
+            _ => current,
+        };
+
+        let mut ctrl = None;
+        match c.to_ascii_uppercase() {
+            'M' => {
+                start = offset(cursor.point().ok_or_else(error)?);
+                path.move_to(start);
+            }
+            'L' => {
+                path.line_to(offset(cursor.point().ok_or_else(error)?));
+            }
+            'H' => {
+                let x = cursor.number().ok_or_else(error)?;
+                let x = if relative { x + current.0 } else { x };
+                path.line_to((x, current.1));
+            }
+            'V' => {
+                let y = cursor.number().ok_or_else(error)?;
+                let y = if relative { y + current.1 } else { y };
+                path.line_to((current.0, y));
+            }
+            'C' => {
+                let c1 = offset(cursor.point().ok_or_else(error)?);
+                let c2 = offset(cursor.point().ok_or_else(error)?);
+                let pt = offset(cursor.point().ok_or_else(error)?);
+                path.cubic_to(c1, c2, pt);
+                ctrl = Some(('C', c2));
+            }
+            'S' => {
+                let c1 = reflected("C");
+                let c2 = offset(cursor.point().ok_or_else(error)?);
+                let pt = offset(cursor.point().ok_or_else(error)?);
+                path.cubic_to(c1, c2, pt);
+                ctrl = Some(('C', c2));
+            }
+            'Q' => {
+                let c1 = offset(cursor.point().ok_or_else(error)?);
+                let pt = offset(cursor.point().ok_or_else(error)?);
+                path.quad_to(c1, pt);
+                ctrl = Some(('Q', c1));
+            }
+            'T' => {
+                let c1 = reflected("Q");
+                let pt = offset(cursor.point().ok_or_else(error)?);
+                path.quad_to(c1, pt);
+                ctrl = Some(('Q', c1));
+            }
+            'A' => {
+                let rx = cursor.number().ok_or_else(error)?;
+                let ry = cursor.number().ok_or_else(error)?;
+                let rotation = cursor.number().ok_or_else(error)?;
+                let large_arc = cursor.flag().ok_or_else(error)?;
+                let sweep = cursor.flag().ok_or_else(error)?;
+                let pt = offset(cursor.point().ok_or_else(error)?);
+                path.arc_to(rx, ry, rotation.to_radians(), large_arc, sweep, pt);
+            }
+            'Z' => {
+                path.close();
+                path.move_to(start);
+            }
+            _ => return Err(error()),
+        }
+        last_ctrl = ctrl;
+    }
+    Ok(path)
+}
+
+/// Parses `value` as a color, `None` standing for `none`
+fn parse_color(value: &str) -> Result<Option<color>> {
+    let value = value.trim();
+    // Paint servers aren't supported, the shape is left unpainted
+    if value.starts_with("url(") {
+        return Ok(None);
+    }
+    let hex = |s: &str| u8::from_str_radix(s, 16).map_err(|_| parse_error("color", value));
+    if value.starts_with('#') {
+        let digits = &value[1..];
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(parse_error("color", value));
+        }
+        return match digits.len() {
+            3 => Ok(Some(color::RGB(
+                hex(&digits[0..1])? * 17,
+                hex(&digits[1..2])? * 17,
+                hex(&digits[2..3])? * 17,
+            ))),
+            6 => Ok(Some(color::RGB(
+                hex(&digits[0..2])?,
+                hex(&digits[2..4])?,
+                hex(&digits[4..6])?,
+            ))),
+            _ => Err(parse_error("color", value)),
+        };
+    }
+    if value.starts_with("rgb(") && value.ends_with(')') {
+        let components: Vec<u8> = value[4..value.len() - 1]
+            .split(',')
+            .map(|c| {
+                let c = c.trim();
+                if c.ends_with('%') {
+                    c[..c.len() - 1]
+                        .parse::<f32>()
+                        .map(|p| (p * 2.55).round().max(0.0).min(255.0) as u8)
+                        .map_err(|_| parse_error("color", value))
+                } else {
+                    c.parse::<f32>()
+                        .map(|v| v.round().max(0.0).min(255.0) as u8)
+                        .map_err(|_| parse_error("color", value))
+                }
+            }).collect::<Result<_>>()?;
+        if components.len() != 3 {
+            return Err(parse_error("color", value));
+        }
+        return Ok(Some(color::RGB(components[0], components[1], components[2])));
+    }
+    match value.to_lowercase().as_str() {
+        "none" | "transparent" => Ok(None),
+        // There is no inherited `color` property, so the current color is the default fill
+        "black" | "currentcolor" => Ok(Some(color::BLACK)),
+        "white" => Ok(Some(color::WHITE)),
+        "gray" | "grey" => Ok(Some(color::RGB(128, 128, 128))),
+        "darkgray" | "darkgrey" => Ok(Some(color::RGB(169, 169, 169))),
+        "lightgray" | "lightgrey" => Ok(Some(color::RGB(211, 211, 211))),
+        "silver" => Ok(Some(color::RGB(192, 192, 192))),
+        "red" => Ok(Some(color::RGB(255, 0, 0))),
+        "green" => Ok(Some(color::RGB(0, 128, 0))),
+        "blue" => Ok(Some(color::RGB(0, 0, 255))),
+        _ => Err(parse_error("color", value)),
+    }
+}
+
+/// Parses a length, ignoring `px` units
+fn parse_length(attribute: &str, value: &str) -> Result<f32> {
+    value
+        .trim()
+        .trim_end_matches("px")
+        .parse()
+        .map_err(|_| parse_error(attribute, value))
+}
+
+/// Reads the length attribute `name`. Values that can't be parsed, like the ones in
+/// unsupported units, are logged and treated as missing.
+fn length_attribute(attributes: &[OwnedAttribute], name: &str) -> Option<f32> {
+    let a = attributes.iter().find(|a| a.name.local_name == name)?;
+    match parse_length(name, &a.value) {
+        Ok(length) => Some(length),
+        Err(e) => {
+            warn!("Ignoring an SVG attribute: {0}", e);
+            None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum TextAnchor {
+    Start,
+    Middle,
+    End,
+}
+
+/// The presentation attributes inherited by the children of an element
+#[derive(Clone, Debug)]
+struct Style {
+    fill: Option<color>,
+    stroke: Option<color>,
+    stroke_style: StrokeStyle,
+    fill_rule: FillRule,
+    font_size: f32,
+    text_anchor: TextAnchor,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            fill: Some(color::BLACK),
+            stroke: None,
+            stroke_style: StrokeStyle::default(),
+            fill_rule: FillRule::NonZero,
+            font_size: 16.0,
+            text_anchor: TextAnchor::Start,
+        }
+    }
+}
+
+impl Style {
+    /// Applies a presentation attribute or a declaration of the `style` attribute. Values
+    /// that can't be parsed, like unknown color names, are logged and leave it unchanged.
+    fn set(&mut self, name: &str, value: &str) {
+        if let Err(e) = self.try_set(name, value) {
+            warn!("Ignoring an SVG attribute: {0}", e);
+        }
+    }
+
+    fn try_set(&mut self, name: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        if value == "inherit" {
+            return Ok(());
+        }
+        match name {
+            "fill" => self.fill = parse_color(value)?,
+            "stroke" => self.stroke = parse_color(value)?,
+            "stroke-width" => self.stroke_style.width = parse_length(name, value)?,
+            "stroke-linecap" => {
+                self.stroke_style.cap = match value {
+                    "butt" => LineCap::Butt,
+                    "round" => LineCap::Round,
+                    "square" => LineCap::Square,
+                    _ => return Err(parse_error(name, value)),
+                }
+            }
+            "stroke-linejoin" => {
+                self.stroke_style.join = match value {
+                    "miter" | "miter-clip" | "arcs" => LineJoin::Miter,
+                    "round" => LineJoin::Round,
+                    "bevel" => LineJoin::Bevel,
+                    _ => return Err(parse_error(name, value)),
+                }
+            }
+            "stroke-miterlimit" => self.stroke_style.miter_limit = parse_length(name, value)?,
+            "stroke-dasharray" => {
+                self.stroke_style.dash_array = if value == "none" {
+                    Vec::new()
+                } else {
+                    Cursor::new(value).numbers()
+                }
+            }
+            "stroke-dashoffset" => self.stroke_style.dash_offset = parse_length(name, value)?,
+            "fill-rule" => {
+                self.fill_rule = match value {
+                    "evenodd" => FillRule::EvenOdd,
+                    "nonzero" => FillRule::NonZero,
+                    _ => return Err(parse_error(name, value)),
+                }
+            }
+            "font-size" => self.font_size = parse_length(name, value)?,
+            "text-anchor" => {
+                self.text_anchor = match value {
+                    "start" => TextAnchor::Start,
+                    "middle" => TextAnchor::Middle,
+                    "end" => TextAnchor::End,
+                    _ => return Err(parse_error(name, value)),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Shape {
+    Path(Path),
+    /// `text` with its baseline starting at `(x, y)`
+    Text { x: f32, y: f32, text: String },
+}
+
+#[derive(Clone, Debug)]
+struct Item {
+    shape: Shape,
+    transform: Transform,
+    style: Style,
+}
+
+/// Builds the outline of a `<rect>`, `<circle>`, `<ellipse>`, `<line>`, `<polyline>`,
+/// `<polygon>` or `<path>`. Returns `None` for other elements and shapes that draw nothing.
+fn shape_path(element: &str, attributes: &[OwnedAttribute]) -> Result<Option<Path>> {
+    let attribute = |name: &str| attributes.iter().find(|a| a.name.local_name == name);
+    let length = |name: &str| length_attribute(attributes, name).unwrap_or(0.0);
+    let mut path = Path::new();
+    match element {
+        "path" => match attribute("d") {
+            Some(a) => return parse_path_data(&a.value).map(Some),
+            None => return Ok(None),
+        },
+        "rect" => {
+            let (x, y) = (length("x"), length("y"));
+            let (width, height) = (length("width"), length("height"));
+            if width <= 0.0 || height <= 0.0 {
+                return Ok(None);
+            }
+            // A missing radius defaults to the other one
+            let rx = length_attribute(attributes, "rx");
+            let ry = length_attribute(attributes, "ry");
+            let (rx, ry) = match (rx, ry) {
+                (None, None) => (0.0, 0.0),
+                (Some(rx), None) => (rx, rx),
+                (None, Some(ry)) => (ry, ry),
+                (Some(rx), Some(ry)) => (rx, ry),
+            };
+            let (rx, ry) = (rx.min(width / 2.0), ry.min(height / 2.0));
+            if rx <= 0.0 || ry <= 0.0 {
+                path.move_to((x, y))
+                    .line_to((x + width, y))
+                    .line_to((x + width, y + height))
+                    .line_to((x, y + height))
+                    .close();
+            } else {
+                path.move_to((x + rx, y))
+                    .line_to((x + width - rx, y))
+                    .arc_to(rx, ry, 0.0, false, true, (x + width, y + ry))
+                    .line_to((x + width, y + height - ry))
+                    .arc_to(rx, ry, 0.0, false, true, (x + width - rx, y + height))
+                    .line_to((x + rx, y + height))
+                    .arc_to(rx, ry, 0.0, false, true, (x, y + height - ry))
+                    .line_to((x, y + ry))
+                    .arc_to(rx, ry, 0.0, false, true, (x + rx, y))
+                    .close();
+            }
+        }
+        "circle" | "ellipse" => {
+            let (cx, cy) = (length("cx"), length("cy"));
+            let (rx, ry) = if element == "circle" {
+                (length("r"), length("r"))
+            } else {
+                (length("rx"), length("ry"))
+            };
+            if rx <= 0.0 || ry <= 0.0 {
+                return Ok(None);
+            }
+            path.move_to((cx + rx, cy))
+                .arc_to(rx, ry, 0.0, false, true, (cx - rx, cy))
+                .arc_to(rx, ry, 0.0, false, true, (cx + rx, cy))
+                .close();
+        }
+        "line" => {
+            path.move_to((length("x1"), length("y1")))
+                .line_to((length("x2"), length("y2")));
+        }
+        "polyline" | "polygon" => {
+            let points = match attribute("points") {
+                Some(a) => Cursor::new(&a.value).numbers(),
+                None => return Ok(None),
+            };
+            for pt in points.chunks(2).filter(|pt| pt.len() == 2) {
+                path.line_to((pt[0], pt[1]));
+            }
+            if element == "polygon" {
+                path.close();
+            }
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(path))
+}
+
+/// A parsed SVG document, drawn with `FramebufferDraw::draw_svg`
+#[derive(Clone, Debug)]
+pub struct SvgImage {
+    width: f32,
+    height: f32,
+    /// `(x, y, width, height)` of the area of the user space the image shows
+    view_box: (f32, f32, f32, f32),
+    items: Vec<Item>,
+}
+
+/// Elements whose contents aren't rendered directly
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "defs", "clipPath", "mask", "symbol", "pattern", "marker", "linearGradient",
+    "radialGradient", "filter", "title", "desc", "metadata", "style", "script",
+];
+
+impl SvgImage {
+    /// Parses the SVG document in `data`
+    pub fn parse(data: &str) -> Result<SvgImage> {
+        let mut image = SvgImage {
+            width: 0.0,
+            height: 0.0,
+            view_box: (0.0, 0.0, 0.0, 0.0),
+            items: Vec::new(),
+        };
+        let mut found_root = false;
+        // The transform and style in effect for each open element
+        let mut stack: Vec<(Transform, Style)> = vec![(Transform::identity(), Style::default())];
+        let mut skipped_depth = 0;
+        let mut pending_text: Option<Item> = None;
+
+        for event in EventReader::from_str(data) {
+            let event = event.map_err(|e| parse_error("svg", &e.to_string()))?;
+            match event {
+                XmlEvent::StartElement {
+                    ref name,
+                    ref attributes,
+                    ..
+                } => {
+                    let element = name.local_name.as_str();
+                    if skipped_depth > 0 || SKIPPED_ELEMENTS.contains(&element) {
+                        skipped_depth += 1;
+                        continue;
+                    }
+
+                    let (mut transform, mut style) = stack.last().cloned().unwrap();
+                    for a in attributes {
+                        match a.name.local_name.as_str() {
+                            "transform" => transform = transform.then(Transform::parse(&a.value)?),
+                            "style" => {}
+                            name => style.set(name, &a.value),
+                        }
+                    }
+                    // Declarations of the style attribute win over presentation attributes
+                    if let Some(a) = attributes.iter().find(|a| a.name.local_name == "style") {
+                        for declaration in a.value.split(';') {
+                            let mut parts = declaration.splitn(2, ':');
+                            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                                style.set(name.trim(), value);
+                            }
+                        }
+                    }
+
+                    if element == "svg" && !found_root {
+                        found_root = true;
+                        image.read_root_geometry(attributes)?;
+                    } else if element == "text" {
+                        let position = |n: &str| length_attribute(attributes, n).unwrap_or(0.0);
+                        pending_text = Some(Item {
+                            shape: Shape::Text {
+                                x: position("x"),
+                                y: position("y"),
+                                text: String::new(),
+                            },
+                            transform,
+                            style: style.clone(),
+                        });
+                    } else if let Some(path) = shape_path(element, attributes)? {
+                        image.items.push(Item {
+                            shape: Shape::Path(path),
+                            transform,
+                            style: style.clone(),
+                        });
+                    }
+                    stack.push((transform, style));
+                }
+                XmlEvent::EndElement { ref name } => {
+                    if skipped_depth > 0 {
+                        skipped_depth -= 1;
+                        continue;
+                    }
+                    stack.pop();
+                    if name.local_name == "text" {
+                        if let Some(item) = pending_text.take() {
+                            image.items.push(item);
+                        }
+                    }
+                }
+                XmlEvent::Characters(ref characters) if skipped_depth == 0 => {
+                    if let Some(Item {
+                        shape: Shape::Text { ref mut text, .. },
+                        ..
+                    }) = pending_text
+                    {
+                        text.push_str(characters);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !found_root {
+            return Err(parse_error("svg", "No <svg> element"));
+        }
+        Ok(image)
+    }
+
+    /// Same as `parse(..)` but reads the document from the file at `path`
+    pub fn load_file(path: &str) -> Result<SvgImage> {
+        let mut data = String::new();
+        File::open(path)?.read_to_string(&mut data)?;
+        SvgImage::parse(&data)
+    }
+
+    /// The intrinsic `(width, height)` of the image
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    /// Reads the size and the view box of the root `<svg>` element. Either of them
+    /// can be derived from the other, percentages being ignored.
+    fn read_root_geometry(&mut self, attributes: &[OwnedAttribute]) -> Result<()> {
+        let attribute = |n: &str| {
+            attributes
+                .iter()
+                .find(|a| a.name.local_name == n && !a.value.trim().ends_with('%'))
+        };
+        let view_box = match attribute("viewBox") {
+            Some(a) => {
+                let numbers = Cursor::new(&a.value).numbers();
+                if numbers.len() != 4 || numbers[2] <= 0.0 || numbers[3] <= 0.0 {
+                    return Err(parse_error("viewBox", &a.value));
+                }
+                Some((numbers[0], numbers[1], numbers[2], numbers[3]))
+            }
+            None => None,
+        };
+        let width = attribute("width").and_then(|_| length_attribute(attributes, "width"));
+        let height = attribute("height").and_then(|_| length_attribute(attributes, "height"));
+
+        let (width, height) = match (width, height, view_box) {
+            (Some(w), Some(h), _) => (w, h),
+            (Some(w), None, Some(vb)) => (w, w * vb.3 / vb.2),
+            (None, Some(h), Some(vb)) => (h * vb.2 / vb.3, h),
+            (None, None, Some(vb)) => (vb.2, vb.3),
+            _ => return Err(parse_error("viewBox", "")),
+        };
+        if width <= 0.0 || height <= 0.0 {
+            return Err(parse_error("width", &width.to_string()));
+        }
+        self.width = width;
+        self.height = height;
+        self.view_box = view_box.unwrap_or((0.0, 0.0, width, height));
+        Ok(())
+    }
+
+    /// Draws the image scaled to fit in `dest`, centered and keeping its aspect ratio.
+    /// Nothing is drawn outside of `dest`. Returns the region that has been modified.
+    pub(crate) fn render(&self, fb: &mut core::Framebuffer, dest: &mxcfb_rect) -> mxcfb_rect {
+        let (vx, vy, vw, vh) = self.view_box;
+        let scale = (dest.width as f32 / vw).min(dest.height as f32 / vh);
+        let left = dest.left as f32 + (dest.width as f32 - vw * scale) / 2.0;
+        let top = dest.top as f32 + (dest.height as f32 - vh * scale) / 2.0;
+        let viewport = Transform::new(scale, 0.0, 0.0, scale, left - vx * scale, top - vy * scale);
+
+        let mut drawn: Option<mxcfb_rect> = None;
+        let mut add = |rect: mxcfb_rect| {
+            if rect.width == 0 || rect.height == 0 {
+                return;
+            }
+            drawn = Some(match drawn {
+                Some(d) => {
+                    let top = ::std::cmp::min(d.top, rect.top);
+                    let left = ::std::cmp::min(d.left, rect.left);
+                    mxcfb_rect {
+                        top,
+                        left,
+                        height: ::std::cmp::max(d.top + d.height, rect.top + rect.height) - top,
+                        width: ::std::cmp::max(d.left + d.width, rect.left + rect.width) - left,
+                    }
+                }
+                None => rect,
+            });
+        };
+
+        fb.push_clip_rect(*dest);
+        for item in &self.items {
+            let transform = viewport.then(item.transform);
+            let style = &item.style;
+            match item.shape {
+                Shape::Path(ref path) => {
+                    let path = path.transformed(|pt| transform.apply(pt));
+                    if let Some(fill) = style.fill {
+                        add(fb.fill_path(&path, style.fill_rule, fill));
+                    }
+                    if let Some(stroke) = style.stroke {
+                        let scale = transform.scale_factor();
+                        let stroke_style = StrokeStyle {
+                            width: style.stroke_style.width * scale,
+                            dash_array: style
+                                .stroke_style
+                                .dash_array
+                                .iter()
+                                .map(|d| d * scale)
+                                .collect(),
+                            dash_offset: style.stroke_style.dash_offset * scale,
+                            ..style.stroke_style.clone()
+                        };
+                        add(fb.stroke_path_styled(&path, &stroke_style, stroke));
+                    }
+                }
+                Shape::Text { x, y, ref text } => {
+                    let fill = match style.fill {
+                        Some(fill) => fill,
+                        None => continue,
+                    };
+                    let text = text.trim();
+                    let size = (style.font_size * transform.scale_factor()).round() as usize;
+                    if text.is_empty() || size == 0 {
+                        continue;
+                    }
+                    let (mut x, y) = transform.apply((x, y));
+                    let width = text::measure_text(&fb.fonts, FontHandle::default(), size, text).width;
+                    match style.text_anchor {
+                        TextAnchor::Start => {}
+                        TextAnchor::Middle => x -= width / 2.0,
+                        TextAnchor::End => x -= width,
+                    }
+                    // Text is painted over the shapes behind it without hiding them
+                    let background = fb.text_background();
+                    fb.set_text_background(None);
+                    add(fb.draw_text(
+                        y.max(0.0) as usize,
+                        x.max(0.0) as usize,
+                        text.to_owned(),
+                        FontHandle::default(),
+                        size,
+                        fill,
+                        false,
+                    ));
+                    fb.set_text_background(background);
+                }
+            }
+        }
+        fb.pop_clip_rect();
+
+        match drawn {
+            Some(rect) => rect.intersection(dest),
+            None => mxcfb_rect::invalid(),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use rusttype::{point, Font, Scale};
+
+use framebuffer::common::{color, mxcfb_rect};
+use framebuffer::fonts::{FontHandle, FontRegistry};
+
+const ELLIPSIS: &str = "\u{2026}";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextAlignment {
+    Left,
+    Center,
+    Right,
+    /// Stretches the spaces between words so that every line but the last one of
+    /// each paragraph spans the full width
+    Justify,
+}
+
+/// Describes how `FramebufferDraw::draw_paragraph` lays out text
+#[derive(Clone, Debug)]
+pub struct ParagraphStyle {
+    pub font: FontHandle,
+    pub size: usize,
+    pub color: color,
+    pub alignment: TextAlignment,
+    /// Distance between two baselines, as a multiple of `size`
+    pub line_height: f32,
+    /// Ends the last visible line with an ellipsis when the text doesn't fit
+    pub ellipsis: bool,
+}
+
+impl Default for ParagraphStyle {
+    fn default() -> ParagraphStyle {
+        ParagraphStyle {
+            font: FontHandle::default(),
+            size: 32,
+            color: color::BLACK,
+            alignment: TextAlignment::Left,
+            line_height: 1.2,
+            ellipsis: true,
+        }
+    }
+}
+
+/// A line of text positioned by `layout_text`
+#[derive(Clone, Debug, PartialEq)]
+pub struct LaidOutLine {
+    pub text: String,
+    /// Baseline of the line
+    pub baseline: u32,
+    /// Left edge of the first glyph
+    pub left: u32,
+    /// Advance width of the line, including the stretched spaces when justified
+    pub width: u32,
+    /// Width of each space between words, which is only larger than the space of
+    /// the font when the line is justified
+    pub word_spacing: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextLayout {
+    pub lines: Vec<LaidOutLine>,
+    /// Set when some of the text didn't fit in the target rectangle
+    pub truncated: bool,
+    /// The region covered by the drawn glyphs, empty if nothing has been drawn
+    pub rect: mxcfb_rect,
+}
+
+/// Pixels covered by a glyph, relative to the origin of the text on the baseline
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphBounds {
+    pub top: i32,
+    pub left: i32,
+    pub bottom: i32,
+    pub right: i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphMetrics {
+    pub character: char,
+    /// Horizontal position of the glyph's origin, relative to the origin of the text
+    pub x: f32,
+    pub advance_width: f32,
+    /// `None` for glyphs that don't draw anything, such as spaces
+    pub bounds: Option<GlyphBounds>,
+}
+
+/// Dimensions of a single line of text, as computed by `measure_text`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextMetrics {
+    /// Advance width of the whole text
+    pub width: f32,
+    /// Distance from the baseline to the top of the font's tallest glyphs
+    pub ascent: f32,
+    /// Distance from the baseline to the bottom of the font's lowest glyphs (negative)
+    pub descent: f32,
+    /// Distance between two baselines recommended by the font
+    pub line_height: f32,
+    pub glyphs: Vec<GlyphMetrics>,
+}
+
+impl TextMetrics {
+    /// Returns the region `FramebufferDraw::draw_text` covers when drawing the measured
+    /// text with its baseline origin at `(y, x)`
+    pub fn rect_at(&self, y: usize, x: usize) -> mxcfb_rect {
+        let (y, x) = (y as i32, x as i32);
+        let (mut min_y, mut min_x, mut max_y, mut max_x) = (y, x, y, x);
+        for b in self.glyphs.iter().filter_map(|g| g.bounds) {
+            min_y = min_y.min(y + b.top);
+            min_x = min_x.min(x + b.left);
+            max_y = max_y.max(y + b.bottom);
+            max_x = max_x.max(x + b.right);
+        }
+        let (min_y, min_x) = (min_y.max(0), min_x.max(0));
+        mxcfb_rect {
+            top: min_y as u32,
+            left: min_x as u32,
+            height: (max_y - min_y).max(0) as u32,
+            width: (max_x - min_x).max(0) as u32,
+        }
+    }
+}
+
+/// Measures `text` as a single line set in `font` at `size`, without drawing it
+pub fn measure_text(
+    fonts: &FontRegistry,
+    font: FontHandle,
+    size: usize,
+    text: &str,
+) -> TextMetrics {
+    let font = fonts.get(font);
+    let scale = Scale::uniform(size as f32);
+    let v_metrics = font.v_metrics(scale);
+
+    let glyphs: Vec<GlyphMetrics> = text
+        .chars()
+        .zip(font.layout(text, scale, point(0.0, 0.0)))
+        .map(|(character, glyph)| GlyphMetrics {
+            character,
+            x: glyph.position().x,
+            advance_width: glyph.unpositioned().h_metrics().advance_width,
+            bounds: glyph.pixel_bounding_box().map(|bb| GlyphBounds {
+                top: bb.min.y,
+                left: bb.min.x,
+                bottom: bb.max.y,
+                right: bb.max.x,
+            }),
+        }).collect();
+
+    TextMetrics {
+        width: glyphs
+            .last()
+            .map(|g| g.x + g.advance_width)
+            .unwrap_or(0.0),
+        ascent: v_metrics.ascent,
+        descent: v_metrics.descent,
+        line_height: v_metrics.ascent - v_metrics.descent + v_metrics.line_gap,
+        glyphs,
+    }
+}
+
+/// Same as `measure_text(..).width`, without collecting the glyphs
+pub(crate) fn advance_width(font: &Font, scale: Scale, text: &str) -> f32 {
+    font.layout(text, scale, point(0.0, 0.0))
+        .last()
+        .map(|g| g.position().x + g.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.0)
+}
+
+/// Splits `word` into pieces no wider than `max_width`, keeping at least one character per piece
+fn break_word(font: &Font, scale: Scale, word: &str, max_width: f32) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    for c in word.chars() {
+        piece.push(c);
+        if piece.chars().count() > 1 && advance_width(font, scale, &piece) > max_width {
+            piece.pop();
+            pieces.push(piece);
+            piece = c.to_string();
+        }
+    }
+    pieces.push(piece);
+    pieces
+}
+
+/// Wraps a paragraph without explicit newlines into lines of words no wider than `max_width`
+fn wrap_paragraph(
+    font: &Font,
+    scale: Scale,
+    paragraph: &str,
+    max_width: f32,
+) -> Vec<Vec<String>> {
+    let mut lines: Vec<Vec<String>> = Vec::new();
+    let mut line: Vec<String> = Vec::new();
+    for word in paragraph.split_whitespace() {
+        let pieces = if advance_width(font, scale, word) > max_width {
+            break_word(font, scale, word, max_width)
+        } else {
+            vec![word.to_owned()]
+        };
+        for piece in pieces {
+            let mut candidate = line.join(" ");
+            if !candidate.is_empty() {
+                candidate.push(' ');
+            }
+            candidate.push_str(&piece);
+            if !line.is_empty() && advance_width(font, scale, &candidate) > max_width {
+                lines.push(line);
+                line = Vec::new();
+            }
+            line.push(piece);
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+/// Drops characters from the end of `text` until it fits in `max_width` with an ellipsis
+fn ellipsize(font: &Font, scale: Scale, text: &str, max_width: f32) -> String {
+    let mut text = text.to_owned();
+    loop {
+        while text.ends_with(char::is_whitespace) {
+            text.pop();
+        }
+        let candidate = format!("{0}{1}", text, ELLIPSIS);
+        if text.is_empty() || advance_width(font, scale, &candidate) <= max_width {
+            return candidate;
+        }
+        text.pop();
+    }
+}
+
+/// Breaks `text` into lines fitting in `rect`, honoring explicit newlines, and positions
+/// them according to `style`. The returned `rect` is left empty since nothing is drawn.
+pub fn layout_text(
+    fonts: &FontRegistry,
+    text: &str,
+    rect: mxcfb_rect,
+    style: &ParagraphStyle,
+) -> TextLayout {
+    let font = fonts.get(style.font);
+    let scale = Scale::uniform(style.size as f32);
+    let v_metrics = font.v_metrics(scale);
+    let max_width = rect.width as f32;
+    let space_width = advance_width(font, scale, " ");
+    let line_advance = style.size as f32 * style.line_height;
+
+    // Words of each line along with whether it ends a paragraph
+    let mut wrapped: Vec<(Vec<String>, bool)> = Vec::new();
+    for paragraph in text.split('\n') {
+        let lines = wrap_paragraph(font, scale, paragraph, max_width);
+        let count = lines.len();
+        for (i, line) in lines.into_iter().enumerate() {
+            wrapped.push((line, i + 1 == count));
+        }
+    }
+
+    // A line fits as long as its descender stays within the rectangle
+    let fitting = |i: usize| {
+        v_metrics.ascent + i as f32 * line_advance - v_metrics.descent <= rect.height as f32
+    };
+    let mut visible = 0;
+    while visible < wrapped.len() && fitting(visible) {
+        visible += 1;
+    }
+    let truncated = visible < wrapped.len();
+
+    let mut lines = Vec::with_capacity(visible);
+    for (i, &(ref words, last_of_paragraph)) in wrapped.iter().take(visible).enumerate() {
+        let mut line_text = words.join(" ");
+        let mut word_spacing = space_width;
+        let is_last_visible = i + 1 == visible;
+        if truncated && is_last_visible && style.ellipsis {
+            line_text = ellipsize(font, scale, &line_text, max_width);
+        }
+
+        let mut width = advance_width(font, scale, &line_text);
+        let stretch = style.alignment == TextAlignment::Justify
+            && !last_of_paragraph
+            && !(truncated && is_last_visible)
+            && words.len() > 1;
+        if stretch {
+            word_spacing += (max_width - width) / (words.len() - 1) as f32;
+            width = max_width;
+        }
+
+        let offset = match style.alignment {
+            TextAlignment::Left | TextAlignment::Justify => 0.0,
+            TextAlignment::Center => (max_width - width) / 2.0,
+            TextAlignment::Right => max_width - width,
+        };
+        lines.push(LaidOutLine {
+            text: line_text,
+            baseline: rect.top + (v_metrics.ascent + i as f32 * line_advance) as u32,
+            left: rect.left + offset.max(0.0) as u32,
+            width: width as u32,
+            word_spacing,
+        });
+    }
+
+    TextLayout {
+        lines,
+        truncated,
+        rect: mxcfb_rect {
+            top: rect.top,
+            left: rect.left,
+            height: 0,
+            width: 0,
+        },
+    }
+}
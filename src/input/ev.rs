@@ -2,12 +2,17 @@ use epoll;
 use evdev;
 use input;
 use std;
+use std::io;
+
+use device::DeviceProfile;
+use error::{Error, Result};
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 pub struct EvDevContext {
     device: input::InputDevice,
+    path: String,
     pub state: input::InputDeviceState,
     pub tx: std::sync::mpsc::Sender<input::InputEvent>,
     exit_requested: Arc<AtomicBool>,
@@ -34,14 +39,24 @@ impl EvDevContext {
         self.exit_requested.store(true, Ordering::Relaxed);
     }
 
+    /// Creates a context for `device`, reading from the node and decoding with the
+    /// geometry described by `profile`
     pub fn new(
         device: input::InputDevice,
+        profile: &DeviceProfile,
         tx: std::sync::mpsc::Sender<input::InputEvent>,
     ) -> EvDevContext {
+        let path = match device {
+            input::InputDevice::Wacom => profile.wacom.path.clone(),
+            input::InputDevice::Multitouch => profile.multitouch.path.clone(),
+            input::InputDevice::GPIO => profile.gpio_path.clone(),
+            _ => unreachable!(),
+        };
         EvDevContext {
             device,
+            path,
             tx,
-            state: input::InputDeviceState::new(device),
+            state: input::InputDeviceState::new(device, profile),
             started: Arc::new(AtomicBool::new(false)),
             exit_requested: Arc::new(AtomicBool::new(false)),
             exited: Arc::new(AtomicBool::new(false)),
@@ -49,72 +64,72 @@ impl EvDevContext {
     }
 
     /// Non-blocking function that will open the provided path and wait for more data with epoll
-    pub fn start(&mut self) {
+    pub fn start(&mut self) -> Result<()> {
         self.started.store(true, Ordering::Relaxed);
         self.exited.store(false, Ordering::Relaxed);
         self.exit_requested.store(false, Ordering::Relaxed);
 
-        let path = match self.device {
-            input::InputDevice::Wacom => "/dev/input/event0",
-            input::InputDevice::Multitouch => "/dev/input/event1",
-            input::InputDevice::GPIO => "/dev/input/event2",
-            _ => unreachable!(),
-        };
+        let path = self.path.clone();
+        let mut dev = evdev::Device::open(&path).map_err(|e| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Unable to open {0}: {1}", path, e),
+            ))
+        })?;
+        let mut v = vec![epoll::Event {
+            events: (epoll::Events::EPOLLET | epoll::Events::EPOLLIN | epoll::Events::EPOLLPRI)
+                .bits(),
+            data: 0,
+        }];
+        let epfd = epoll::create(false)?;
+        epoll::ctl(epfd, epoll::ControlOptions::EPOLL_CTL_ADD, dev.fd(), v[0])?;
 
-        match evdev::Device::open(&path) {
-            Err(e) => error!("Error while reading events from epoll fd: {0}", e),
-            Ok(mut dev) => {
-                let mut v = vec![epoll::Event {
-                    events: (epoll::Events::EPOLLET
-                        | epoll::Events::EPOLLIN
-                        | epoll::Events::EPOLLPRI)
-                        .bits(),
-                    data: 0,
-                }];
-                let epfd = epoll::create(false).unwrap();
-                epoll::ctl(epfd, epoll::ControlOptions::EPOLL_CTL_ADD, dev.fd(), v[0]).unwrap();
+        // init callback
+        info!("Init complete for {0}", path);
 
-                // init callback
-                info!("Init complete for {0}", String::from(path));
-
-                let exit_req = Arc::clone(&self.exit_requested);
-                let exited = Arc::clone(&self.exited);
-                let device_type = self.device;
-                let state = self.state.clone();
-                let tx = self.tx.clone();
-                let _ = std::thread::spawn(move || {
-                    while !exit_req.load(Ordering::Relaxed) {
-                        // -1 indefinite wait but it is okay because our EPOLL FD
-                        // is watching on ALL input devices at once.
-                        let res = epoll::wait(epfd, -1, &mut v[0..1]).unwrap();
-                        if res != 1 {
-                            warn!("epoll_wait returned {0}", res);
-                        }
+        let exit_req = Arc::clone(&self.exit_requested);
+        let exited = Arc::clone(&self.exited);
+        let device_type = self.device;
+        let state = self.state.clone();
+        let tx = self.tx.clone();
+        let _ = std::thread::spawn(move || {
+            while !exit_req.load(Ordering::Relaxed) {
+                // -1 indefinite wait but it is okay because our EPOLL FD
+                // is watching on ALL input devices at once.
+                match epoll::wait(epfd, -1, &mut v[0..1]) {
+                    Ok(1) => {}
+                    Ok(res) => warn!("epoll_wait returned {0}", res),
+                    Err(e) => {
+                        error!("Error while waiting on epoll fd: {0}", e);
+                        break;
+                    }
+                }
 
-                        for ev in dev.events_no_sync().unwrap() {
-                            // event callback
-                            let decoded_event = match device_type {
-                                input::InputDevice::Multitouch => {
-                                    input::multitouch::decode(&ev, &state)
-                                }
-                                input::InputDevice::Wacom => input::wacom::decode(&ev, &state),
-                                input::InputDevice::GPIO => input::gpio::decode(&ev, &state),
-                                _ => unreachable!(),
-                            };
-                            if let Some(event) = decoded_event {
-                                match tx.send(event) {
-                                    Ok(_) => {}
-                                    Err(e) => error!(
-                                        "Failed to write InputEvent into the channel: {0}",
-                                        e
-                                    ),
-                                };
-                            }
-                        }
+                let events = match dev.events_no_sync() {
+                    Ok(events) => events,
+                    Err(e) => {
+                        error!("Error while reading events from {0}: {1}", path, e);
+                        break;
                     }
-                    exited.store(true, Ordering::Relaxed);
-                });
+                };
+                for ev in events {
+                    // event callback
+                    let decoded_event = match device_type {
+                        input::InputDevice::Multitouch => input::multitouch::decode(&ev, &state),
+                        input::InputDevice::Wacom => input::wacom::decode(&ev, &state),
+                        input::InputDevice::GPIO => input::gpio::decode(&ev, &state),
+                        _ => unreachable!(),
+                    };
+                    if let Some(event) = decoded_event {
+                        match tx.send(event) {
+                            Ok(_) => {}
+                            Err(e) => error!("Failed to write InputEvent into the channel: {0}", e),
+                        };
+                    }
+                }
             }
-        }
+            exited.store(true, Ordering::Relaxed);
+        });
+        Ok(())
     }
 }
@@ -25,6 +25,8 @@ pub enum InputDeviceState {
     GPIOState(std::sync::Arc<gpio::GPIOState>),
 }
 
+use device::DeviceProfile;
+use framebuffer::common::Orientation;
 use std;
 use std::sync::Arc;
 impl Clone for InputDeviceState {
@@ -44,18 +46,27 @@ impl Clone for InputDeviceState {
 }
 
 impl InputDeviceState {
-    pub fn new(dev: InputDevice) -> InputDeviceState {
+    pub fn new(dev: InputDevice, profile: &DeviceProfile) -> InputDeviceState {
         match dev {
             InputDevice::GPIO => InputDeviceState::GPIOState(Arc::new(gpio::GPIOState::default())),
             InputDevice::Wacom => {
-                InputDeviceState::WacomState(Arc::new(wacom::WacomState::default()))
-            }
-            InputDevice::Multitouch => {
-                InputDeviceState::MultitouchState(Arc::new(multitouch::MultitouchState::default()))
+                InputDeviceState::WacomState(Arc::new(wacom::WacomState::new(profile)))
             }
+            InputDevice::Multitouch => InputDeviceState::MultitouchState(Arc::new(
+                multitouch::MultitouchState::new(profile),
+            )),
             _ => unreachable!(),
         }
     }
+
+    /// Sets the orientation positions decoded by this device are reported in
+    pub fn set_orientation(&self, orientation: Orientation) {
+        match self {
+            InputDeviceState::WacomState(ref state) => state.set_orientation(orientation),
+            InputDeviceState::MultitouchState(ref state) => state.set_orientation(orientation),
+            InputDeviceState::GPIOState(_) => {}
+        }
+    }
 }
 
 #[derive(PartialEq, Clone)]
@@ -1,25 +1,34 @@
-use framebuffer::common::{DISPLAYHEIGHT, DISPLAYWIDTH, MTHEIGHT, MTWIDTH};
+use atomic::Atomic;
+use device::{DeviceProfile, DigitizerProfile};
+use framebuffer::common::Orientation;
 
 use evdev::raw::input_event;
 use input::{InputDeviceState, InputEvent};
 use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering};
 
-const MT_HSCALAR: f32 = (DISPLAYWIDTH as f32) / (MTWIDTH as f32);
-const MT_VSCALAR: f32 = (DISPLAYHEIGHT as f32) / (MTHEIGHT as f32);
-
 pub struct MultitouchState {
+    digitizer: DigitizerProfile,
+    display_height: u32,
+    display_width: u32,
+    orientation: Atomic<Orientation>,
     last_pressure: AtomicU8,
     last_touch_size: AtomicU8,
     last_touch_id: AtomicU16,
+    /// Raw positions, as reported by the digitizer
     last_x: AtomicU16,
     last_y: AtomicU16,
     last_finger_id: AtomicU16,
     currently_touching: AtomicBool,
 }
 
-impl ::std::default::Default for MultitouchState {
-    fn default() -> Self {
+impl MultitouchState {
+    /// Creates the state of the multitouch digitizer of `profile`
+    pub fn new(profile: &DeviceProfile) -> MultitouchState {
         MultitouchState {
+            digitizer: profile.multitouch.clone(),
+            display_height: profile.display_height,
+            display_width: profile.display_width,
+            orientation: Atomic::new(Orientation::Rotate0),
             last_pressure: AtomicU8::new(0),
             last_touch_size: AtomicU8::new(0),
             last_touch_id: AtomicU16::new(0),
@@ -29,6 +38,34 @@ impl ::std::default::Default for MultitouchState {
             currently_touching: AtomicBool::new(false),
         }
     }
+
+    /// Sets the orientation the decoded positions are reported in
+    pub fn set_orientation(&self, orientation: Orientation) {
+        self.orientation.store(orientation, Ordering::Relaxed);
+    }
+
+    /// Returns the last touch position in display coordinates as `(y, x)`
+    fn last_position(&self) -> (u16, u16) {
+        let (y, x) = self.digitizer.map_to_display(
+            self.last_x.load(Ordering::Relaxed),
+            self.last_y.load(Ordering::Relaxed),
+            self.display_height,
+            self.display_width,
+        );
+        let (y, x) = self.orientation.load(Ordering::Relaxed).from_native(
+            u32::from(y),
+            u32::from(x),
+            self.display_height,
+            self.display_width,
+        );
+        (y as u16, x as u16)
+    }
+}
+
+impl ::std::default::Default for MultitouchState {
+    fn default() -> Self {
+        MultitouchState::new(&DeviceProfile::default())
+    }
 }
 
 #[derive(PartialEq, Copy, Clone)]
@@ -62,16 +99,13 @@ pub fn decode(ev: &input_event, outer_state: &InputDeviceState) -> Option<InputE
                     None
                 }
                 53 => {
-                    let val = ev.value as u16;
-                    state.last_x.store(MTWIDTH - val, Ordering::Relaxed);
+                    state.last_x.store(ev.value as u16, Ordering::Relaxed);
                     None
                 }
                 54 => {
-                    let val = ev.value as u16;
-                    state.last_y.store(MTHEIGHT - val, Ordering::Relaxed);
+                    state.last_y.store(ev.value as u16, Ordering::Relaxed);
 
-                    let y = (f32::from(state.last_y.load(Ordering::Relaxed)) * MT_VSCALAR) as u16;
-                    let x = (f32::from(state.last_x.load(Ordering::Relaxed)) * MT_HSCALAR) as u16;
+                    let (y, x) = state.last_position();
                     let event = MultitouchEvent::Touch {
                         gesture_seq: state.last_touch_id.load(Ordering::Relaxed),
                         finger_id: state.last_finger_id.load(Ordering::Relaxed),
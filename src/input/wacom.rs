@@ -4,10 +4,8 @@ use input::{InputDeviceState, InputEvent};
 use std;
 use std::sync::atomic::{AtomicU16, Ordering};
 
-use framebuffer::common::{DISPLAYHEIGHT, DISPLAYWIDTH, WACOMHEIGHT, WACOMWIDTH};
-
-const WACOM_HSCALAR: f32 = (DISPLAYWIDTH as f32) / (WACOMWIDTH as f32);
-const WACOM_VSCALAR: f32 = (DISPLAYHEIGHT as f32) / (WACOMHEIGHT as f32);
+use device::{DeviceProfile, DigitizerProfile};
+use framebuffer::common::Orientation;
 
 const EV_SYNC: u16 = 0;
 const EV_KEY: u16 = 1;
@@ -20,6 +18,11 @@ const WACOM_EVCODE_XPOS: u16 = 0;
 const WACOM_EVCODE_YPOS: u16 = 1;
 
 pub struct WacomState {
+    digitizer: DigitizerProfile,
+    display_height: u32,
+    display_width: u32,
+    orientation: Atomic<Orientation>,
+    /// Raw positions, as reported by the digitizer
     last_x: AtomicU16,
     last_y: AtomicU16,
     last_xtilt: AtomicU16,
@@ -29,9 +32,14 @@ pub struct WacomState {
     last_tool: Atomic<Option<WacomPen>>,
 }
 
-impl ::std::default::Default for WacomState {
-    fn default() -> Self {
+impl WacomState {
+    /// Creates the state of the Wacom digitizer of `profile`
+    pub fn new(profile: &DeviceProfile) -> WacomState {
         WacomState {
+            digitizer: profile.wacom.clone(),
+            display_height: profile.display_height,
+            display_width: profile.display_width,
+            orientation: Atomic::new(Orientation::Rotate0),
             last_x: AtomicU16::new(0),
             last_y: AtomicU16::new(0),
             last_xtilt: AtomicU16::new(0),
@@ -41,6 +49,34 @@ impl ::std::default::Default for WacomState {
             last_tool: Atomic::new(None),
         }
     }
+
+    /// Sets the orientation the decoded positions are reported in
+    pub fn set_orientation(&self, orientation: Orientation) {
+        self.orientation.store(orientation, Ordering::Relaxed);
+    }
+
+    /// Returns the last position of the pen in display coordinates as `(y, x)`
+    fn last_position(&self) -> (u16, u16) {
+        let (y, x) = self.digitizer.map_to_display(
+            self.last_x.load(Ordering::Relaxed),
+            self.last_y.load(Ordering::Relaxed),
+            self.display_height,
+            self.display_width,
+        );
+        let (y, x) = self.orientation.load(Ordering::Relaxed).from_native(
+            u32::from(y),
+            u32::from(x),
+            self.display_height,
+            self.display_width,
+        );
+        (y as u16, x as u16)
+    }
+}
+
+impl ::std::default::Default for WacomState {
+    fn default() -> Self {
+        WacomState::new(&DeviceProfile::default())
+    }
 }
 
 #[repr(u16)]
@@ -93,24 +129,30 @@ pub fn decode(ev: &input_event, outer_state: &InputDeviceState) -> Option<InputE
     };
     match ev._type {
         EV_SYNC => match state.last_tool.load(Ordering::Relaxed) {
-            Some(WacomPen::ToolPen) => Some(InputEvent::WacomEvent {
-                event: WacomEvent::Hover {
-                    y: (f32::from(state.last_y.load(Ordering::Relaxed)) * WACOM_VSCALAR) as u16,
-                    x: (f32::from(state.last_x.load(Ordering::Relaxed)) * WACOM_HSCALAR) as u16,
-                    distance: state.last_dist.load(Ordering::Relaxed) as u16,
-                    tilt_x: state.last_xtilt.load(Ordering::Relaxed),
-                    tilt_y: state.last_ytilt.load(Ordering::Relaxed),
-                },
-            }),
-            Some(WacomPen::Touch) => Some(InputEvent::WacomEvent {
-                event: WacomEvent::Draw {
-                    x: (f32::from(state.last_x.load(Ordering::Relaxed)) * WACOM_HSCALAR) as u16,
-                    y: (f32::from(state.last_y.load(Ordering::Relaxed)) * WACOM_VSCALAR) as u16,
-                    pressure: state.last_pressure.load(Ordering::Relaxed),
-                    tilt_x: state.last_xtilt.load(Ordering::Relaxed),
-                    tilt_y: state.last_ytilt.load(Ordering::Relaxed),
-                },
-            }),
+            Some(WacomPen::ToolPen) => {
+                let (y, x) = state.last_position();
+                Some(InputEvent::WacomEvent {
+                    event: WacomEvent::Hover {
+                        y,
+                        x,
+                        distance: state.last_dist.load(Ordering::Relaxed) as u16,
+                        tilt_x: state.last_xtilt.load(Ordering::Relaxed),
+                        tilt_y: state.last_ytilt.load(Ordering::Relaxed),
+                    },
+                })
+            }
+            Some(WacomPen::Touch) => {
+                let (y, x) = state.last_position();
+                Some(InputEvent::WacomEvent {
+                    event: WacomEvent::Draw {
+                        x,
+                        y,
+                        pressure: state.last_pressure.load(Ordering::Relaxed),
+                        tilt_x: state.last_xtilt.load(Ordering::Relaxed),
+                        tilt_y: state.last_ytilt.load(Ordering::Relaxed),
+                    },
+                })
+            }
             _ => None,
         },
         EV_KEY => {
@@ -166,12 +208,11 @@ pub fn decode(ev: &input_event, outer_state: &InputDeviceState) -> Option<InputE
                         .store(ev.value as u16, Ordering::Relaxed);;
                 }
                 WACOM_EVCODE_XPOS => {
-                    // x and y are inverted due to remarkable
-                    let val = ev.value as u16;
-                    state.last_y.store(WACOMHEIGHT - val, Ordering::Relaxed);
+                    // mapped onto the display by the DigitizerProfile upon sync
+                    state.last_x.store(ev.value as u16, Ordering::Relaxed);
                 }
                 WACOM_EVCODE_YPOS => {
-                    state.last_x.store(ev.value as u16, Ordering::Relaxed);
+                    state.last_y.store(ev.value as u16, Ordering::Relaxed);
                 }
                 _ => {
                     debug!(
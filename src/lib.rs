@@ -48,6 +48,7 @@ extern crate hlua;
 extern crate libc;
 extern crate mmap;
 extern crate rusttype;
+extern crate xml;
 extern crate zstd;
 
 pub extern crate epoll;
@@ -56,6 +57,13 @@ pub extern crate image;
 pub extern crate line_drawing;
 pub extern crate stopwatch;
 
+/// The error type shared by the rest of the crate
+pub mod error;
+pub use error::{Error, Result};
+
+/// Describes the display and digitizer geometry of the tablet revision being driven
+pub mod device;
+
 /// One of the core components, allowing output and refresh of the EInk display
 pub mod framebuffer;
 
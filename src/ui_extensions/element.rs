@@ -3,15 +3,24 @@ use std::hash::{Hash, Hasher};
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use image;
+use image::GenericImage;
 
 use framebuffer::common;
 use framebuffer::common::{color, mxcfb_rect};
+use framebuffer::dither::Dither;
+use framebuffer::fill::FillStyle;
+use framebuffer::fonts::{FontHandle, FontRegistry};
+use framebuffer::text;
 use framebuffer::refresh::PartialRefreshMode;
+use framebuffer::svg::SvgImage;
 use framebuffer::FramebufferDraw;
 use framebuffer::FramebufferRefresh;
 
 use appctx;
 
+/// Space left between a `UIElement::Text` and its border
+const TEXT_BORDER_PADDING: usize = 8;
+
 pub type ActiveRegionFunction = fn(&mut appctx::ApplicationContext, UIElementHandle);
 
 #[derive(Clone)]
@@ -71,18 +80,30 @@ impl Eq for UIElementWrapper {}
 pub enum UIElement {
     Text {
         text: String,
+        /// Defaults to the built-in font when left as `FontHandle::default()`
+        font: FontHandle,
         scale: usize,
         foreground: color,
         border_px: usize,
     },
     Image {
         img: image::DynamicImage,
+        /// Applied when drawing the image, see `FramebufferDraw::draw_image`
+        dither: Dither,
+    },
+    /// An SVG icon scaled to fit in `height` by `width`, see `FramebufferDraw::draw_svg`
+    Svg {
+        svg: SvgImage,
+        height: usize,
+        width: usize,
     },
     Region {
         height: usize,
         width: usize,
         border_color: color,
         border_px: usize,
+        /// Painted inside of the border, the region being left as it is when `None`
+        background: Option<FillStyle>,
     },
     Unspecified,
 }
@@ -102,6 +123,45 @@ impl UIElementHandle {
 }
 
 impl UIElementWrapper {
+    /// Returns the region the element will occupy once drawn at its current position
+    pub fn expected_rect(&self, fonts: &FontRegistry) -> Option<mxcfb_rect> {
+        let (y, x) = (self.y as u32, self.x as u32);
+        match self.inner {
+            UIElement::Text {
+                ref text,
+                font,
+                scale,
+                border_px,
+                ..
+            } => {
+                let mut rect = text::measure_text(fonts, font, scale, text).rect_at(self.y, self.x);
+                if border_px > 0 {
+                    let padding = TEXT_BORDER_PADDING as u32;
+                    rect.top = rect.top.saturating_sub(padding);
+                    rect.left = rect.left.saturating_sub(padding);
+                    rect.height += 2 * padding;
+                    rect.width += 2 * padding;
+                }
+                Some(rect)
+            }
+            UIElement::Image { ref img, .. } => Some(mxcfb_rect {
+                top: y,
+                left: x,
+                height: img.height(),
+                width: img.width(),
+            }),
+            UIElement::Svg { height, width, .. } | UIElement::Region { height, width, .. } => {
+                Some(mxcfb_rect {
+                    top: y,
+                    left: x,
+                    height: height as u32,
+                    width: width as u32,
+                })
+            }
+            UIElement::Unspecified => None,
+        }
+    }
+
     pub fn draw(
         &mut self,
         app: &mut appctx::ApplicationContext,
@@ -110,6 +170,7 @@ impl UIElementWrapper {
         let (x, y) = (self.x, self.y);
         let refresh = self.refresh;
         let framebuffer = app.get_framebuffer_ref();
+        let expected_rect = self.expected_rect(&framebuffer.fonts);
 
         let old_filled_rect = match self.last_drawn_rect {
             Some(rect) => {
@@ -122,13 +183,12 @@ impl UIElementWrapper {
                     color::WHITE,
                 );
 
-                // We have filled the old_filled_rect, now we need to also refresh that but if
-                // only if it isn't at the same spot. Otherwise we will be refreshing it for no
-                // reason and showing a blank frame. There is of course still a caveat since we don't
-                // know the dimensions of a drawn text before it is actually drawn.
-                // TODO: Take care of the point above ^
-                if rect.top != y as u32 && rect.left != x as u32 {
-                    framebuffer.partial_refresh(
+                // We have filled the old_filled_rect, now we need to also refresh that but
+                // only if the element isn't about to be drawn over all of it. Otherwise we will
+                // be refreshing it for no reason and showing a blank frame.
+                let covered = expected_rect.map_or(false, |r| r.contains_rect(&rect));
+                if !covered {
+                    if let Err(e) = framebuffer.partial_refresh(
                         &rect,
                         PartialRefreshMode::Wait,
                         common::waveform_mode::WAVEFORM_MODE_DU,
@@ -136,7 +196,9 @@ impl UIElementWrapper {
                         common::dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                         0,
                         false,
-                    );
+                    ) {
+                        warn!("Failed to refresh the cleared element area: {0}", e);
+                    }
                 }
 
                 rect
@@ -149,6 +211,7 @@ impl UIElementWrapper {
         let rect = match self.inner {
             UIElement::Text {
                 ref text,
+                font,
                 scale,
                 foreground,
                 border_px,
@@ -156,19 +219,35 @@ impl UIElementWrapper {
                 y,
                 x,
                 foreground,
+                font,
                 scale,
                 border_px,
-                8,
+                TEXT_BORDER_PADDING,
                 text.to_string(),
                 refresh,
             ),
-            UIElement::Image { ref img } => app.display_image(&img, y, x, refresh),
+            UIElement::Image { ref img, dither } => app.display_image(&img, y, x, dither, refresh),
+            UIElement::Svg {
+                ref svg,
+                height,
+                width,
+            } => app.display_svg(svg, y, x, height, width, refresh),
             UIElement::Region {
                 height,
                 width,
                 border_color,
                 border_px,
-            } => app.display_rect(y, x, height, width, border_px, border_color, refresh),
+                ref background,
+            } => app.display_rect(
+                y,
+                x,
+                height,
+                width,
+                border_px,
+                border_color,
+                background.as_ref(),
+                refresh,
+            ),
             UIElement::Unspecified => return,
         };
 
@@ -197,7 +276,7 @@ impl UIElementWrapper {
 
         if let Some(last_rect) = self.last_drawn_rect {
             if last_rect != rect {
-                framebuffer.partial_refresh(
+                if let Err(e) = framebuffer.partial_refresh(
                     &last_rect,
                     PartialRefreshMode::Async,
                     common::waveform_mode::WAVEFORM_MODE_DU,
@@ -205,7 +284,9 @@ impl UIElementWrapper {
                     common::dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                     0,
                     false,
-                );
+                ) {
+                    warn!("Failed to refresh the previous element area: {0}", e);
+                }
             }
         }
 
@@ -3,6 +3,7 @@ use std;
 
 use framebuffer::common::*;
 use framebuffer::core;
+use framebuffer::fonts::FontHandle;
 
 use framebuffer::refresh::PartialRefreshMode;
 
@@ -45,7 +46,7 @@ pub fn lua_refresh(
             height: nheight as u32,
             width: nwidth as u32,
         };
-        if bdeep {
+        let result = if bdeep {
             framebuffer.partial_refresh(
                 &rect,
                 if bwait {
@@ -58,7 +59,7 @@ pub fn lua_refresh(
                 dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                 0,
                 false,
-            );
+            )
         } else {
             framebuffer.partial_refresh(
                 &rect,
@@ -72,11 +73,15 @@ pub fn lua_refresh(
                 dither_mode::EPDC_FLAG_EXP1,
                 DRAWING_QUANT_BIT,
                 false,
-            );
+            )
+        };
+        if let Err(e) = result {
+            warn!("Failed to refresh the region requested from Lua: {0}", e);
         }
     }
 }
 
+/// Draws text with the luminance `color`, 0 being black and 255 white
 pub fn lua_draw_text(
     y: hlua::AnyLuaValue,
     x: hlua::AnyLuaValue,
@@ -99,6 +104,7 @@ pub fn lua_draw_text(
             ny as usize,
             nx as usize,
             stext,
+            FontHandle::default(),
             nsize as usize,
             color::GRAY(ncolor as u8),
             false,
@@ -106,6 +112,7 @@ pub fn lua_draw_text(
     };
 }
 
+/// Sets a pixel to the luminance `color`, 0 being black and 255 white
 pub fn lua_set_pixel(y: hlua::AnyLuaValue, x: hlua::AnyLuaValue, color: hlua::AnyLuaValue) {
     if let (
         hlua::AnyLuaValue::LuaNumber(ny),
@@ -121,11 +128,13 @@ pub fn lua_set_pixel(y: hlua::AnyLuaValue, x: hlua::AnyLuaValue, color: hlua::An
 pub fn lua_clear() {
     let framebuffer = get_current_framebuffer!();
     framebuffer.clear();
-    framebuffer.full_refresh(
+    if let Err(e) = framebuffer.full_refresh(
         waveform_mode::WAVEFORM_MODE_INIT,
         display_temp::TEMP_USE_AMBIENT,
         dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
         0,
         true,
-    );
+    ) {
+        warn!("Failed to refresh the cleared screen from Lua: {0}", e);
+    }
 }
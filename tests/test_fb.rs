@@ -1,7 +1,35 @@
-//extern crate libremarkable;
-//use libremarkable::fb::{Framebuffer,FramebufferBase};
-//use libremarkable::fbdraw::FramebufferDraw;
-//use libremarkable::mxc_types::{VarScreeninfo,FixScreeninfo};
+extern crate libremarkable;
+
+use libremarkable::framebuffer::backend::MemoryBackend;
+use libremarkable::framebuffer::common::*;
+use libremarkable::framebuffer::core::Framebuffer;
+use libremarkable::framebuffer::path::Path;
+use libremarkable::framebuffer::refresh::{PartialRefreshMode, PxpFlags};
+use libremarkable::framebuffer::{
+    FramebufferBuffering, FramebufferDraw, FramebufferIO, FramebufferRefresh,
+};
+
+/// A cleared in-memory framebuffer of `height` by `width` 8-bit gray pixels
+fn gray_fb(height: u32, width: u32) -> Framebuffer<'static> {
+    let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(height, width)));
+    fb.set_pixel_format(PixelFormat::Gray8).unwrap();
+    fb.clear();
+    fb
+}
+
+/// The luminance of the pixel at `(y, x)`, 0 being black
+fn gray_at(fb: &Framebuffer, y: usize, x: usize) -> u8 {
+    fb.read_pixel(y, x).to_gray8()
+}
+
+fn is_black(fb: &Framebuffer, y: usize, x: usize) -> bool {
+    gray_at(fb, y, x) == 0x00
+}
+
+/// Whether the pixel at `(y, x)` is closer to black than to white, for antialiased edges
+fn is_dark(fb: &Framebuffer, y: usize, x: usize) -> bool {
+    gray_at(fb, y, x) < 0x80
+}
 
 #[test]
 fn test_internal() {
@@ -9,4 +37,1800 @@ fn test_internal() {
     assert_eq!(42, 42);
 }
 
+#[test]
+fn test_memory_backend_pixels() {
+    let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(100, 50)));
+    assert_eq!(fb.var_screen_info.yres, 100);
+    assert_eq!(fb.var_screen_info.xres, 50);
+    assert_eq!(fb.read_pixel(10, 10), color::from_native(color::WHITE.as_native()));
+
+    fb.write_pixel(10, 10, color::BLACK);
+    assert_eq!(fb.read_pixel(10, 10), color::from_native(color::BLACK.as_native()));
+
+    // Out of range writes are ignored
+    fb.write_pixel(100, 50, color::BLACK);
+}
+
+#[test]
+fn test_memory_backend_dump_restore() {
+    let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(100, 50)));
+    let rect = mxcfb_rect {
+        top: 5,
+        left: 5,
+        height: 10,
+        width: 20,
+    };
+    fb.fill_rect(5, 5, 10, 20, color::BLACK);
+    let dump = fb.dump_region(rect).unwrap();
+    assert_eq!(dump.len(), 10 * 20 * 2);
+
+    fb.clear();
+    assert_eq!(fb.read_pixel(6, 6), color::from_native(color::WHITE.as_native()));
+    fb.restore_region(rect, &dump).unwrap();
+    assert_eq!(fb.read_pixel(6, 6), color::from_native(color::BLACK.as_native()));
+
+    assert!(fb
+        .dump_region(mxcfb_rect {
+            top: 95,
+            left: 0,
+            height: 10,
+            width: 10,
+        })
+        .is_err());
+}
+
+#[test]
+fn test_memory_backend_records_refreshes() {
+    let backend = MemoryBackend::new(100, 50);
+    let log = backend.refresh_log();
+    let fb = Framebuffer::from_backend(Box::new(backend));
+
+    let marker = fb.partial_refresh(
+        &mxcfb_rect {
+            top: 80,
+            left: 30,
+            height: 10,
+            width: 10,
+        },
+        PartialRefreshMode::Async,
+        waveform_mode::WAVEFORM_MODE_DU,
+        display_temp::TEMP_USE_REMARKABLE_DRAW,
+        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        false,
+    ).unwrap();
+    fb.full_refresh(
+        waveform_mode::WAVEFORM_MODE_INIT,
+        display_temp::TEMP_USE_AMBIENT,
+        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        true,
+    ).unwrap();
+
+    let refreshes = log.lock().unwrap();
+    assert_eq!(refreshes.len(), 2);
+    assert_eq!(refreshes[0].update_marker, marker);
+    assert_eq!(
+        refreshes[0].update_region,
+        mxcfb_rect {
+            top: 80,
+            left: 30,
+            height: 10,
+            width: 10,
+        }
+    );
+    assert_eq!(refreshes[1].update_region.height, 100);
+    assert_eq!(refreshes[1].update_region.width, 50);
+}
+
+#[test]
+fn test_pxp_refresh_flags() {
+    let backend = MemoryBackend::new(100, 50);
+    let log = backend.refresh_log();
+    let mut fb = Framebuffer::from_backend(Box::new(backend));
+    let region = mxcfb_rect {
+        top: 10,
+        left: 10,
+        height: 20,
+        width: 20,
+    };
+    fb.fill_rect(10, 10, 20, 20, color::BLACK);
+
+    fb.partial_refresh_with_flags(
+        &region,
+        PartialRefreshMode::DryRun,
+        waveform_mode::WAVEFORM_MODE_DU,
+        display_temp::TEMP_USE_REMARKABLE_DRAW,
+        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        false,
+        PxpFlags {
+            monochrome: true,
+            ..PxpFlags::default()
+        },
+    ).unwrap();
+    fb.invert_region(&region, waveform_mode::WAVEFORM_MODE_DU, true).unwrap();
+    fb.set_night_mode(true);
+    fb.full_refresh(
+        waveform_mode::WAVEFORM_MODE_GC16,
+        display_temp::TEMP_USE_AMBIENT,
+        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        true,
+    ).unwrap();
+    fb.invert_region(&region, waveform_mode::WAVEFORM_MODE_DU, true).unwrap();
+
+    let flags: Vec<u32> = log.lock().unwrap().iter().map(|u| u.flags).collect();
+    assert_eq!(
+        flags,
+        vec![
+            EPDC_FLAG_TEST_COLLISION | EPDC_FLAG_FORCE_MONOCHROME,
+            EPDC_FLAG_ENABLE_INVERSION,
+            EPDC_FLAG_ENABLE_INVERSION,
+            0,
+        ]
+    );
+    // The EPDC does the inverting, the pixels stay as they were drawn
+    assert_eq!(fb.read_pixel(15, 15), color::from_native(color::BLACK.as_native()));
+}
+
+#[test]
+fn test_device_profile_digitizer_mapping() {
+    use libremarkable::device::DeviceProfile;
+
+    let profile = DeviceProfile::remarkable();
+    let (height, width) = (profile.display_height, profile.display_width);
+
+    // The pen reports its X position along the height of the display, bottom to top
+    let (raw_x, raw_y) = (5000, 12000);
+    let (y, x) = profile.wacom.map_to_display(raw_x, raw_y, height, width);
+    assert_eq!(
+        y,
+        (f32::from(WACOMHEIGHT - raw_x) * (f32::from(DISPLAYHEIGHT) / f32::from(WACOMHEIGHT)))
+            as u16
+    );
+    assert_eq!(
+        x,
+        (f32::from(raw_y) * (f32::from(DISPLAYWIDTH) / f32::from(WACOMWIDTH))) as u16
+    );
+
+    // Both touch axes are inverted
+    let (raw_x, raw_y) = (100, 900);
+    let (y, x) = profile.multitouch.map_to_display(raw_x, raw_y, height, width);
+    assert_eq!(
+        y,
+        (f32::from(MTHEIGHT - raw_y) * (f32::from(DISPLAYHEIGHT) / f32::from(MTHEIGHT))) as u16
+    );
+    assert_eq!(
+        x,
+        (f32::from(MTWIDTH - raw_x) * (f32::from(DISPLAYWIDTH) / f32::from(MTWIDTH))) as u16
+    );
+
+    // Out of range positions are clamped to the edges of the display
+    assert_eq!(profile.multitouch.map_to_display(0xffff, 0xffff, height, width), (0, 0));
+    assert_eq!(
+        profile.multitouch.map_to_display(0, 0, height, width),
+        (height as u16 - 1, width as u16 - 1)
+    );
+}
+
+#[test]
+fn test_orientation_rotates_pixels_and_refreshes() {
+    let backend = MemoryBackend::new(100, 50);
+    let log = backend.refresh_log();
+    let mut fb = Framebuffer::from_backend(Box::new(backend));
+    fb.set_orientation(Orientation::Rotate90);
+    assert_eq!(fb.dimensions(), (50, 100));
+
+    // The top left corner ends up in the top right corner of the panel
+    fb.write_pixel(0, 0, color::BLACK);
+    assert_eq!(fb.read_pixel(0, 0), color::from_native(color::BLACK.as_native()));
+    fb.set_orientation(Orientation::Rotate0);
+    assert_eq!(fb.read_pixel(0, 49), color::from_native(color::BLACK.as_native()));
+    fb.set_orientation(Orientation::Rotate90);
+
+    let region = mxcfb_rect {
+        top: 10,
+        left: 20,
+        height: 5,
+        width: 30,
+    };
+    let dump = fb.dump_region(region).unwrap();
+    assert_eq!(dump.len(), 5 * 30 * 2);
+    fb.restore_region(region, &dump).unwrap();
+
+    fb.partial_refresh(
+        &region,
+        PartialRefreshMode::Async,
+        waveform_mode::WAVEFORM_MODE_DU,
+        display_temp::TEMP_USE_AMBIENT,
+        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        false,
+    ).unwrap();
+    let refreshes = log.lock().unwrap();
+    assert_eq!(
+        refreshes[0].update_region,
+        mxcfb_rect {
+            top: 20,
+            left: 35,
+            height: 30,
+            width: 5,
+        }
+    );
+}
+
+#[test]
+fn test_orientation_maps_display_edges() {
+    let (height, width) = (1872, 1404);
+    let cases = [
+        (Orientation::Rotate0, (0, 0), (1871, 1403)),
+        (Orientation::Rotate90, (1403, 0), (0, 1871)),
+        (Orientation::Rotate180, (1871, 1403), (0, 0)),
+        (Orientation::Rotate270, (0, 1871), (1403, 0)),
+    ];
+    for &(orientation, top_left, bottom_right) in &cases {
+        assert_eq!(orientation.from_native(0, 0, height, width), top_left);
+        assert_eq!(orientation.from_native(1871, 1403, height, width), bottom_right);
+        // One past the far edges, which the digitizers can report, doesn't wrap around
+        let (y, x) = orientation.from_native(height, width, height, width);
+        let (oriented_height, oriented_width) = orientation.dimensions(height, width);
+        assert!(y <= oriented_height && x <= oriented_width, "{0:?}", orientation);
+    }
+}
+
+#[test]
+fn test_back_buffer_present() {
+    let backend = MemoryBackend::new(100, 50);
+    let log = backend.refresh_log();
+    let mut fb = Framebuffer::from_backend(Box::new(backend));
+    let black = color::from_native(color::BLACK.as_native());
+    let white = color::from_native(color::WHITE.as_native());
+
+    fb.enable_back_buffer().unwrap();
+    fb.fill_rect(10, 10, 5, 5, color::BLACK);
+    assert_eq!(fb.read_pixel(12, 12), black);
+
+    // Nothing reaches the visible frame before it is presented
+    fb.disable_back_buffer();
+    assert_eq!(fb.read_pixel(12, 12), white);
+    fb.enable_back_buffer().unwrap();
+    fb.fill_rect(10, 10, 5, 5, color::BLACK);
+
+    let region = mxcfb_rect {
+        top: 10,
+        left: 10,
+        height: 5,
+        width: 5,
+    };
+    fb.present(
+        &region,
+        waveform_mode::WAVEFORM_MODE_DU,
+        display_temp::TEMP_USE_AMBIENT,
+        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        true,
+    ).unwrap();
+    fb.disable_back_buffer();
+    assert_eq!(fb.read_pixel(12, 12), black);
+
+    let refreshes = log.lock().unwrap();
+    assert_eq!(refreshes.len(), 1);
+    assert_eq!(refreshes[0].flags & EPDC_FLAG_USE_ALT_BUFFER, EPDC_FLAG_USE_ALT_BUFFER);
+    assert_eq!(refreshes[0].alt_buffer_data.alt_update_region, region);
+    assert_eq!(refreshes[0].alt_buffer_data.height, 100);
+}
+
+#[test]
+fn test_gray8_pixel_format() {
+    let mut fb = gray_fb(100, 50);
+    assert_eq!(fb.pixel_format(), PixelFormat::Gray8);
+    assert_eq!(fb.var_screen_info.bits_per_pixel, 8);
+    assert_eq!(fb.fix_screen_info.line_length, 50);
+
+    fb.write_pixel(10, 10, color::BLACK);
+    fb.write_pixel(10, 11, color::GRAY(0x40));
+    assert_eq!(fb.read_pixel(10, 10), color::GRAY(0x00));
+    assert_eq!(fb.read_pixel(10, 11), color::GRAY(0x40));
+    assert_eq!(fb.read_pixel(10, 12), color::GRAY(0xFF));
+
+    let region = mxcfb_rect {
+        top: 10,
+        left: 10,
+        height: 1,
+        width: 3,
+    };
+    let dump = fb.dump_region(region).unwrap();
+    assert_eq!(dump, vec![0x00, 0x40, 0xFF]);
+
+    let img = libremarkable::framebuffer::storage::rgbimage_from_u8_slice(
+        3,
+        1,
+        &dump,
+        PixelFormat::Gray8,
+    ).unwrap();
+    assert_eq!(img.get_pixel(0, 0).data, [0, 0, 0]);
+    assert_eq!(img.get_pixel(1, 0).data, [0x40, 0x40, 0x40]);
+    assert_eq!(img.get_pixel(2, 0).data, [0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn test_font_registry() {
+    use libremarkable::framebuffer::fonts::{FontHandle, FontStyle, FontWeight};
+
+    let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(200, 400)));
+    let bold = fb
+        .fonts
+        .load_file(
+            "Headline",
+            FontWeight::Bold,
+            FontStyle::Normal,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/assets/Roboto-Regular.ttf"),
+        ).unwrap();
+    assert_ne!(bold, FontHandle::default());
+    assert_eq!(
+        fb.fonts.find("Headline", FontWeight::Bold, FontStyle::Normal),
+        Some(bold)
+    );
+    // Closest weight is picked when there is no exact match
+    assert_eq!(
+        fb.fonts.find("Headline", FontWeight::Black, FontStyle::Italic),
+        Some(bold)
+    );
+    assert_eq!(
+        fb.fonts.find("Roboto", FontWeight::Regular, FontStyle::Normal),
+        Some(FontHandle::default())
+    );
+    assert_eq!(fb.fonts.find("Missing", FontWeight::Regular, FontStyle::Normal), None);
+
+    let with_default = fb.draw_text(
+        50,
+        10,
+        "Hello".to_owned(),
+        FontHandle::default(),
+        30,
+        color::BLACK,
+        true,
+    );
+    let with_loaded = fb.draw_text(50, 10, "Hello".to_owned(), bold, 30, color::BLACK, true);
+    assert_eq!(with_default, with_loaded);
+    assert!(with_loaded.width > 0);
+}
+
+#[test]
+fn test_paragraph_layout() {
+    use libremarkable::framebuffer::text::{ParagraphStyle, TextAlignment};
+
+    let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(400, 400)));
+    let rect = mxcfb_rect {
+        top: 20,
+        left: 20,
+        height: 120,
+        width: 200,
+    };
+    let mut style = ParagraphStyle {
+        size: 30,
+        alignment: TextAlignment::Right,
+        ellipsis: false,
+        ..Default::default()
+    };
+
+    let layout = fb.draw_paragraph(
+        rect,
+        "The quick brown fox\njumps over the lazy dog and keeps on running",
+        &style,
+        false,
+    );
+    assert_eq!(layout.lines[0].text, "The quick brown");
+    assert_eq!(layout.lines[1].text, "fox");
+    assert_eq!(layout.lines[2].text, "jumps over the");
+    // Only three lines of 36px fit in 120px
+    assert_eq!(layout.lines.len(), 3);
+    assert!(layout.truncated);
+    for line in &layout.lines {
+        assert!(line.width <= rect.width);
+        // Flush right, give or take rounding
+        assert!(rect.left + rect.width - (line.left + line.width) <= 1);
+    }
+    assert!(rect.contains_rect(&layout.rect));
+    assert!(layout.rect.width > 0 && layout.rect.height > 0);
+
+    style.ellipsis = true;
+    let ellipsized = fb.draw_paragraph(
+        rect,
+        "The quick brown fox jumps over the lazy dog and keeps on running",
+        &style,
+        true,
+    );
+    assert!(ellipsized.lines[2].text.ends_with('\u{2026}'));
+}
+
+#[test]
+fn test_measure_text() {
+    use libremarkable::framebuffer::fonts::FontHandle;
+    use libremarkable::framebuffer::text::measure_text;
+
+    let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(200, 400)));
+    let metrics = measure_text(&fb.fonts, FontHandle::default(), 40, "Hey you");
+    assert_eq!(metrics.glyphs.len(), 7);
+    assert_eq!(metrics.glyphs[3].character, ' ');
+    assert_eq!(metrics.glyphs[3].bounds, None);
+    assert!(metrics.ascent > 0.0 && metrics.descent < 0.0);
+    assert!(metrics.line_height >= metrics.ascent - metrics.descent);
+    let last = metrics.glyphs.last().unwrap();
+    assert_eq!(metrics.width, last.x + last.advance_width);
+
+    // Matches what draw_text reports without needing to draw
+    let drawn = fb.draw_text(
+        100,
+        20,
+        "Hey you".to_owned(),
+        FontHandle::default(),
+        40,
+        color::BLACK,
+        true,
+    );
+    assert_eq!(metrics.rect_at(100, 20), drawn);
+}
+
+#[test]
+fn test_antialiased_line_between_rows() {
+    let mut fb = gray_fb(100, 100);
+
+    // A thin line between two rows is shared evenly by both of them
+    let rect = fb.draw_line_antialiased(20.5, 10.0, 20.5, 60.0, 1.0, color::BLACK);
+    assert_eq!(rect.top, 20);
+    assert_eq!(rect.height, 2);
+    let (above, below) = (gray_at(&fb, 20, 30), gray_at(&fb, 21, 30));
+    assert!(above > 100 && above < 155);
+    assert!(below > 100 && below < 155);
+    assert_eq!(gray_at(&fb, 19, 30), 0xFF);
+}
+
+#[test]
+fn test_antialiased_thick_line() {
+    let mut fb = gray_fb(100, 100);
+
+    // Thick strokes are solid in the middle and fade out on their edges
+    let rect = fb.draw_line_antialiased(60.0, 20.0, 60.0, 80.0, 6.5, color::BLACK);
+    assert!(is_black(&fb, 60, 50));
+    assert!(is_black(&fb, 62, 50));
+    let edge = gray_at(&fb, 63, 50);
+    assert!(edge > 0x00 && edge < 0xFF);
+    assert_eq!(gray_at(&fb, 64, 50), 0xFF);
+    assert!(rect.top <= 57 && rect.top + rect.height >= 64);
+}
+
+#[test]
+fn test_antialiased_bezier() {
+    let mut fb = gray_fb(100, 100);
+
+    let rect = fb.draw_bezier_antialiased(
+        (10.0, 90.0),
+        (50.0, 70.0),
+        (90.0, 90.0),
+        2.0,
+        color::BLACK,
+    );
+    assert!(rect.width > 0 && rect.left <= 10 && rect.left + rect.width >= 90);
+    let grays = (rect.top..rect.top + rect.height)
+        .flat_map(|y| (rect.left..rect.left + rect.width).map(move |x| (y, x)))
+        .map(|(y, x)| gray_at(&fb, y as usize, x as usize))
+        .filter(|&v| v > 0x00 && v < 0xFF)
+        .count();
+    assert!(grays > 0);
+}
+
+#[test]
+fn test_antialiased_line_far_off_screen() {
+    let mut fb = gray_fb(100, 100);
+
+    // Only the part crossing the display is rasterized
+    let rect = fb.draw_line_antialiased(-1.0e7, -1.0e7, 1.0e7, 1.0e7, 3.0, color::BLACK);
+    assert!(is_black(&fb, 50, 50));
+    assert!(rect.top + rect.height <= 100 && rect.left + rect.width <= 100);
+}
+
+/// A square path a million pixels across around the origin
+fn huge_square() -> Path {
+    let mut huge = Path::new();
+    huge.move_to((-1.0e6, -1.0e6))
+        .line_to((1.0e6, -1.0e6))
+        .line_to((1.0e6, 1.0e6))
+        .line_to((-1.0e6, 1.0e6))
+        .close();
+    huge
+}
+
+#[test]
+fn test_huge_path_fill_is_clipped() {
+    let mut fb = gray_fb(100, 100);
+    let clip = mxcfb_rect {
+        top: 0,
+        left: 0,
+        height: 10,
+        width: 10,
+    };
+
+    fb.push_clip_rect(clip);
+    let rect = fb.fill_path(&huge_square(), FillRule::NonZero, color::BLACK);
+    fb.pop_clip_rect();
+    assert_eq!(rect, clip);
+    assert_eq!(gray_at(&fb, 10, 0), 0xFF);
+}
+
+#[test]
+fn test_strokes_entirely_off_screen() {
+    use libremarkable::framebuffer::ink::StrokePoint;
+    use libremarkable::framebuffer::stroke::StrokeStyle;
+
+    let mut fb = gray_fb(100, 100);
+
+    // Shapes entirely off the display draw nothing
+    let far = [(1.0e8, 1.0e8), (1.0e8 + 5.0e4, 1.0e8), (1.0e8, 1.0e8 + 5.0e4)];
+    let rect = fb.draw_polyline(&far, true, &StrokeStyle::new(4.0), color::BLACK);
+    assert_eq!(rect.width, 0);
+    let ink: Vec<StrokePoint> = far.iter().map(|&(x, y)| StrokePoint::new(y, x, 6.0)).collect();
+    let rect = fb.draw_ink_stroke(&ink, color::BLACK);
+    assert_eq!(rect.width, 0);
+    let rect = fb.stroke_path(&huge_square(), 2.0, color::BLACK);
+    assert_eq!(rect.width, 0);
+    assert_eq!(gray_at(&fb, 50, 50), 0xFF);
+}
+
+#[test]
+fn test_path_fill_on_pixel_boundaries() {
+    let mut fb = gray_fb(200, 200);
+
+    // A square whose edges fall on pixel boundaries is filled without any gray
+    let mut square = Path::new();
+    square
+        .move_to((9.5, 9.5))
+        .line_to((29.5, 9.5))
+        .line_to((29.5, 29.5))
+        .line_to((9.5, 29.5))
+        .close();
+    let rect = fb.fill_path(&square, FillRule::NonZero, color::BLACK);
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 10,
+            left: 10,
+            height: 20,
+            width: 20,
+        }
+    );
+    assert!(is_black(&fb, 10, 10));
+    assert!(is_black(&fb, 29, 29));
+    assert_eq!(gray_at(&fb, 30, 20), 0xFF);
+}
+
+#[test]
+fn test_path_arcs() {
+    use libremarkable::framebuffer::path::PathSegment;
+
+    let mut fb = gray_fb(200, 200);
+
+    // Two half circles make a full one
+    let mut circle = Path::new();
+    circle
+        .move_to((80.0, 100.0))
+        .arc_to(20.0, 20.0, 0.0, false, true, (120.0, 100.0))
+        .arc_to(20.0, 20.0, 0.0, false, true, (80.0, 100.0))
+        .close();
+    assert!(circle.segments().iter().all(|s| match *s {
+        PathSegment::LineTo(_) | PathSegment::QuadTo(_, _) => false,
+        _ => true,
+    }));
+    let rect = fb.fill_path(&circle, FillRule::NonZero, color::BLACK);
+    assert!(rect.top >= 79 && rect.top <= 80);
+    assert!(rect.left >= 79 && rect.left <= 80);
+    assert!(rect.height >= 40 && rect.height <= 42);
+    assert!(is_black(&fb, 100, 100));
+    assert_eq!(gray_at(&fb, 84, 84), 0xFF);
+}
+
+#[test]
+fn test_path_stroke() {
+    let mut fb = gray_fb(200, 200);
+
+    // Strokes leave the enclosed area untouched
+    let mut outline = Path::new();
+    outline
+        .move_to((150.0, 150.0))
+        .cubic_to((150.0, 130.0), (190.0, 130.0), (190.0, 150.0))
+        .quad_to((170.0, 190.0), (150.0, 150.0));
+    let rect = fb.stroke_path(&outline, 3.0, color::BLACK);
+    assert!(rect.top <= 135 && rect.left <= 149 && rect.left + rect.width >= 191);
+    assert!(is_black(&fb, 150, 150));
+    assert_eq!(gray_at(&fb, 150, 170), 0xFF);
+}
+
+#[test]
+fn test_polygon_fill_rules() {
+    let mut fb = gray_fb(200, 200);
+
+    // A pentagram, whose center is wound around twice
+    let star: Vec<(f32, f32)> = (0..5)
+        .map(|i| {
+            let angle = -std::f32::consts::PI / 2.0 + i as f32 * 4.0 * std::f32::consts::PI / 5.0;
+            (100.0 + 50.0 * angle.cos(), 100.0 + 50.0 * angle.sin())
+        }).collect();
+    let rect = fb.fill_polygon(&star, FillRule::EvenOdd, color::BLACK);
+    assert!(rect.top >= 50 && rect.top <= 51 && rect.top + rect.height <= 141);
+    assert_eq!(gray_at(&fb, 100, 100), 0xFF);
+    assert!(is_black(&fb, 70, 100));
+
+    fb.fill_polygon(&star, FillRule::NonZero, color::BLACK);
+    assert!(is_black(&fb, 100, 100));
+}
+
+#[test]
+fn test_fill_ellipse_leaves_no_gaps() {
+    let mut fb = gray_fb(200, 200);
+
+    let rect = fb.fill_ellipse(100, 100, 20, 40, color::BLACK);
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 80,
+            left: 60,
+            height: 41,
+            width: 81,
+        }
+    );
+    for y in 80..121 {
+        for x in 60..141 {
+            let (dy, dx) = ((y as f32 - 100.0) / 20.0, (x as f32 - 100.0) / 40.0);
+            let distance = dy * dy + dx * dx;
+            let expected = if distance <= 0.9 {
+                0x00
+            } else if distance > 1.1 {
+                0xFF
+            } else {
+                continue;
+            };
+            assert_eq!(gray_at(&fb, y, x), expected);
+        }
+    }
+}
+
+#[test]
+fn test_fill_circle_past_the_edges() {
+    let mut fb = gray_fb(200, 200);
+
+    // Shapes extending past the edges are clipped
+    let rect = fb.fill_circle(5, 5, 10, color::BLACK);
+    assert_eq!(rect.top, 0);
+    assert_eq!(rect.left, 0);
+    assert_eq!(rect.height, 16);
+}
+
+#[test]
+fn test_scanline_fill_far_off_screen() {
+    let mut fb = gray_fb(100, 100);
+
+    // Only the rows on the display are scanned
+    let tall = [(10.0, -1.0e9), (20.0, -1.0e9), (20.0, 1.0e9), (10.0, 1.0e9)];
+    let rect = fb.fill_polygon(&tall, FillRule::NonZero, color::BLACK);
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 0,
+            left: 10,
+            height: 100,
+            width: 10,
+        }
+    );
+
+    let far = [(0.0, 1.0e9), (100.0, 1.0e9), (100.0, 2.0e9)];
+    let rect = fb.fill_polygon(&far, FillRule::NonZero, color::BLACK);
+    assert_eq!(rect.width, 0);
+}
+
+#[test]
+fn test_ellipse_outline() {
+    let mut fb = gray_fb(200, 200);
+
+    // An outline and a fill with the inner radii make up the whole ellipse
+    let rect = fb.draw_ellipse(100, 100, 30, 50, 4, color::BLACK);
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 70,
+            left: 50,
+            height: 61,
+            width: 101,
+        }
+    );
+    assert!(is_black(&fb, 100, 50) && is_black(&fb, 100, 53) && !is_black(&fb, 100, 54));
+    assert!(is_black(&fb, 70, 100) && !is_black(&fb, 100, 100));
+    fb.fill_ellipse(100, 100, 26, 46, color::BLACK);
+    let whole = fb.dump_region(rect).unwrap();
+    fb.clear();
+    fb.fill_ellipse(100, 100, 30, 50, color::BLACK);
+    assert_eq!(fb.dump_region(rect).unwrap(), whole);
+}
+
+#[test]
+fn test_arc() {
+    let mut fb = gray_fb(200, 200);
+
+    // Angles go clockwise from 3 o'clock, so this is the bottom right quarter
+    let rect = fb.draw_arc(100, 100, 40, 0.0, 90.0, 3, color::BLACK);
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 100,
+            left: 100,
+            height: 41,
+            width: 41,
+        }
+    );
+    assert!(is_black(&fb, 100, 140) && is_black(&fb, 140, 100) && !is_black(&fb, 120, 120));
+}
+
+#[test]
+fn test_pie_wraps_around_zero() {
+    let mut fb = gray_fb(200, 200);
+
+    let rect = fb.fill_pie(100, 100, 40, 315.0, 45.0, color::BLACK);
+    assert_eq!(rect.left, 100);
+    assert_eq!(rect.width, 41);
+    assert!(is_black(&fb, 100, 130) && !is_black(&fb, 100, 70) && !is_black(&fb, 70, 100));
+}
+
+#[test]
+fn test_pie_includes_its_apex() {
+    let mut fb = gray_fb(200, 200);
+
+    // The bottom left quarter, which doesn't contain 0 degrees
+    fb.fill_pie(100, 100, 40, 90.0, 180.0, color::BLACK);
+    assert!(is_black(&fb, 100, 100));
+    assert!(is_black(&fb, 120, 80));
+    assert_eq!(gray_at(&fb, 80, 120), 0xFF);
+}
+
+#[test]
+fn test_fill_rounded_rect() {
+    let mut fb = gray_fb(200, 200);
+
+    // Rounded corners are cut off, the straight edges aren't
+    let rect = fb.fill_rounded_rect(20, 20, 50, 100, 10, color::BLACK);
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 20,
+            left: 20,
+            height: 50,
+            width: 100,
+        }
+    );
+    assert!(!is_black(&fb, 20, 20) && is_black(&fb, 20, 30) && is_black(&fb, 45, 20));
+    assert!(!is_black(&fb, 69, 119) && is_black(&fb, 69, 109));
+}
+
+#[test]
+fn test_draw_rounded_rect() {
+    let mut fb = gray_fb(200, 200);
+
+    let rect = fb.draw_rounded_rect(20, 20, 50, 100, 10, 2, color::BLACK);
+    assert_eq!(rect.height, 50);
+    assert_eq!(rect.width, 100);
+    assert!(is_black(&fb, 20, 70) && is_black(&fb, 21, 70) && !is_black(&fb, 22, 70));
+    assert!(is_black(&fb, 45, 119) && !is_black(&fb, 45, 117) && !is_black(&fb, 20, 20));
+}
+
+#[test]
+fn test_clip_rect_stack() {
+    let mut fb = gray_fb(100, 100);
+    assert_eq!(fb.clip_rect(), None);
+
+    fb.push_clip_rect(mxcfb_rect {
+        top: 10,
+        left: 10,
+        height: 50,
+        width: 50,
+    });
+    // Nested clip rectangles can only shrink the drawable area
+    fb.push_clip_rect(mxcfb_rect {
+        top: 40,
+        left: 0,
+        height: 60,
+        width: 30,
+    });
+    assert_eq!(
+        fb.clip_rect(),
+        Some(mxcfb_rect {
+            top: 40,
+            left: 10,
+            height: 20,
+            width: 20,
+        })
+    );
+
+    let rect = fb.fill_circle(50, 20, 30, color::BLACK);
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 40,
+            left: 10,
+            height: 20,
+            width: 20,
+        }
+    );
+    fb.fill_rect(0, 0, 100, 5, color::BLACK);
+    fb.draw_line_antialiased(45.0, 0.0, 45.0, 99.0, 3.0, color::BLACK);
+    assert!(is_black(&fb, 40, 10) && is_black(&fb, 59, 29));
+    assert!(!is_black(&fb, 39, 20) && !is_black(&fb, 60, 20));
+    assert!(!is_black(&fb, 50, 9) && !is_black(&fb, 50, 30));
+    assert!(!is_black(&fb, 50, 2) && !is_black(&fb, 45, 50));
+
+    fb.pop_clip_rect();
+    fb.fill_rect(20, 40, 2, 2, color::BLACK);
+    assert!(is_black(&fb, 20, 40));
+    fb.fill_rect(5, 5, 2, 2, color::BLACK);
+    assert!(!is_black(&fb, 5, 5));
+
+    assert!(fb.pop_clip_rect().is_some());
+    assert_eq!(fb.clip_rect(), None);
+    fb.fill_rect(5, 5, 2, 2, color::BLACK);
+    assert!(is_black(&fb, 5, 5));
+}
+
+#[test]
+fn test_huge_clip_rect() {
+    let mut fb = gray_fb(100, 100);
+
+    // Clip rectangles reaching past the display are limited to it rather than overflowing
+    fb.push_clip_rect(mxcfb_rect {
+        top: 10,
+        left: 20,
+        height: std::u32::MAX,
+        width: std::u32::MAX,
+    });
+    assert_eq!(
+        fb.clip_rect(),
+        Some(mxcfb_rect {
+            top: 10,
+            left: 20,
+            height: 90,
+            width: 80,
+        })
+    );
+    fb.fill_rect(0, 0, 100, 100, color::BLACK);
+    assert!(is_black(&fb, 10, 20) && is_black(&fb, 99, 99));
+    assert!(!is_black(&fb, 9, 50) && !is_black(&fb, 50, 19));
+}
+
+#[test]
+fn test_clear_honors_clip_rect() {
+    let mut fb = gray_fb(100, 100);
+    fb.fill_rect(0, 0, 100, 100, color::BLACK);
+
+    fb.push_clip_rect(mxcfb_rect {
+        top: 10,
+        left: 20,
+        height: 30,
+        width: 40,
+    });
+    fb.set_raster_op(RasterOp::Xor);
+    fb.clear();
+    fb.set_raster_op(RasterOp::Copy);
+    fb.pop_clip_rect();
+    assert_eq!(gray_at(&fb, 10, 20), 0xFF);
+    assert_eq!(gray_at(&fb, 39, 59), 0xFF);
+    assert!(is_black(&fb, 9, 20) && is_black(&fb, 40, 59) && is_black(&fb, 10, 60));
+
+    fb.clear();
+    assert_eq!(gray_at(&fb, 0, 0), 0xFF);
+}
+
+#[test]
+fn test_restore_region_honors_clip_rect() {
+    let region = mxcfb_rect {
+        top: 10,
+        left: 10,
+        height: 10,
+        width: 10,
+    };
+    for &orientation in &[Orientation::Rotate0, Orientation::Rotate90] {
+        let mut fb = gray_fb(100, 100);
+        fb.set_orientation(orientation);
+        fb.fill_rect(10, 10, 10, 10, color::BLACK);
+        let dump = fb.dump_region(region).unwrap();
+        fb.clear();
+
+        fb.push_clip_rect(mxcfb_rect {
+            top: 15,
+            left: 0,
+            height: 100,
+            width: 15,
+        });
+        fb.restore_region(region, &dump).unwrap();
+        fb.pop_clip_rect();
+        assert!(is_black(&fb, 15, 10), "{0:?}", orientation);
+        assert!(is_black(&fb, 19, 14), "{0:?}", orientation);
+        assert_eq!(gray_at(&fb, 14, 10), 0xFF, "{0:?}", orientation);
+        assert_eq!(gray_at(&fb, 15, 15), 0xFF, "{0:?}", orientation);
+    }
+}
+
+/// A 256x16 horizontal gradient from black to white
+fn gray_gradient() -> libremarkable::image::RgbImage {
+    use libremarkable::image::{Rgb, RgbImage};
+
+    RgbImage::from_fn(256, 16, |x, _| Rgb([x as u8; 3]))
+}
+
+/// The distinct gray levels in the 16 rows starting at `top`
+fn gray_levels(fb: &Framebuffer, top: usize) -> Vec<u8> {
+    let mut seen = std::collections::BTreeSet::new();
+    for y in top..top + 16 {
+        for x in 0..256 {
+            seen.insert(gray_at(fb, y, x));
+        }
+    }
+    seen.into_iter().collect()
+}
+
+/// The mean gray level of the 16x16 square at `(top, left)`
+fn mean_gray(fb: &Framebuffer, top: usize, left: usize) -> u32 {
+    let mut sum = 0u32;
+    for y in top..top + 16 {
+        for x in left..left + 16 {
+            sum += u32::from(gray_at(fb, y, x));
+        }
+    }
+    sum / 256
+}
+
+#[test]
+fn test_dithered_images() {
+    use libremarkable::framebuffer::dither::{Dither, GrayLevels};
+
+    let mut fb = gray_fb(64, 256);
+    let gradient = gray_gradient();
+
+    fb.draw_image(&gradient, 0, 0, Dither::FloydSteinberg(GrayLevels::Two));
+    assert_eq!(gray_levels(&fb, 0), vec![0x00, 0xFF]);
+    fb.draw_image(&gradient, 16, 0, Dither::Atkinson(GrayLevels::Four));
+    assert_eq!(gray_levels(&fb, 16), vec![0x00, 0x55, 0xAA, 0xFF]);
+    fb.draw_image(&gradient, 32, 0, Dither::Bayer(GrayLevels::Sixteen));
+    assert_eq!(gray_levels(&fb, 32).len(), 16);
+
+    // The average tone of each area is preserved
+    for &top in &[0, 16, 32] {
+        let m = mean_gray(&fb, top, 120);
+        assert!(m > 110 && m < 145, "mean {0} at {1}", m, top);
+    }
+}
+
+#[test]
+fn test_dithered_fill() {
+    use libremarkable::framebuffer::dither::{Dither, GrayLevels};
+
+    let mut fb = gray_fb(64, 256);
+
+    // A mid gray fill reduced to black and white is an even checkerboard-like mix
+    let rect = fb.fill_rect_dithered(
+        48,
+        0,
+        16,
+        256,
+        color::from_gray8(0x80),
+        Dither::Bayer(GrayLevels::Two),
+    );
+    assert_eq!(rect.height, 16);
+    assert_eq!(gray_levels(&fb, 48), vec![0x00, 0xFF]);
+    let m = mean_gray(&fb, 48, 0);
+    assert!(m > 120 && m < 136);
+}
+
+#[test]
+fn test_undithered_image() {
+    use libremarkable::framebuffer::dither::Dither;
+
+    let mut fb = gray_fb(64, 256);
+
+    // Without dithering the pixels are written as they are
+    fb.draw_image(&gray_gradient(), 0, 0, Dither::None);
+    assert_eq!(gray_at(&fb, 0, 100), 100);
+}
+
+#[test]
+fn test_image_scaled_to_fit() {
+    use libremarkable::framebuffer::dither::Dither;
+    use libremarkable::framebuffer::imaging::{ImageFit, ScaleFilter};
+    use libremarkable::image::{DynamicImage, GrayImage, Luma};
+
+    let mut fb = gray_fb(200, 200);
+    let dest = mxcfb_rect {
+        top: 10,
+        left: 10,
+        height: 100,
+        width: 50,
+    };
+
+    // A 20x10 black image: fitting keeps its 2:1 aspect ratio and centers it vertically
+    let black = DynamicImage::ImageLuma8(GrayImage::from_pixel(20, 10, Luma([0])));
+    let rect = fb.draw_image_scaled(
+        &black,
+        dest,
+        ScaleFilter::Bilinear,
+        ImageFit::Fit,
+        Dither::None,
+    );
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 47,
+            left: 10,
+            height: 25,
+            width: 50,
+        }
+    );
+    assert!(is_black(&fb, 47, 10));
+    assert_eq!(gray_at(&fb, 46, 10), 0xFF);
+}
+
+#[test]
+fn test_image_scaled_to_fill() {
+    use libremarkable::framebuffer::dither::Dither;
+    use libremarkable::framebuffer::imaging::{ImageFit, ScaleFilter};
+    use libremarkable::image::{DynamicImage, GrayImage, Luma};
+
+    let mut fb = gray_fb(200, 200);
+    let dest = mxcfb_rect {
+        top: 10,
+        left: 10,
+        height: 100,
+        width: 50,
+    };
+    let black = DynamicImage::ImageLuma8(GrayImage::from_pixel(20, 10, Luma([0])));
+
+    // Filling covers the whole destination, stretching too
+    for &fit in &[ImageFit::Fill, ImageFit::Stretch] {
+        fb.clear();
+        let rect = fb.draw_image_scaled(&black, dest, ScaleFilter::Lanczos, fit, Dither::None);
+        assert_eq!(rect, dest);
+        // Lanczos slightly rings on the edges
+        assert!(gray_at(&fb, 10, 10) < 0x08);
+        assert!(gray_at(&fb, 109, 59) < 0x08);
+        assert_eq!(gray_at(&fb, 110, 60), 0xFF);
+    }
+}
+
+#[test]
+fn test_blended_image() {
+    use libremarkable::framebuffer::dither::Dither;
+    use libremarkable::framebuffer::imaging::{ImageFit, ScaleFilter};
+    use libremarkable::image::{DynamicImage, Rgba, RgbaImage};
+
+    let mut fb = gray_fb(200, 200);
+
+    // Transparent pixels leave the content beneath, translucent ones blend with it
+    fb.fill_rect(150, 0, 10, 10, color::BLACK);
+    let mut overlay = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 0]));
+    overlay.put_pixel(5, 5, Rgba([255, 255, 255, 128]));
+    let dest = mxcfb_rect {
+        top: 150,
+        left: 0,
+        height: 10,
+        width: 10,
+    };
+    fb.draw_image_scaled(
+        &DynamicImage::ImageRgba8(overlay),
+        dest,
+        ScaleFilter::Nearest,
+        ImageFit::Stretch,
+        Dither::None,
+    );
+    assert!(is_black(&fb, 150, 0));
+    let blended = gray_at(&fb, 155, 5);
+    assert!(blended > 120 && blended < 136);
+}
+
+#[test]
+fn test_translucent_images_across_the_edge() {
+    use libremarkable::framebuffer::dither::{Dither, GrayLevels};
+    use libremarkable::framebuffer::imaging::{ImageFit, ScaleFilter};
+    use libremarkable::image::{DynamicImage, Rgba, RgbaImage};
+
+    let mut fb = gray_fb(100, 100);
+    let translucent = RgbaImage::from_pixel(40, 10, Rgba([255, 255, 255, 128]));
+    let overlay = DynamicImage::ImageRgba8(translucent);
+    // Half of the destination lies past the right edge of the display
+    let dest = mxcfb_rect {
+        top: 0,
+        left: 80,
+        height: 10,
+        width: 40,
+    };
+
+    fb.fill_rect(0, 80, 10, 20, color::BLACK);
+    fb.draw_image_scaled(&overlay, dest, ScaleFilter::Nearest, ImageFit::Stretch, Dither::None);
+    let blended = gray_at(&fb, 5, 90);
+    assert!(blended > 120 && blended < 136);
+
+    fb.fill_rect(0, 80, 10, 20, color::BLACK);
+    let dithered = Dither::Bayer(GrayLevels::Two);
+    fb.draw_image_scaled(&overlay, dest, ScaleFilter::Nearest, ImageFit::Stretch, dithered);
+    let white = (80..100).filter(|&x| gray_at(&fb, 4, x) == 0xFF).count();
+    assert!(white > 5 && white < 15, "{0}", white);
+}
+
+#[test]
+fn test_row_fill_and_blit() {
+    let orientations = [
+        Orientation::Rotate0,
+        Orientation::Rotate90,
+        Orientation::Rotate180,
+        Orientation::Rotate270,
+    ];
+    for &format in &[PixelFormat::Rgb565, PixelFormat::Gray8] {
+        for &orientation in &orientations {
+            let draw = |by_rows: bool| {
+                let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(40, 30)));
+                fb.set_pixel_format(format).unwrap();
+                fb.set_orientation(orientation);
+                fb.push_clip_rect(mxcfb_rect {
+                    top: 2,
+                    left: 3,
+                    height: 30,
+                    width: 20,
+                });
+                let colors: Vec<color> = (0..50).map(|i| color::GRAY(i as u8 * 5)).collect();
+                let mut row = Vec::new();
+                for &c in &colors {
+                    format.encode(c, &mut row);
+                }
+                for y in 0..40 {
+                    if by_rows {
+                        fb.fill_row(y, 1, 8, color::RGB(10, 200, 30));
+                        fb.write_row(y, 12, &row);
+                    } else {
+                        for x in 1..9 {
+                            fb.write_pixel(y, x, color::RGB(10, 200, 30));
+                        }
+                        for (i, &c) in colors.iter().enumerate() {
+                            fb.write_pixel(y, 12 + i, c);
+                        }
+                    }
+                }
+                let (height, width) = fb.dimensions();
+                fb.dump_region(mxcfb_rect {
+                    top: 0,
+                    left: 0,
+                    height,
+                    width,
+                }).unwrap()
+            };
+            assert_eq!(draw(true), draw(false), "{0:?} {1:?}", format, orientation);
+        }
+    }
+}
+
+#[test]
+fn test_write_frame() {
+    // Whole frames are copied as they are
+    let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(10, 10)));
+    fb.write_frame(&[0u8; 200]);
+    assert_eq!(fb.read_pixel(9, 9), color::from_native(color::BLACK.as_native()));
+}
+
+#[test]
+fn test_svg_rendering() {
+    use libremarkable::framebuffer::svg::SvgImage;
+
+    let svg = SvgImage::parse(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10" viewBox="0 0 20 10">
+            <defs><rect width="20" height="10"/></defs>
+            <rect x="0" y="0" width="10" height="10" fill="#000"/>
+            <g transform="translate(10 0)" style="fill: none">
+                <path d="M2 2h6v6h-6z" stroke="black" stroke-width="2"/>
+            </g>
+        </svg>"##,
+    ).unwrap();
+    assert_eq!(svg.size(), (20.0, 10.0));
+
+    let mut fb = gray_fb(200, 200);
+    // Scaled by 4 and centered vertically in a 80x80 region
+    let dest = mxcfb_rect {
+        top: 10,
+        left: 10,
+        height: 80,
+        width: 80,
+    };
+    let rect = fb.draw_svg(&svg, dest);
+    assert!(dest.contains_rect(&rect));
+
+    // The filled square covers the left half
+    assert!(is_black(&fb, 31, 11));
+    assert!(is_black(&fb, 68, 48));
+    assert_eq!(gray_at(&fb, 29, 11), 0xFF);
+    assert_eq!(gray_at(&fb, 71, 48), 0xFF);
+    // The right half is an outlined square, 8px wide once scaled
+    assert!(is_black(&fb, 50, 60));
+    assert_eq!(gray_at(&fb, 50, 70), 0xFF);
+    assert!(is_black(&fb, 38, 70));
+}
+
+#[test]
+fn test_svg_clipped_to_region() {
+    use libremarkable::framebuffer::svg::SvgImage;
+
+    let mut fb = gray_fb(200, 200);
+
+    // Nothing is drawn outside of the region
+    let small = mxcfb_rect {
+        top: 0,
+        left: 0,
+        height: 10,
+        width: 10,
+    };
+    let big = SvgImage::parse(
+        r#"<svg viewBox="0 0 10 10"><circle cx="5" cy="5" r="20" fill="black"/></svg>"#,
+    ).unwrap();
+    assert_eq!(fb.draw_svg(&big, small), small);
+    assert_eq!(gray_at(&fb, 10, 5), 0xFF);
+    assert!(is_black(&fb, 9, 9));
+}
+
+#[test]
+fn test_svg_parse_errors() {
+    use libremarkable::framebuffer::svg::SvgImage;
+
+    assert!(SvgImage::parse("<svg viewBox=\"0 0 10 10\"><path d=\"M0 0 L\"/></svg>").is_err());
+    assert!(SvgImage::parse("<notsvg/>").is_err());
+}
+
+#[test]
+fn test_svg_ignores_unsupported_values() {
+    use libremarkable::framebuffer::svg::SvgImage;
+
+    // Unparsable colors and lengths leave the attribute unset, the rest still renders
+    let svg = SvgImage::parse(
+        r##"<svg width="10" height="1em" viewBox="0 0 10 10">
+            <rect x="2em" width="5" height="10" fill="#é12"/>
+            <rect x="5" width="5" height="10" fill="orange" stroke="navy" stroke-width="12pt"/>
+        </svg>"##,
+    ).unwrap();
+    assert_eq!(svg.size(), (10.0, 10.0));
+
+    let mut fb = gray_fb(20, 20);
+    let dest = mxcfb_rect {
+        top: 0,
+        left: 0,
+        height: 10,
+        width: 10,
+    };
+    fb.draw_svg(&svg, dest);
+    assert!(is_black(&fb, 5, 2));
+    assert!(is_black(&fb, 5, 7));
+    assert_eq!(gray_at(&fb, 5, 10), 0xFF);
+}
+
+#[test]
+fn test_ink_stroke_pressure() {
+    use libremarkable::framebuffer::ink::{StrokePoint, MAX_PRESSURE};
+
+    assert_eq!(StrokePoint::from_pressure(5, 6, 0, 1.0, 9.0), StrokePoint::new(5.0, 6.0, 1.0));
+    assert_eq!(StrokePoint::from_pressure(5, 6, MAX_PRESSURE, 1.0, 9.0).width, 9.0);
+}
+
+#[test]
+fn test_tapered_ink_stroke() {
+    use libremarkable::framebuffer::ink::StrokePoint;
+
+    let mut fb = gray_fb(150, 150);
+
+    // A horizontal stroke tapering from 2px to 20px
+    let rect = fb.draw_ink_stroke(
+        &[
+            StrokePoint::new(50.0, 20.0, 2.0),
+            StrokePoint::new(50.0, 100.0, 20.0),
+        ],
+        color::BLACK,
+    );
+    assert!(rect.contains_point(50, 20) && rect.contains_point(59, 100));
+    let thickness = |fb: &Framebuffer, x: usize| (30..70).filter(|&y| is_dark(fb, y, x)).count();
+    assert!(thickness(&fb, 22) <= 3);
+    assert!((9..=12).contains(&thickness(&fb, 60)));
+    assert!((19..=21).contains(&thickness(&fb, 100)));
+    // The wide end is rounded off
+    assert!(is_black(&fb, 50, 109));
+    assert_eq!(gray_at(&fb, 41, 109), 0xFF);
+}
+
+#[test]
+fn test_ink_stroke_sharp_turn() {
+    use libremarkable::framebuffer::ink::StrokePoint;
+
+    let mut fb = gray_fb(150, 150);
+
+    // A sharp turn leaves no gap at its tip
+    fb.draw_ink_stroke(
+        &[
+            StrokePoint::new(120.0, 20.0, 10.0),
+            StrokePoint::new(100.0, 60.0, 10.0),
+            StrokePoint::new(120.0, 100.0, 10.0),
+        ],
+        color::BLACK,
+    );
+    for y in 97..104 {
+        assert!(is_black(&fb, y, 60));
+    }
+    assert_eq!(gray_at(&fb, 92, 60), 0xFF);
+}
+
+#[test]
+fn test_stroke_caps() {
+    use libremarkable::framebuffer::stroke::{LineCap, StrokeStyle};
+
+    let mut fb = gray_fb(200, 200);
+
+    // Caps of a horizontal 10px stroke from x=20 to x=60
+    for &(cap, start_covered, corner_covered) in &[
+        (LineCap::Butt, false, false),
+        (LineCap::Square, true, true),
+        (LineCap::Round, true, false),
+    ] {
+        fb.clear();
+        let style = StrokeStyle {
+            cap,
+            ..StrokeStyle::new(10.0)
+        };
+        fb.draw_polyline(&[(20.0, 50.0), (60.0, 50.0)], false, &style, color::BLACK);
+        assert!(is_dark(&fb, 50, 40));
+        assert_eq!(is_dark(&fb, 50, 16), start_covered);
+        assert_eq!(is_dark(&fb, 46, 16), corner_covered);
+    }
+}
+
+#[test]
+fn test_stroke_joins() {
+    use libremarkable::framebuffer::stroke::{LineJoin, StrokeStyle};
+
+    let mut fb = gray_fb(200, 200);
+
+    // Joins of a right angle
+    for &(join, tip_covered) in &[(LineJoin::Miter, true), (LineJoin::Bevel, false)] {
+        fb.clear();
+        let style = StrokeStyle {
+            join,
+            ..StrokeStyle::new(10.0)
+        };
+        let points = [(20.0, 100.0), (60.0, 100.0), (60.0, 140.0)];
+        fb.draw_polyline(&points, false, &style, color::BLACK);
+        assert!(is_dark(&fb, 98, 62));
+        assert_eq!(is_dark(&fb, 96, 64), tip_covered);
+    }
+    // Sharp corners go over the miter limit and are beveled
+    fb.clear();
+    let points = [(20.0, 100.0), (100.0, 100.0), (20.0, 110.0)];
+    fb.draw_polyline(&points, false, &StrokeStyle::new(10.0), color::BLACK);
+    assert!(!is_dark(&fb, 100, 120));
+}
+
+#[test]
+fn test_dashed_rect() {
+    use libremarkable::framebuffer::stroke::StrokeStyle;
+
+    let mut fb = gray_fb(200, 200);
+
+    let style = StrokeStyle {
+        dash_array: vec![6.0, 4.0],
+        ..StrokeStyle::new(1.0)
+    };
+    let rect = fb.draw_rect_styled(10, 10, 50, 100, &style, color::BLACK);
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 10,
+            left: 10,
+            height: 51,
+            width: 101,
+        }
+    );
+    let top_edge: Vec<bool> = (10..30).map(|x| is_dark(&fb, 10, x)).collect();
+    let period = [true, true, true, true, true, true, false, false, false, false];
+    assert_eq!(&top_edge[..10], &period);
+    assert_eq!(&top_edge[10..], &top_edge[..10]);
+    assert!(!is_dark(&fb, 11, 11));
+}
+
+#[test]
+fn test_solid_rect_borders() {
+    let mut fb = gray_fb(200, 200);
+
+    // Solid borders cover the corners fully, with even widths extending up and left
+    fb.draw_rect(10, 10, 50, 100, 2, color::BLACK);
+    assert!(is_dark(&fb, 9, 9) && is_dark(&fb, 10, 10) && is_dark(&fb, 60, 110));
+    assert!(!is_dark(&fb, 8, 9) && !is_dark(&fb, 11, 11) && !is_dark(&fb, 61, 110));
+}
 
+#[test]
+fn test_thick_line_caps() {
+    let mut fb = gray_fb(200, 200);
+
+    // Thick lines get square caps
+    fb.draw_line(150, 20, 150, 40, 3, color::BLACK);
+    assert!(is_dark(&fb, 149, 19) && is_dark(&fb, 151, 41));
+    assert!(!is_dark(&fb, 152, 30) && !is_dark(&fb, 150, 18));
+}
+
+#[test]
+fn test_halftone_fill() {
+    use libremarkable::framebuffer::fill::FillStyle;
+
+    let mut fb = gray_fb(100, 100);
+
+    // Halftones only use black and white, in proportion to the gray level
+    let rect = fb.fill_rect_styled(0, 0, 16, 16, &FillStyle::Halftone(0x40));
+    assert_eq!(
+        rect,
+        mxcfb_rect {
+            top: 0,
+            left: 0,
+            height: 16,
+            width: 16,
+        }
+    );
+    let values: Vec<u8> = (0..256).map(|i| gray_at(&fb, i / 16, i % 16)).collect();
+    assert!(values.iter().all(|&v| v == 0x00 || v == 0xFF));
+    let black = values.iter().filter(|&&v| v == 0x00).count();
+    assert!(black >= 180 && black <= 200);
+}
+
+#[test]
+fn test_hatch_fill() {
+    use libremarkable::framebuffer::fill::{FillStyle, HatchDirection};
+
+    let mut fb = gray_fb(100, 100);
+
+    // Hatching leaves the background alone when it has none
+    fb.fill_rect(20, 0, 10, 10, color::from_gray8(0x80));
+    let hatch = FillStyle::Hatch {
+        direction: HatchDirection::Horizontal,
+        spacing: 4,
+        thickness: 1,
+        foreground: color::BLACK,
+        background: None,
+    };
+    fb.fill_rect_styled(20, 0, 10, 10, &hatch);
+    assert_eq!(gray_at(&fb, 20, 5), 0x00);
+    assert_eq!(gray_at(&fb, 21, 5), 0x80);
+    assert_eq!(gray_at(&fb, 24, 5), 0x00);
+}
+
+/// A checkerboard of 2px black and white squares
+fn checkerboard() -> libremarkable::framebuffer::fill::FillStyle {
+    libremarkable::framebuffer::fill::FillStyle::Checkerboard {
+        size: 2,
+        foreground: color::BLACK,
+        background: Some(color::WHITE),
+    }
+}
+
+#[test]
+fn test_checkerboard_fill() {
+    let mut fb = gray_fb(100, 100);
+
+    // Checkerboards are aligned on the framebuffer
+    fb.fill_rect_styled(41, 41, 4, 4, &checkerboard());
+    assert_eq!(gray_at(&fb, 41, 41), 0x00);
+    assert_eq!(gray_at(&fb, 41, 42), 0xFF);
+    assert_eq!(gray_at(&fb, 42, 42), 0x00);
+    assert_eq!(gray_at(&fb, 40, 40), 0xFF);
+}
+
+#[test]
+fn test_styled_polygon_fill() {
+    use libremarkable::framebuffer::fill::FillStyle;
+
+    let mut fb = gray_fb(100, 100);
+
+    // Polygons are painted where the centers of the pixels are inside
+    let triangle = [(50.0, 50.0), (90.0, 50.0), (50.0, 90.0)];
+    fb.fill_polygon_styled(&triangle, FillRule::NonZero, &checkerboard());
+    assert_eq!(gray_at(&fb, 52, 52), 0x00);
+    assert_eq!(gray_at(&fb, 85, 85), 0xFF);
+    fb.fill_polygon_styled(&triangle, FillRule::NonZero, &FillStyle::Solid(color::BLACK));
+    assert_eq!(gray_at(&fb, 53, 52), 0x00);
+}
+
+#[test]
+fn test_gradient_fill() {
+    use libremarkable::framebuffer::dither::{Dither, GrayLevels};
+    use libremarkable::framebuffer::fill::FillStyle;
+
+    let mut fb = gray_fb(100, 100);
+
+    // Gradients go from one color to the other, dithered when asked
+    let gradient = |dither| FillStyle::LinearGradient {
+        start: (0.0, 0.0),
+        end: (99.0, 0.0),
+        from: color::BLACK,
+        to: color::WHITE,
+        dither,
+    };
+    fb.fill_rect_styled(0, 0, 10, 100, &gradient(Dither::None));
+    assert_eq!(gray_at(&fb, 5, 0), 0x00);
+    assert_eq!(gray_at(&fb, 5, 99), 0xFF);
+    assert!((120..136).contains(&gray_at(&fb, 5, 50)));
+    fb.fill_rect_styled(10, 0, 10, 100, &gradient(Dither::Bayer(GrayLevels::Two)));
+    let count = |x0: usize, x1: usize, v: u8| {
+        (10..20)
+            .flat_map(|y| (x0..x1).map(move |x| (y, x)))
+            .filter(|&(y, x)| gray_at(&fb, y, x) == v)
+            .count()
+    };
+    assert_eq!(count(0, 100, 0x00) + count(0, 100, 0xFF), 1000);
+    assert!(count(0, 25, 0x00) > 175);
+    assert!(count(75, 100, 0xFF) > 175);
+}
+
+/// A 60x60 grayscale framebuffer whose left half is dark gray
+fn half_gray_fb() -> Framebuffer<'static> {
+    let mut fb = gray_fb(60, 60);
+    fb.fill_rect(0, 0, 60, 30, color::from_gray8(0x40));
+    fb
+}
+
+const WHOLE_60X60: mxcfb_rect = mxcfb_rect {
+    top: 0,
+    left: 0,
+    height: 60,
+    width: 60,
+};
+
+#[test]
+fn test_xor_raster_op() {
+    let mut fb = half_gray_fb();
+    let before = fb.dump_region(WHOLE_60X60).unwrap();
+
+    // Drawing a rubber band twice with XOR leaves no trace
+    assert_eq!(fb.raster_op(), RasterOp::Copy);
+    fb.set_raster_op(RasterOp::Xor);
+    fb.draw_rect(10, 10, 30, 40, 1, color::WHITE);
+    assert_eq!(gray_at(&fb, 10, 20), 0xBF);
+    assert_eq!(gray_at(&fb, 10, 40), 0x00);
+    fb.draw_rect(10, 10, 30, 40, 1, color::WHITE);
+    let after = fb.dump_region(WHOLE_60X60).unwrap();
+    assert_eq!(before, after);
+}
+
+#[test]
+fn test_invert_raster_op() {
+    let mut fb = half_gray_fb();
+
+    // Inverting ignores the color written
+    fb.set_raster_op(RasterOp::Invert);
+    fb.fill_rect(0, 25, 1, 10, color::BLACK);
+    assert_eq!(gray_at(&fb, 0, 25), 0xBF);
+    assert_eq!(gray_at(&fb, 0, 30), 0x00);
+}
+
+#[test]
+fn test_blend_raster_ops() {
+    let mut fb = half_gray_fb();
+
+    // Darkening and lightening blend modes
+    fb.set_raster_op(RasterOp::Min);
+    fb.fill_rect(5, 25, 1, 10, color::from_gray8(0x80));
+    assert_eq!((gray_at(&fb, 5, 25), gray_at(&fb, 5, 30)), (0x40, 0x80));
+    fb.set_raster_op(RasterOp::Max);
+    fb.fill_rect(6, 25, 1, 10, color::from_gray8(0x80));
+    assert_eq!((gray_at(&fb, 6, 25), gray_at(&fb, 6, 30)), (0x80, 0xFF));
+    fb.set_raster_op(RasterOp::Multiply);
+    fb.fill_rect(7, 25, 1, 10, color::from_gray8(0x80));
+    assert_eq!((gray_at(&fb, 7, 25), gray_at(&fb, 7, 30)), (0x20, 0x80));
+    fb.set_raster_op(RasterOp::And);
+    fb.write_pixel(8, 25, color::from_gray8(0x0F));
+    assert_eq!(gray_at(&fb, 8, 25), 0x00);
+    fb.set_raster_op(RasterOp::Or);
+    fb.write_pixel(8, 26, color::from_gray8(0x0F));
+    assert_eq!(gray_at(&fb, 8, 26), 0x4F);
+}
+
+#[test]
+fn test_restore_region_ignores_raster_op() {
+    let mut fb = half_gray_fb();
+    let before = fb.dump_region(WHOLE_60X60).unwrap();
+    fb.set_raster_op(RasterOp::Invert);
+    fb.fill_rect(0, 25, 1, 10, color::BLACK);
+    assert_eq!(gray_at(&fb, 0, 30), 0x00);
+
+    // Restoring a region always copies
+    fb.set_raster_op(RasterOp::Xor);
+    fb.restore_region(WHOLE_60X60, &before).unwrap();
+    assert_eq!(gray_at(&fb, 0, 30), 0xFF);
+}
+
+#[test]
+fn test_raster_ops_on_rotated_rows() {
+    // Rotated rows and RGB565 pixels go through the same operations
+    let mut fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(20, 30)));
+    fb.set_orientation(Orientation::Rotate90);
+    fb.set_raster_op(RasterOp::Xor);
+    fb.fill_row(3, 2, 5, color::WHITE);
+    assert_eq!(fb.read_pixel(3, 4), color::from_native(color::BLACK.as_native()));
+    fb.fill_row(3, 2, 5, color::WHITE);
+    assert_eq!(fb.read_pixel(3, 4), color::from_native(color::WHITE.as_native()));
+}
+
+#[test]
+fn test_eink_palette() {
+    use libremarkable::framebuffer::gray;
+
+    assert_eq!(color::eink(0).to_gray8(), 0x00);
+    assert_eq!(color::eink(8).to_gray8(), 0x88);
+    assert_eq!(color::eink(15).to_gray8(), 0xFF);
+    assert_eq!(gray::nearest_level(0x80), 8);
+    assert_eq!(gray::quantize(0x80), 0x88);
+}
+
+#[test]
+fn test_perceptual_luminance() {
+    use libremarkable::framebuffer::gray;
+
+    // Green looks far lighter than red, which looks lighter than blue
+    let (red, green, blue) = (
+        gray::luminance(255, 0, 0),
+        gray::luminance(0, 255, 0),
+        gray::luminance(0, 0, 255),
+    );
+    assert!(green > red && red > blue, "{0} {1} {2}", red, green, blue);
+    assert!(red > 120 && red < 135, "{0}", red);
+    assert_eq!(gray::luminance(200, 200, 200), 200);
+    assert_eq!(color::RGB(255, 0, 0).to_gray8(), red);
+}
+
+#[test]
+fn test_tone_curves() {
+    use libremarkable::framebuffer::gray::ToneCurve;
+
+    assert_eq!(ToneCurve::new(1.0, 1.0), ToneCurve::identity());
+    assert!(ToneCurve::new(2.2, 1.0).apply(0x80) > 0x80);
+    let contrasted = ToneCurve::new(1.0, 2.0);
+    assert!(contrasted.apply(0x40) < 0x40);
+    assert!(contrasted.apply(0xC0) > 0xC0);
+    assert_eq!(contrasted.apply(0x00), 0x00);
+}
+
+#[test]
+fn test_images_go_through_the_tone_curve() {
+    use libremarkable::framebuffer::dither::Dither;
+    use libremarkable::framebuffer::gray::ToneCurve;
+    use libremarkable::image::{Rgb, RgbImage};
+
+    let mut fb = gray_fb(100, 100);
+
+    fb.set_tone_curve(ToneCurve::from_fn(|v| 255 - v));
+    let img = RgbImage::from_fn(2, 1, |x, _| Rgb([if x == 0 { 255 } else { 0 }; 3]));
+    fb.draw_image(&img, 0, 0, Dither::None);
+    assert_eq!(gray_at(&fb, 0, 0), 0x00);
+    assert_eq!(gray_at(&fb, 0, 1), 0xFF);
+}
+
+#[test]
+fn test_text_blends_onto_the_background() {
+    use libremarkable::framebuffer::fonts::FontHandle;
+
+    let mut fb = gray_fb(100, 100);
+
+    // Without a background, text antialiases against what is underneath
+    fb.set_text_background(None);
+    fb.fill_rect(20, 0, 60, 100, color::from_gray8(0x80));
+    let area = fb.draw_text(
+        60,
+        10,
+        "Wo".to_owned(),
+        FontHandle::default(),
+        30,
+        color::BLACK,
+        false,
+    );
+    let mut darkest = 0xFF;
+    for y in area.top..area.top + area.height {
+        for x in area.left..area.left + area.width {
+            let v = gray_at(&fb, y as usize, x as usize);
+            assert!(v <= 0x80, "{0} at {1}, {2}", v, y, x);
+            darkest = darkest.min(v);
+        }
+    }
+    assert_eq!(darkest, 0x00);
+}
+
+#[test]
+fn test_text_replaces_what_is_underneath() {
+    use libremarkable::framebuffer::fonts::FontHandle;
+
+    let mut fb = gray_fb(100, 100);
+    assert_eq!(fb.text_background(), Some(color::WHITE));
+
+    // The boxes of the glyphs are filled with the background, so text can overwrite text
+    fb.fill_rect(20, 0, 60, 100, color::from_gray8(0x80));
+    let area = fb.draw_text(
+        60,
+        10,
+        "Wo".to_owned(),
+        FontHandle::default(),
+        30,
+        color::BLACK,
+        false,
+    );
+    let values: Vec<u8> = (area.top..area.top + area.height)
+        .flat_map(|y| (area.left..area.left + area.width).map(move |x| (y, x)))
+        .map(|(y, x)| gray_at(&fb, y as usize, x as usize))
+        .collect();
+    assert!(values.contains(&0x00) && values.contains(&0xFF));
+}
+
+#[test]
+fn test_posterized_colormap() {
+    use libremarkable::framebuffer::cmap::Colormap;
+
+    let fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(100, 50)));
+    assert_eq!(fb.colormap().unwrap(), Colormap::identity());
+
+    let posterized = Colormap::posterize(4);
+    assert_eq!(posterized.len(), 256);
+    assert_eq!(posterized.red[0x20], 0x0000);
+    assert_eq!(posterized.red[0x60], 0x5555);
+    assert_eq!(posterized.red[0xF0], 0xFFFF);
+    fb.set_colormap(&posterized).unwrap();
+    assert_eq!(fb.colormap().unwrap(), posterized);
+}
+
+#[test]
+fn test_partial_colormap() {
+    use libremarkable::framebuffer::cmap::Colormap;
+
+    let fb = Framebuffer::from_backend(Box::new(MemoryBackend::new(100, 50)));
+    fb.set_colormap(&Colormap::posterize(4)).unwrap();
+
+    // Partial colormaps only overwrite their own entries
+    let mut dark = Colormap::identity();
+    dark.start = 0xF0;
+    for component in &mut [&mut dark.red, &mut dark.green, &mut dark.blue] {
+        component.truncate(0x10);
+        for entry in component.iter_mut() {
+            *entry = 0;
+        }
+    }
+    fb.set_colormap(&dark).unwrap();
+    let installed = fb.colormap().unwrap();
+    assert_eq!(installed.red[0x60], 0x5555);
+    assert_eq!(installed.red[0xF0], 0x0000);
+    dark.start = 0xF8;
+    assert!(fb.set_colormap(&dark).is_err());
+}
+
+#[test]
+fn test_colormap_refresh() {
+    let backend = MemoryBackend::new(100, 50);
+    let log = backend.refresh_log();
+    let fb = Framebuffer::from_backend(Box::new(backend));
+
+    fb.partial_refresh_with_flags(
+        &mxcfb_rect {
+            top: 0,
+            left: 0,
+            height: 10,
+            width: 10,
+        },
+        PartialRefreshMode::Async,
+        waveform_mode::WAVEFORM_MODE_GC16,
+        display_temp::TEMP_USE_AMBIENT,
+        dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        false,
+        PxpFlags {
+            use_cmap: true,
+            ..PxpFlags::default()
+        },
+    ).unwrap();
+    assert_eq!(log.lock().unwrap()[0].flags, EPDC_FLAG_USE_CMAP);
+}